@@ -0,0 +1,1085 @@
+use std::collections::{HashMap, HashSet};
+use crate::emulator::decoder::{Mnemonic, OperandSize, Pattern};
+use crate::emulator::error::AssemblerError;
+use crate::emulator::opcodes::{Entry, OPCODE_TABLE};
+
+//Ensamblador de 8086 con sintaxis FASM (también admite la de emu8086 y el ptr de Intel).
+//Codifica las instrucciones buscando en la misma tabla de opcodes que usa el decodificador,
+//así cada instrucción que se añade a la tabla se puede ensamblar y desensamblar.
+//Admite etiquetas (las que empiezan por punto son locales a la última etiqueta global), ORG, DB, DW
+//(con cadenas y DUP), EQU, $ y expresiones con + - * / y paréntesis
+
+const REGISTERS_B: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+const REGISTERS_W: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+const SEGMENTS: [&str; 4] = ["es", "cs", "ss", "ds"];
+const MAX_PASSES: usize = 16;
+//Tamaño máximo de un .com, el segmento menos el PSP (el mismo límite que en load_com_bytes)
+const MAX_PROGRAM_SIZE: usize = 0x10000 - 0x0100;
+
+//Ensambla el código fuente y devuelve los bytes del programa, listos para guardarse como .com
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblerError> {
+    let statements = parse(source)?;
+    let mut assembler = Assembler { symbols: HashMap::new(), address: 0, final_pass: false, output: Vec::new() };
+    //Como FASM, cada pasada elige la forma más corta que admiten los valores de la pasada anterior (los
+    //símbolos que todavía no tienen valor se suponen pequeños). Un salto que deja de caber se alarga,
+    //lo que solo aleja los destinos, así que se repite hasta que los símbolos dejan de cambiar
+    let mut previous: Option<HashMap<String, (i64, bool)>> = None;
+    for _ in 0..MAX_PASSES {
+        assembler.pass(&statements)?;
+        let values: HashMap<String, (i64, bool)> = assembler.symbols.iter()
+            .map(|(name, symbol)| (name.clone(), (symbol.value, symbol.known)))
+            .collect();
+        if previous.as_ref() == Some(&values) {
+            //Lo que no tiene valor cuando ya no cambia nada es un EQU que depende de sí mismo
+            let circular = assembler.symbols.iter()
+                .filter(|(_, symbol)| !symbol.known)
+                .min_by_key(|(_, symbol)| symbol.line);
+            if let Some((name, symbol)) = circular {
+                return error(symbol.line, format!("la definición de {} depende de sí misma", name));
+            }
+            assembler.final_pass = true;
+            assembler.pass(&statements)?;
+            return Ok(assembler.output);
+        }
+        previous = Some(values);
+    }
+    let line = assembler.symbols.values().map(|symbol| symbol.line).min().unwrap_or(0);
+    Err(AssemblerError { line, message: "no se pueden resolver los símbolos".to_string() })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Str(Vec<u8>),
+    Symbol(char),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(i64),
+    Symbol(String),
+    //$, dirección de la instrucción actual
+    Current,
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+}
+
+//Distancia indicada en un salto con short, near o far
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Distance {
+    Short,
+    Near,
+    Far,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum AsmOperand {
+    Reg8(u8),
+    Reg16(u8),
+    Sreg(u8),
+    Imm(Expr),
+    //Dirección lejana inmediata segmento:offset
+    Far(Expr, Expr),
+    //Operando en memoria: registros base e indice (campo r/m del ModRM, None si es una dirección directa)
+    Mem { size: Option<OperandSize>, segment: Option<u8>, base: Option<u8>, displacement: Option<Expr> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DataItem {
+    Value(Expr),
+    Bytes(Vec<u8>),
+    //? en DB y DW, se rellena con 0
+    Uninitialized,
+    Dup(Expr, Vec<DataItem>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Statement {
+    Label(String),
+    Equ(String, Expr),
+    Org(Expr),
+    Data { word: bool, items: Vec<DataItem> },
+    Instruction { prefixes: Vec<u8>, mnemonic: String, operands: Vec<AsmOperand>, distance: Option<Distance> },
+}
+
+//Sentencia con la línea del código fuente de la que sale
+struct Line {
+    number: usize,
+    statement: Statement,
+}
+
+fn error<T>(line: usize, message: impl Into<String>) -> Result<T, AssemblerError> {
+    Err(AssemblerError { line, message: message.into() })
+}
+
+fn tokenize(text: &str, line: usize) -> Result<Vec<Token>, AssemblerError> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == ';' {
+            break;
+        }else if c.is_whitespace() {
+            i += 1;
+        }else if c == '\'' || c == '"' {
+            let end = match chars[i + 1..].iter().position(|&other| other == c) {
+                Some(position) => i + 1 + position,
+                None => return error(line, "cadena sin cerrar"),
+            };
+            tokens.push(Token::Str(chars[i + 1..end].iter().collect::<String>().into_bytes()));
+            i = end + 1;
+        }else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            match parse_number(&text) {
+                Some(value) => tokens.push(Token::Number(value)),
+                None => return error(line, format!("número no válido: {}", text)),
+            }
+        }else if c.is_alphabetic() || matches!(c, '_' | '.' | '@' | '?' | '$') {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '.' | '@' | '?')) {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        }else if "[](),:+-*/".contains(c) {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        }else{
+            return error(line, format!("carácter no válido: {}", c));
+        }
+    }
+    Ok(tokens)
+}
+
+//Números en decimal, hexadecimal (0x1F o 1Fh) y binario (101b)
+fn parse_number(text: &str) -> Option<i64> {
+    let text = text.to_lowercase().replace('_', "");
+    if let Some(digits) = text.strip_prefix("0x") {
+        i64::from_str_radix(digits, 16).ok()
+    }else if let Some(digits) = text.strip_suffix('h') {
+        i64::from_str_radix(digits, 16).ok()
+    }else if let Some(digits) = text.strip_suffix('b') {
+        i64::from_str_radix(digits, 2).ok()
+    }else{
+        text.strip_suffix('d').unwrap_or(&text).parse().ok()
+    }
+}
+
+fn is_ident(token: Option<&Token>, name: &str) -> bool {
+    matches!(token, Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case(name))
+}
+
+fn register_index(names: &[&str], name: &str) -> Option<u8> {
+    names.iter().position(|register| register.eq_ignore_ascii_case(name)).map(|index| index as u8)
+}
+
+fn parse(source: &str) -> Result<Vec<Line>, AssemblerError> {
+    let mut lines = Vec::new();
+    let mut global_label = String::new();
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        let mut tokens = tokenize(text, number)?;
+        //Las etiquetas locales (.nombre) pertenecen a la última etiqueta global
+        for token in tokens.iter_mut() {
+            if let Token::Ident(name) = token {
+                if name.starts_with('.') && name.len() > 1 {
+                    *name = format!("{}{}", global_label, name);
+                }
+            }
+        }
+        let mut rest = &tokens[..];
+        if let [Token::Ident(name), Token::Symbol(':'), ..] = rest {
+            if !SEGMENTS.iter().any(|segment| segment.eq_ignore_ascii_case(name)) {
+                if !name.contains('.') {
+                    global_label = name.clone();
+                }
+                lines.push(Line { number, statement: Statement::Label(name.clone()) });
+                rest = &rest[2..];
+            }
+        }
+        if rest.is_empty() {
+            continue;
+        }
+        //nombre EQU valor y nombre DB/DW datos, que además define la etiqueta
+        if let [Token::Ident(name), second, ..] = rest {
+            if is_ident(Some(second), "equ") {
+                let value = parse_expression(&rest[2..], number)?;
+                lines.push(Line { number, statement: Statement::Equ(name.clone(), value) });
+                continue;
+            }
+            if is_ident(Some(second), "db") || is_ident(Some(second), "dw") {
+                if !name.contains('.') {
+                    global_label = name.clone();
+                }
+                lines.push(Line { number, statement: Statement::Label(name.clone()) });
+                rest = &rest[1..];
+            }
+        }
+        let statement = parse_statement(rest, number)?;
+        lines.push(Line { number, statement });
+    }
+    Ok(lines)
+}
+
+fn parse_statement(tokens: &[Token], line: usize) -> Result<Statement, AssemblerError> {
+    let mut rest = tokens;
+    let mut prefixes = Vec::new();
+    //Prefijos escritos como palabras delante de la instrucción
+    while let Some(Token::Ident(word)) = rest.first() {
+        let prefix = match word.to_lowercase().as_str() {
+            "lock" => 0xF0,
+            "rep" | "repe" | "repz" => 0xF3,
+            "repne" | "repnz" => 0xF2,
+            name => match register_index(&SEGMENTS, name) {
+                Some(segment) if rest.len() > 1 && rest.get(1) != Some(&Token::Symbol(':')) => 0x26 | (segment << 3),
+                _ => break,
+            },
+        };
+        prefixes.push(prefix);
+        rest = &rest[1..];
+    }
+    let Some(Token::Ident(name)) = rest.first() else {
+        return error(line, "se esperaba una instrucción");
+    };
+    let name = name.to_lowercase();
+    let arguments = split_commas(&rest[1..]);
+    match name.as_str() {
+        "org" => Ok(Statement::Org(parse_expression(&rest[1..], line)?)),
+        "db" | "dw" => {
+            let items = arguments.iter().map(|item| parse_data_item(item, line)).collect::<Result<Vec<_>, _>>()?;
+            Ok(Statement::Data { word: name == "dw", items })
+        },
+        _ => {
+            let mut distance = None;
+            let mut operands = Vec::new();
+            for argument in arguments {
+                let (operand, operand_distance) = parse_operand(argument, line)?;
+                distance = distance.or(operand_distance);
+                operands.push(operand);
+            }
+            Ok(Statement::Instruction { prefixes, mnemonic: name, operands, distance })
+        },
+    }
+}
+
+//Separa por comas de primer nivel, fuera de corchetes y paréntesis
+fn split_commas(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut parts = Vec::new();
+    if tokens.is_empty() {
+        return parts;
+    }
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Symbol('[') | Token::Symbol('(') => depth += 1,
+            Token::Symbol(']') | Token::Symbol(')') => depth -= 1,
+            Token::Symbol(',') if depth == 0 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    parts.push(&tokens[start..]);
+    parts
+}
+
+fn parse_data_item(tokens: &[Token], line: usize) -> Result<DataItem, AssemblerError> {
+    match tokens {
+        [Token::Str(bytes)] if bytes.len() != 1 => Ok(DataItem::Bytes(bytes.clone())),
+        [Token::Ident(name)] if name == "?" => Ok(DataItem::Uninitialized),
+        _ => {
+            //cuenta DUP (datos)
+            if let Some(position) = tokens.iter().position(|token| is_ident(Some(token), "dup")) {
+                let count = parse_expression(&tokens[..position], line)?;
+                let inner = match &tokens[position + 1..] {
+                    [Token::Symbol('('), inner @ .., Token::Symbol(')')] => inner,
+                    _ => return error(line, "DUP necesita los datos entre paréntesis"),
+                };
+                let items = split_commas(inner).iter().map(|item| parse_data_item(item, line)).collect::<Result<Vec<_>, _>>()?;
+                return Ok(DataItem::Dup(count, items));
+            }
+            Ok(DataItem::Value(parse_expression(tokens, line)?))
+        },
+    }
+}
+
+fn parse_operand(tokens: &[Token], line: usize) -> Result<(AsmOperand, Option<Distance>), AssemblerError> {
+    let mut rest = tokens;
+    let mut size = None;
+    let mut distance = None;
+    while let Some(Token::Ident(word)) = rest.first() {
+        match word.to_lowercase().as_str() {
+            "byte" => size = Some(OperandSize::Byte),
+            "word" => size = Some(OperandSize::Word),
+            "dword" => size = Some(OperandSize::Dword),
+            "ptr" => {},
+            "short" => distance = Some(Distance::Short),
+            "near" => distance = Some(Distance::Near),
+            "far" => distance = Some(Distance::Far),
+            _ => break,
+        }
+        rest = &rest[1..];
+    }
+    if distance == Some(Distance::Far) {
+        size = Some(OperandSize::Dword);
+    }
+    //Segmento delante de los corchetes, como en es:[bx]
+    let mut segment = None;
+    if let [Token::Ident(name), Token::Symbol(':'), Token::Symbol('['), ..] = rest {
+        if let Some(index) = register_index(&SEGMENTS, name) {
+            segment = Some(index);
+            rest = &rest[2..];
+        }
+    }
+    let operand = match rest {
+        [] => return error(line, "falta un operando"),
+        [Token::Symbol('['), inner @ .., Token::Symbol(']')] => parse_memory(inner, size, segment, line)?,
+        [Token::Ident(name)] if register_index(&REGISTERS_B, name).is_some() => AsmOperand::Reg8(register_index(&REGISTERS_B, name).unwrap_or(0)),
+        [Token::Ident(name)] if register_index(&REGISTERS_W, name).is_some() => AsmOperand::Reg16(register_index(&REGISTERS_W, name).unwrap_or(0)),
+        [Token::Ident(name)] if register_index(&SEGMENTS, name).is_some() => AsmOperand::Sreg(register_index(&SEGMENTS, name).unwrap_or(0)),
+        _ => {
+            //segmento:offset inmediato
+            match rest.iter().position(|token| *token == Token::Symbol(':')) {
+                Some(position) => AsmOperand::Far(parse_expression(&rest[..position], line)?, parse_expression(&rest[position + 1..], line)?),
+                None => AsmOperand::Imm(parse_expression(rest, line)?),
+            }
+        },
+    };
+    Ok((operand, distance))
+}
+
+//Contenido de los corchetes: registros base e indice, segmento (es:) y desplazamiento
+fn parse_memory(tokens: &[Token], size: Option<OperandSize>, segment: Option<u8>, line: usize) -> Result<AsmOperand, AssemblerError> {
+    let mut rest = tokens;
+    let mut segment = segment;
+    if let [Token::Ident(name), Token::Symbol(':'), ..] = rest {
+        if let Some(index) = register_index(&SEGMENTS, name) {
+            segment = Some(index);
+            rest = &rest[2..];
+        }
+    }
+    //Se separa en términos sumados o restados, los que son un registro forman la base
+    let mut registers = Vec::new();
+    let mut displacement: Option<Expr> = None;
+    let mut depth = 0;
+    let mut start = 0;
+    let mut negative = false;
+    for i in 0..=rest.len() {
+        let at_end = i == rest.len();
+        if !at_end {
+            match rest[i] {
+                Token::Symbol('(') => depth += 1,
+                Token::Symbol(')') => depth -= 1,
+                _ => {},
+            }
+        }
+        let separator = !at_end && depth == 0 && i > start && matches!(rest[i], Token::Symbol('+') | Token::Symbol('-'));
+        if !(at_end || separator) {
+            continue;
+        }
+        let term = &rest[start..i];
+        match term {
+            //Sin nada entre los corchetes, se avisa más abajo como dirección vacía
+            [] if start == 0 => {},
+            [] => return error(line, "falta un término detrás del + o - en la dirección"),
+            [Token::Ident(name)] if register_index(&REGISTERS_W, name).is_some() => {
+                if negative {
+                    return error(line, "un registro no se puede restar en una dirección");
+                }
+                registers.push(name.to_lowercase());
+            },
+            _ => {
+                let value = parse_expression(term, line)?;
+                let value = if negative { Expr::Neg(Box::new(value)) } else { value };
+                displacement = Some(match displacement {
+                    Some(previous) => Expr::Binary('+', Box::new(previous), Box::new(value)),
+                    None => value,
+                });
+            },
+        }
+        if !at_end {
+            negative = rest[i] == Token::Symbol('-');
+            start = i + 1;
+        }
+    }
+    registers.sort();
+    let base = match registers.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [] => None,
+        ["bx", "si"] => Some(0b000),
+        ["bx", "di"] => Some(0b001),
+        ["bp", "si"] => Some(0b010),
+        ["bp", "di"] => Some(0b011),
+        ["si"] => Some(0b100),
+        ["di"] => Some(0b101),
+        ["bp"] => Some(0b110),
+        ["bx"] => Some(0b111),
+        _ => return error(line, "combinación de registros no válida en la dirección"),
+    };
+    if base.is_none() && displacement.is_none() {
+        return error(line, "dirección vacía");
+    }
+    Ok(AsmOperand::Mem { size, segment, base, displacement })
+}
+
+fn parse_expression(tokens: &[Token], line: usize) -> Result<Expr, AssemblerError> {
+    let mut position = 0;
+    let expr = parse_sum(tokens, &mut position, line)?;
+    if position != tokens.len() {
+        return error(line, "expresión no válida");
+    }
+    Ok(expr)
+}
+
+fn parse_sum(tokens: &[Token], position: &mut usize, line: usize) -> Result<Expr, AssemblerError> {
+    let mut left = parse_product(tokens, position, line)?;
+    while let Some(Token::Symbol(operator @ ('+' | '-'))) = tokens.get(*position) {
+        *position += 1;
+        let right = parse_product(tokens, position, line)?;
+        left = Expr::Binary(*operator, Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_product(tokens: &[Token], position: &mut usize, line: usize) -> Result<Expr, AssemblerError> {
+    let mut left = parse_unary(tokens, position, line)?;
+    while let Some(Token::Symbol(operator @ ('*' | '/'))) = tokens.get(*position) {
+        *position += 1;
+        let right = parse_unary(tokens, position, line)?;
+        left = Expr::Binary(*operator, Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_unary(tokens: &[Token], position: &mut usize, line: usize) -> Result<Expr, AssemblerError> {
+    let token = tokens.get(*position).cloned();
+    *position += 1;
+    match token {
+        Some(Token::Symbol('-')) => Ok(Expr::Neg(Box::new(parse_unary(tokens, position, line)?))),
+        Some(Token::Symbol('+')) => parse_unary(tokens, position, line),
+        Some(Token::Symbol('(')) => {
+            let expr = parse_sum(tokens, position, line)?;
+            if tokens.get(*position) != Some(&Token::Symbol(')')) {
+                return error(line, "falta un paréntesis");
+            }
+            *position += 1;
+            Ok(expr)
+        },
+        Some(Token::Number(value)) => Ok(Expr::Number(value)),
+        //Una cadena de uno o dos caracteres es un número, el primero en el byte bajo
+        Some(Token::Str(bytes)) if !bytes.is_empty() && bytes.len() <= 2 => {
+            Ok(Expr::Number(bytes.iter().rev().fold(0, |value, byte| value << 8 | *byte as i64)))
+        },
+        Some(Token::Ident(name)) if name == "$" => Ok(Expr::Current),
+        Some(Token::Ident(name)) if name != "?" => Ok(Expr::Symbol(name)),
+        _ => error(line, "expresión no válida"),
+    }
+}
+
+//Nombres de las instrucciones, con sus sinónimos. call y jmp pueden ser near o far según el operando
+fn mnemonics(name: &str) -> Option<&'static [Mnemonic]> {
+    use Mnemonic::*;
+    let mnemonics: &'static [Mnemonic] = match name {
+        "aaa" => &[Aaa], "aad" => &[Aad], "aam" => &[Aam], "aas" => &[Aas], "adc" => &[Adc], "add" => &[Add],
+        "and" => &[And], "call" => &[Call, CallFar], "cbw" => &[Cbw], "clc" => &[Clc], "cld" => &[Cld], "cli" => &[Cli],
+        "cmc" => &[Cmc], "cmp" => &[Cmp], "cmpsb" => &[Cmpsb], "cmpsw" => &[Cmpsw], "cwd" => &[Cwd], "daa" => &[Daa],
+        "das" => &[Das], "dec" => &[Dec], "div" => &[Div], "esc" => &[Esc], "hlt" => &[Hlt], "idiv" => &[Idiv],
+        "imul" => &[Imul], "in" => &[In], "inc" => &[Inc], "int" => &[Int], "int3" => &[Int3], "into" => &[Into],
+        "iret" => &[Iret], "jo" => &[Jo], "jno" => &[Jno], "jb" | "jc" | "jnae" => &[Jb], "jae" | "jnc" | "jnb" => &[Jae],
+        "je" | "jz" => &[Je], "jne" | "jnz" => &[Jne], "jbe" | "jna" => &[Jbe], "ja" | "jnbe" => &[Ja], "js" => &[Js],
+        "jns" => &[Jns], "jp" | "jpe" => &[Jp], "jnp" | "jpo" => &[Jnp], "jl" | "jnge" => &[Jl], "jge" | "jnl" => &[Jge],
+        "jle" | "jng" => &[Jle], "jg" | "jnle" => &[Jg], "jcxz" => &[Jcxz], "jmp" => &[Jmp, JmpFar], "lahf" => &[Lahf],
+        "lds" => &[Lds], "lea" => &[Lea], "les" => &[Les], "lodsb" => &[Lodsb], "lodsw" => &[Lodsw], "loop" => &[Loop],
+        "loope" | "loopz" => &[Loopz], "loopne" | "loopnz" => &[Loopnz], "mov" => &[Mov], "movsb" => &[Movsb],
+        "movsw" => &[Movsw], "mul" => &[Mul], "neg" => &[Neg], "nop" => &[Nop], "not" => &[Not], "or" => &[Or],
+        "out" => &[Out], "pop" => &[Pop], "popf" => &[Popf], "push" => &[Push], "pushf" => &[Pushf], "rcl" => &[Rcl],
+        "rcr" => &[Rcr], "ret" | "retn" => &[Ret], "retf" => &[Retf], "rol" => &[Rol], "ror" => &[Ror], "sahf" => &[Sahf],
+        "sar" => &[Sar], "sbb" => &[Sbb], "scasb" => &[Scasb], "scasw" => &[Scasw], "setmo" => &[Setmo],
+        "shl" | "sal" => &[Shl], "shr" => &[Shr], "stc" => &[Stc], "std" => &[Std], "sti" => &[Sti], "stosb" => &[Stosb],
+        "stosw" => &[Stosw], "sub" => &[Sub], "test" => &[Test], "wait" | "fwait" => &[Wait], "xchg" => &[Xchg],
+        "xlat" | "xlatb" => &[Xlat], "xor" => &[Xor],
+        _ => return None,
+    };
+    Some(mnemonics)
+}
+
+#[derive(Debug, Clone)]
+struct Symbol {
+    value: i64,
+    //Si al definirlo se conocía su valor. Los EQU circulares nunca llegan a conocerse
+    known: bool,
+    line: usize,
+}
+
+//Valor de una expresión y si todos sus símbolos tienen valor, de esta pasada o de la anterior.
+//Los valores desconocidos se suponen pequeños al elegir la forma de una instrucción
+#[derive(Debug, Clone, Copy)]
+struct Value {
+    value: i64,
+    known: bool,
+}
+
+struct Assembler {
+    symbols: HashMap<String, Symbol>,
+    address: i64,
+    final_pass: bool,
+    output: Vec<u8>,
+}
+
+//Forma de codificar una instrucción encontrada en la tabla de opcodes
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    opcode: u8,
+    group: Option<u8>,
+    patterns: [Pattern; 2],
+    swapped: bool,
+}
+
+impl Assembler {
+    fn pass(&mut self, lines: &[Line]) -> Result<(), AssemblerError> {
+        self.address = 0;
+        self.output.clear();
+        let mut defined = HashSet::new();
+        for line in lines {
+            let result = match &line.statement {
+                Statement::Label(name) => {
+                    let value = Value { value: self.address, known: true };
+                    self.define(name, value, line.number, &mut defined)
+                },
+                Statement::Equ(name, expr) => match self.eval(expr) {
+                    Ok(value) => self.define(name, value, line.number, &mut defined),
+                    Err(message) => Err(message),
+                },
+                Statement::Org(expr) => {
+                    let value = self.eval(expr);
+                    value.map(|value| self.address = value.value)
+                },
+                Statement::Data { word, items } => self.data(*word, items),
+                Statement::Instruction { prefixes, mnemonic, operands, distance } => self.instruction(prefixes, mnemonic, operands, *distance),
+            };
+            result.map_err(|message| AssemblerError { line: line.number, message })?;
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, value: Value, line: usize, defined: &mut HashSet<String>) -> Result<(), String> {
+        if !defined.insert(name.to_string()) {
+            return Err(format!("símbolo definido dos veces: {}", name));
+        }
+        self.symbols.insert(name.to_string(), Symbol { value: value.value, known: value.known, line });
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<Value, String> {
+        Ok(match expr {
+            Expr::Number(value) => Value { value: *value, known: true },
+            Expr::Current => Value { value: self.address, known: true },
+            Expr::Symbol(name) => match self.symbols.get(name) {
+                Some(symbol) => Value { value: symbol.value, known: symbol.known },
+                None if !self.final_pass => Value { value: 0, known: false },
+                None => return Err(format!("símbolo no definido: {}", name)),
+            },
+            Expr::Neg(inner) => {
+                let inner = self.eval(inner)?;
+                let value = inner.value.checked_neg().ok_or_else(Self::overflow)?;
+                Value { value, known: inner.known }
+            },
+            Expr::Binary(operator, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                let value = match operator {
+                    '+' => left.value.checked_add(right.value),
+                    '-' => left.value.checked_sub(right.value),
+                    '*' => left.value.checked_mul(right.value),
+                    _ if right.value == 0 && self.final_pass => return Err("división entre cero".to_string()),
+                    _ if right.value == 0 => Some(0),
+                    _ => left.value.checked_div(right.value),
+                };
+                Value {
+                    value: value.ok_or_else(Self::overflow)?,
+                    known: left.known && right.known,
+                }
+            },
+        })
+    }
+
+    fn overflow() -> String {
+        "desbordamiento en la expresión".to_string()
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.output.extend_from_slice(bytes);
+        self.address += bytes.len() as i64;
+    }
+
+    //Comprueba en la última pasada que un valor cabe en el campo en el que se va a guardar
+    fn check_range(&self, value: i64, min: i64, max: i64, message: &str) -> Result<(), String> {
+        if self.final_pass && !(min..=max).contains(&value) {
+            return Err(format!("{}: {}", message, value));
+        }
+        Ok(())
+    }
+
+    fn data(&mut self, word: bool, items: &[DataItem]) -> Result<(), String> {
+        for item in items {
+            match item {
+                DataItem::Bytes(bytes) if !word => self.emit(bytes),
+                DataItem::Bytes(bytes) => {
+                    for pair in bytes.chunks(2) {
+                        let value = pair.iter().rev().fold(0u16, |value, byte| value << 8 | *byte as u16);
+                        self.emit(&value.to_le_bytes());
+                    }
+                },
+                DataItem::Uninitialized => self.emit(if word { &[0, 0] } else { &[0] }),
+                DataItem::Value(expr) => {
+                    let value = self.eval(expr)?.value;
+                    if word {
+                        self.check_range(value, -0x8000, 0xFFFF, "valor fuera de rango para DW")?;
+                        self.emit(&(value as u16).to_le_bytes());
+                    }else{
+                        self.check_range(value, -0x80, 0xFF, "valor fuera de rango para DB")?;
+                        self.emit(&[value as u8]);
+                    }
+                },
+                DataItem::Dup(count, inner) => {
+                    let count = self.eval(count)?;
+                    if !count.known || count.value < 0 {
+                        return Err("DUP necesita un número de repeticiones conocido".to_string());
+                    }
+                    if count.value == 0 {
+                        continue;
+                    }
+                    //Se genera una repetición para saber cuánto ocupa y no reservar más de lo que cabe en un .com
+                    let start = self.output.len();
+                    self.data(word, inner)?;
+                    let size = (self.output.len() - start) as i64;
+                    if size == 0 {
+                        continue;
+                    }
+                    let total = (count.value - 1).saturating_mul(size).saturating_add(self.output.len() as i64);
+                    if total > MAX_PROGRAM_SIZE as i64 {
+                        return Err(format!("DUP genera más de {} bytes, lo máximo en un .com", MAX_PROGRAM_SIZE));
+                    }
+                    for _ in 1..count.value {
+                        self.data(word, inner)?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn instruction(&mut self, prefixes: &[u8], name: &str, operands: &[AsmOperand], distance: Option<Distance>) -> Result<(), String> {
+        let Some(mnemonics) = mnemonics(name) else {
+            return Err(format!("instrucción desconocida: {}", name));
+        };
+        //AAM y AAD sin operando usan la base 10
+        let mut operands = operands.to_vec();
+        if operands.is_empty() && matches!(mnemonics, [Mnemonic::Aam] | [Mnemonic::Aad]) {
+            operands.push(AsmOperand::Imm(Expr::Number(10)));
+        }
+        if operands.len() > 2 {
+            return Err("demasiados operandos".to_string());
+        }
+        let mut candidates = Vec::new();
+        for opcode in 0..=0xFFu8 {
+            match OPCODE_TABLE[opcode as usize] {
+                Entry::Op(mnemonic, first, second) if mnemonics.contains(&mnemonic) => {
+                    self.add_candidates(&mut candidates, opcode, None, [first, second], mnemonic, &operands, distance)?;
+                },
+                Entry::Group(table) => {
+                    for (index, (mnemonic, first, second)) in table.iter().enumerate() {
+                        if mnemonics.contains(mnemonic) {
+                            self.add_candidates(&mut candidates, opcode, Some(index as u8), [*first, *second], *mnemonic, &operands, distance)?;
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+        //La forma más corta de las que caben; si no cabe ninguna (un JZ a más de 127 bytes, por ejemplo)
+        //la más corta, y la última pasada dará el error de valor fuera de rango
+        let mut best: Option<(Candidate, usize, bool)> = None;
+        for candidate in candidates {
+            let ordered = Self::ordered(&operands, candidate.swapped);
+            let length = self.encoded_length(candidate, &ordered, prefixes)?;
+            let safe = self.is_safe(candidate, &ordered, length)?;
+            let better = match best {
+                None => true,
+                Some((_, best_length, best_safe)) => (safe && !best_safe) || (safe == best_safe && length < best_length),
+            };
+            if better {
+                best = Some((candidate, length, safe));
+            }
+        }
+        let Some((candidate, _, _)) = best else {
+            let missing_size = operands.iter().any(|operand| matches!(operand, AsmOperand::Mem { size: None, .. }))
+                && !operands.iter().any(|operand| matches!(operand, AsmOperand::Reg8(_) | AsmOperand::Reg16(_) | AsmOperand::Sreg(_)));
+            return Err(if missing_size {
+                format!("falta el tamaño del operando en memoria (byte o word) en {}", name)
+            }else{
+                format!("combinación de operandos no válida para {}", name)
+            });
+        };
+        let ordered = Self::ordered(&operands, candidate.swapped);
+        let bytes = self.encode(candidate, &ordered, prefixes)?;
+        self.emit(&bytes);
+        Ok(())
+    }
+
+    fn ordered(operands: &[AsmOperand], swapped: bool) -> Vec<AsmOperand> {
+        let mut ordered = operands.to_vec();
+        if swapped {
+            ordered.reverse();
+        }
+        ordered
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn add_candidates(&self, candidates: &mut Vec<Candidate>, opcode: u8, group: Option<u8>, patterns: [Pattern; 2], mnemonic: Mnemonic, operands: &[AsmOperand], distance: Option<Distance>) -> Result<(), String> {
+        let count = patterns.iter().filter(|pattern| **pattern != Pattern::None).count();
+        if count != operands.len() {
+            return Ok(());
+        }
+        //XCHG y TEST admiten los operandos en cualquier orden
+        let orders: &[bool] = if matches!(mnemonic, Mnemonic::Xchg | Mnemonic::Test) && operands.len() == 2 { &[false, true] } else { &[false] };
+        for &swapped in orders {
+            let ordered = Self::ordered(operands, swapped);
+            let mut matched = true;
+            for (i, operand) in ordered.iter().enumerate() {
+                let other = ordered.get(1 - i);
+                if !self.matches(patterns[i], operand, other, opcode, mnemonic, distance)? {
+                    matched = false;
+                }
+            }
+            if matched {
+                candidates.push(Candidate { opcode, group, patterns, swapped });
+            }
+        }
+        Ok(())
+    }
+
+    //Tamaño de un operando en memoria: el indicado, el del registro del otro operando o, en las instrucciones
+    //que solo trabajan con words (PUSH, POP, CALL y JMP), word
+    fn memory_size(size: Option<OperandSize>, other: Option<&AsmOperand>, mnemonic: Mnemonic) -> Option<OperandSize> {
+        size.or(match other {
+            Some(AsmOperand::Reg8(_)) => Some(OperandSize::Byte),
+            Some(AsmOperand::Reg16(_)) | Some(AsmOperand::Sreg(_)) => Some(OperandSize::Word),
+            _ if matches!(mnemonic, Mnemonic::Push | Mnemonic::Pop | Mnemonic::Call | Mnemonic::Jmp) => Some(OperandSize::Word),
+            _ => None,
+        })
+    }
+
+    fn matches(&self, pattern: Pattern, operand: &AsmOperand, other: Option<&AsmOperand>, opcode: u8, mnemonic: Mnemonic, distance: Option<Distance>) -> Result<bool, String> {
+        //LEA, LDS, LES y ESC solo usan la dirección, el tamaño no importa
+        let any_size = matches!(mnemonic, Mnemonic::Lea | Mnemonic::Lds | Mnemonic::Les | Mnemonic::Esc);
+        let memory_size = |size: &Option<OperandSize>, wanted: OperandSize| {
+            any_size || Self::memory_size(*size, other, mnemonic) == Some(wanted)
+        };
+        Ok(match (pattern, operand) {
+            (Pattern::Eb, AsmOperand::Reg8(_)) | (Pattern::Gb, AsmOperand::Reg8(_)) => true,
+            (Pattern::Ew, AsmOperand::Reg16(_)) | (Pattern::Gw, AsmOperand::Reg16(_)) => true,
+            //Con far el salto indirecto tiene que ser lejano
+            (Pattern::Ew, AsmOperand::Mem { .. }) if distance == Some(Distance::Far) => false,
+            (Pattern::Eb, AsmOperand::Mem { size, .. }) => memory_size(size, OperandSize::Byte),
+            (Pattern::Ew, AsmOperand::Mem { size, .. }) => memory_size(size, OperandSize::Word),
+            (Pattern::Ep, AsmOperand::Mem { size, .. }) => memory_size(size, OperandSize::Dword),
+            (Pattern::Ob, AsmOperand::Mem { size, base: None, .. }) => memory_size(size, OperandSize::Byte),
+            (Pattern::Ow, AsmOperand::Mem { size, base: None, .. }) => memory_size(size, OperandSize::Word),
+            (Pattern::Sw, AsmOperand::Sreg(_)) => true,
+            (Pattern::Ib | Pattern::Iw | Pattern::Is, AsmOperand::Imm(_)) => true,
+            (Pattern::Rb, AsmOperand::Imm(_)) => distance != Some(Distance::Near) && distance != Some(Distance::Far),
+            (Pattern::Rw, AsmOperand::Imm(_)) => distance != Some(Distance::Short) && distance != Some(Distance::Far),
+            (Pattern::Ap, AsmOperand::Far(..)) => true,
+            (Pattern::Al, AsmOperand::Reg8(0)) | (Pattern::Ax, AsmOperand::Reg16(0)) => true,
+            (Pattern::Cl, AsmOperand::Reg8(1)) | (Pattern::Dx, AsmOperand::Reg16(2)) => true,
+            (Pattern::Zb, AsmOperand::Reg8(index)) | (Pattern::Zw, AsmOperand::Reg16(index)) => *index == opcode & 0x07,
+            (Pattern::Zs, AsmOperand::Sreg(index)) => *index == (opcode >> 3) & 0x03,
+            (Pattern::One, AsmOperand::Imm(expr)) => {
+                let value = self.eval(expr)?;
+                !value.known || value.value == 1
+            },
+            (Pattern::Cop, AsmOperand::Imm(expr)) => {
+                let value = self.eval(expr)?;
+                !value.known || (value.value >> 3) & 0x07 == (opcode & 0x07) as i64
+            },
+            _ => false,
+        })
+    }
+
+    //Campos de memoria del ModRM: mod, r/m y desplazamiento. El desplazamiento de 8 bits se usa si el valor
+    //cabe o todavía no se conoce, y [bp] sin desplazamiento necesita mod 01 con desplazamiento 0
+    fn memory_fields(&self, base: Option<u8>, displacement: &Option<Expr>) -> Result<(u8, u8, Vec<u8>), String> {
+        let value = match displacement {
+            Some(expr) => Some(self.eval(expr)?),
+            None => None,
+        };
+        let Some(rm_field) = base else {
+            let address = value.map_or(0, |value| value.value);
+            self.check_range(address, -0x8000, 0xFFFF, "dirección fuera de rango")?;
+            return Ok((0b00, 0b110, (address as u16).to_le_bytes().to_vec()));
+        };
+        Ok(match value {
+            None if rm_field != 0b110 => (0b00, rm_field, Vec::new()),
+            None => (0b01, rm_field, vec![0]),
+            Some(Value { value: 0, known: true }) if rm_field != 0b110 => (0b00, rm_field, Vec::new()),
+            Some(Value { value, known }) if !known || (-0x80..=0x7F).contains(&value) => (0b01, rm_field, vec![value as u8]),
+            Some(Value { value, .. }) => {
+                self.check_range(value, -0x8000, 0xFFFF, "desplazamiento fuera de rango")?;
+                (0b10, rm_field, (value as u16).to_le_bytes().to_vec())
+            },
+        })
+    }
+
+    fn uses_modrm(candidate: Candidate) -> bool {
+        candidate.group.is_some() || candidate.patterns.iter().any(|pattern| {
+            matches!(pattern, Pattern::Eb | Pattern::Ew | Pattern::Ep | Pattern::Gb | Pattern::Gw | Pattern::Sw | Pattern::Cop)
+        })
+    }
+
+    fn segment_prefix(operands: &[AsmOperand]) -> Option<u8> {
+        operands.iter().find_map(|operand| match operand {
+            AsmOperand::Mem { segment: Some(segment), .. } => Some(0x26 | (segment << 3)),
+            _ => None,
+        })
+    }
+
+    fn encoded_length(&self, candidate: Candidate, operands: &[AsmOperand], prefixes: &[u8]) -> Result<usize, String> {
+        let mut length = prefixes.len() + 1 + Self::segment_prefix(operands).map_or(0, |_| 1);
+        if Self::uses_modrm(candidate) {
+            length += 1;
+        }
+        for (pattern, operand) in candidate.patterns.iter().zip(operands) {
+            length += match (pattern, operand) {
+                (Pattern::Eb | Pattern::Ew | Pattern::Ep, AsmOperand::Mem { base, displacement, .. }) => self.memory_fields(*base, displacement)?.2.len(),
+                (Pattern::Ob | Pattern::Ow | Pattern::Iw | Pattern::Rw, _) => 2,
+                (Pattern::Ib | Pattern::Is | Pattern::Rb, _) => 1,
+                (Pattern::Ap, _) => 4,
+                _ => 0,
+            };
+        }
+        Ok(length)
+    }
+
+    //Si todos los valores de la forma caben en ella, los que todavía no se conocen se suponen pequeños
+    fn is_safe(&self, candidate: Candidate, operands: &[AsmOperand], length: usize) -> Result<bool, String> {
+        for (pattern, operand) in candidate.patterns.iter().zip(operands) {
+            let AsmOperand::Imm(expr) = operand else {
+                continue;
+            };
+            let value = self.eval(expr)?;
+            let safe = match pattern {
+                Pattern::Ib => !value.known || (-0x80..=0xFF).contains(&value.value),
+                Pattern::Is => !value.known || Self::fits_signed_byte(value.value),
+                Pattern::Rb => !value.known || (-0x80..=0x7F).contains(&Self::relative(value.value, self.address + length as i64)),
+                _ => true,
+            };
+            if !safe {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    //Desplazamiento de un salto hasta el destino, IP da la vuelta al pasar de 0xFFFF
+    fn relative(target: i64, next: i64) -> i64 {
+        target.wrapping_sub(next) as u16 as i16 as i64
+    }
+
+    //Un inmediato de 16 bits que se puede guardar en 8 bits extendidos con signo (0xFFFF es -1)
+    fn fits_signed_byte(value: i64) -> bool {
+        (-0x80..=0x7F).contains(&value) || (0xFF80..=0xFFFF).contains(&value)
+    }
+
+    fn encode(&self, candidate: Candidate, operands: &[AsmOperand], prefixes: &[u8]) -> Result<Vec<u8>, String> {
+        let length = self.encoded_length(candidate, operands, prefixes)? as i64;
+        let mut bytes = prefixes.to_vec();
+        bytes.extend(Self::segment_prefix(operands));
+        bytes.push(candidate.opcode);
+        let mut modrm = candidate.group.unwrap_or(0) << 3;
+        let mut data = Vec::new();
+        for (pattern, operand) in candidate.patterns.iter().zip(operands) {
+            match (pattern, operand) {
+                (Pattern::Eb | Pattern::Ew | Pattern::Ep, AsmOperand::Reg8(index) | AsmOperand::Reg16(index)) => modrm |= 0b11000000 | index,
+                (Pattern::Eb | Pattern::Ew | Pattern::Ep, AsmOperand::Mem { base, displacement, .. }) => {
+                    let (mod_field, rm_field, displacement) = self.memory_fields(*base, displacement)?;
+                    modrm |= mod_field << 6 | rm_field;
+                    data.extend(displacement);
+                },
+                (Pattern::Gb | Pattern::Gw, AsmOperand::Reg8(index) | AsmOperand::Reg16(index)) => modrm |= index << 3,
+                (Pattern::Sw, AsmOperand::Sreg(index)) => modrm |= index << 3,
+                (Pattern::Ob | Pattern::Ow, AsmOperand::Mem { displacement, .. }) => data.extend(self.memory_fields(None, displacement)?.2),
+                (Pattern::Ap, AsmOperand::Far(segment, offset)) => {
+                    let segment = self.eval(segment)?.value;
+                    let offset = self.eval(offset)?.value;
+                    self.check_range(segment, 0, 0xFFFF, "segmento fuera de rango")?;
+                    self.check_range(offset, 0, 0xFFFF, "offset fuera de rango")?;
+                    data.extend((offset as u16).to_le_bytes());
+                    data.extend((segment as u16).to_le_bytes());
+                },
+                (_, AsmOperand::Imm(expr)) => {
+                    let value = self.eval(expr)?.value;
+                    match pattern {
+                        Pattern::Ib => {
+                            self.check_range(value, -0x80, 0xFF, "valor fuera de rango para 8 bits")?;
+                            data.push(value as u8);
+                        },
+                        Pattern::Is => data.push(value as u8),
+                        Pattern::Iw => {
+                            self.check_range(value, -0x8000, 0xFFFF, "valor fuera de rango para 16 bits")?;
+                            data.extend((value as u16).to_le_bytes());
+                        },
+                        Pattern::Rb => {
+                            let displacement = Self::relative(value, self.address + length);
+                            self.check_range(displacement, -0x80, 0x7F, "salto corto fuera de rango")?;
+                            data.push(displacement as u8);
+                        },
+                        Pattern::Rw => data.extend((value.wrapping_sub(self.address + length) as u16).to_le_bytes()),
+                        Pattern::Cop => modrm |= ((value & 0x07) as u8) << 3,
+                        _ => {},
+                    }
+                },
+                _ => {},
+            }
+        }
+        if Self::uses_modrm(candidate) {
+            bytes.push(modrm);
+        }
+        bytes.extend(data);
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ensambla(source: &str) -> Vec<u8> {
+        assemble(source).unwrap()
+    }
+
+    #[test]
+    fn test_ensambla_fuentes_de_tests() {
+        //Los .com de tests/mov están hechos a mano y los fuentes los reproducen byte a byte, las codificaciones
+        //que no son las que elige el ensamblador van como db
+        for name in ["MOV_ACC", "MOV_SEG", "MOV_IMM_MEM", "MOV_WORD_MEM", "MOV_WORD_REG"] {
+            let source = std::fs::read_to_string(format!("./tests/mov/{}.asm", name)).unwrap();
+            let expected = std::fs::read(format!("./tests/mov/{}.com", name)).unwrap();
+            assert_eq!(ensambla(&source), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_formas_cortas() {
+        assert_eq!(ensambla("add ax,5"), vec![0x05, 0x05, 0x00]);
+        assert_eq!(ensambla("add bx,-1"), vec![0x83, 0xC3, 0xFF]);
+        assert_eq!(ensambla("add bx,1000h"), vec![0x81, 0xC3, 0x00, 0x10]);
+        assert_eq!(ensambla("mov al,[bp]"), vec![0x8A, 0x46, 0x00]);
+        assert_eq!(ensambla("mov ax,[es:bx+si-2]"), vec![0x26, 0x8B, 0x40, 0xFE]);
+        assert_eq!(ensambla("mov ax,word ptr es:[di+100h]"), vec![0x26, 0x8B, 0x85, 0x00, 0x01]);
+        assert_eq!(ensambla("shl word [bx],1\nrcr al,cl"), vec![0xD1, 0x27, 0xD2, 0xD8]);
+        assert_eq!(ensambla("xchg cx,ax\nxchg [bx],dl\ntest al,[si]"), vec![0x91, 0x86, 0x17, 0x84, 0x04]);
+        assert_eq!(ensambla("aam\naad 16"), vec![0xD4, 0x0A, 0xD5, 0x10]);
+        assert_eq!(ensambla("rep movsb\nrepne scasw\ncs lodsb"), vec![0xF3, 0xA4, 0xF2, 0xAF, 0x2E, 0xAC]);
+        assert_eq!(ensambla("push es\npop ds\npush word [bx]\nint 21h\nint3"), vec![0x06, 0x1F, 0xFF, 0x37, 0xCD, 0x21, 0xCC]);
+        assert_eq!(ensambla("in al,dx\nout 60h,ax\nesc 3Fh,[bx]"), vec![0xEC, 0xE7, 0x60, 0xDF, 0x3F]);
+    }
+
+    #[test]
+    fn test_saltos_y_etiquetas() {
+        let source = "org 100h
+inicio:
+    mov cx,3
+.bucle:
+    loop .bucle
+    jmp inicio
+    jmp fin
+    call far 0700h:0100h
+    jmp dword [bx]
+    jz short fin
+fin:
+    ret";
+        assert_eq!(ensambla(source), vec![
+            0xB9, 0x03, 0x00,
+            0xE2, 0xFE,
+            0xEB, 0xF9,
+            0xEB, 0x09,
+            0x9A, 0x00, 0x01, 0x00, 0x07,
+            0xFF, 0x2F,
+            0x74, 0x00,
+            0xC3,
+        ]);
+        assert!(assemble("jz lejos\ndb 200 dup (0)\nlejos: ret").is_err());
+    }
+
+    #[test]
+    fn test_saltos_hacia_delante_como_fasm() {
+        //Los saltos hacia delante empiezan cortos y solo se alargan si el destino queda a más de 127 bytes.
+        //jmp medio cabe justo en la primera pasada, pero al alargarse jmp lejos deja de caber
+        let source = "jmp medio
+jmp lejos
+db 125 dup (0)
+medio: db 10 dup (0)
+lejos: ret";
+        let mut expected = vec![0xE9, 0x80, 0x00, 0xE9, 0x87, 0x00];
+        expected.extend([0; 135]);
+        expected.push(0xC3);
+        assert_eq!(ensambla(source), expected);
+        assert_eq!(ensambla("jmp near fin\nfin: ret"), vec![0xE9, 0x00, 0x00, 0xC3]);
+        //Lo mismo con inmediatos y desplazamientos de símbolos posteriores
+        assert_eq!(ensambla("add bx,VALOR\nmov al,[si+VALOR]\nVALOR equ 5"), vec![0x83, 0xC3, 0x05, 0x8A, 0x44, 0x05]);
+        assert_eq!(ensambla("add bx,VALOR\nVALOR equ 1000h"), vec![0x81, 0xC3, 0x00, 0x10]);
+    }
+
+    #[test]
+    fn test_datos_y_constantes() {
+        let source = "org 100h
+LONGITUD equ fin - texto
+    mov cx,LONGITUD
+    mov si,texto
+texto db 'Hola',13,10
+fin:
+tabla dw 1234h, fin, 2 dup (?)
+    db '$'";
+        assert_eq!(ensambla(source), vec![
+            0xB9, 0x06, 0x00,
+            0xBE, 0x06, 0x01,
+            b'H', b'o', b'l', b'a', 13, 10,
+            0x34, 0x12, 0x0C, 0x01, 0x00, 0x00, 0x00, 0x00,
+            b'$',
+        ]);
+    }
+
+    #[test]
+    fn test_errores() {
+        assert_eq!(assemble("nop\nmovx ax,1").unwrap_err(), AssemblerError { line: 2, message: "instrucción desconocida: movx".to_string() });
+        assert_eq!(assemble("mov [bx],1").unwrap_err().line, 1);
+        assert!(assemble("mov al,300").is_err());
+        assert!(assemble("mov ax,[bx+bp]").is_err());
+        assert!(assemble("mov ax,[bx+]").is_err());
+        assert!(assemble("mov ax,[bx+si-]").is_err());
+        assert_eq!(ensambla("mov ax,[bx+-2]"), vec![0x8B, 0x47, 0xFE]);
+        assert!(assemble("jmp nada").is_err());
+        assert!(assemble("a: nop\na: nop").is_err());
+        let desbordamiento = |source: &str| assemble(source).unwrap_err().message;
+        assert_eq!(desbordamiento("mov ax,(0-9223372036854775807-1)/(0-1)"), "desbordamiento en la expresión");
+        assert_eq!(desbordamiento("mov ax,9223372036854775807+1"), "desbordamiento en la expresión");
+        assert_eq!(desbordamiento("mov ax,-(0-9223372036854775807-1)"), "desbordamiento en la expresión");
+        assert_eq!(desbordamiento("mov ax,4294967296*4294967296"), "desbordamiento en la expresión");
+        assert_eq!(assemble("x equ y\ny equ x\nmov ax,x").unwrap_err(), AssemblerError { line: 1, message: "la definición de x depende de sí misma".to_string() });
+        assert_eq!(assemble("x equ x+1").unwrap_err().line, 1);
+        assert_eq!(assemble("nop\ndb 99999999999 dup (0)").unwrap_err().line, 2);
+        assert!(assemble("db 8000h dup (?)\ndw 4000h dup (1,2)").is_err());
+        assert_eq!(ensambla("db 0FF00h dup (0)").len(), MAX_PROGRAM_SIZE);
+        assert_eq!(ensambla("db 99999999999 dup ('')"), Vec::<u8>::new());
+        //Un EQU que usa otro definido más adelante sí se resuelve
+        assert_eq!(ensambla("mov ax,x\nx equ y*2\ny equ 21"), vec![0xB8, 0x2A, 0x00]);
+    }
+}
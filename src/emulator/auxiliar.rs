@@ -8,117 +8,187 @@ pub const FLAG_TF: u16 = 0b0000_0001_0000_0000;  // Bit 8
 pub const FLAG_IF: u16 = 0b0000_0010_0000_0000;  // Bit 9
 pub const FLAG_DF: u16 = 0b0000_0100_0000_0000;  // Bit 10
 pub const FLAG_OF: u16 = 0b0000_1000_0000_0000;  // Bit 11
+//Bits de flags que existen en el 8086, el resto no se pueden modificar
+pub const FLAGS_MASK: u16 = FLAG_CF | FLAG_PF | FLAG_AF | FLAG_ZF | FLAG_SF | FLAG_TF | FLAG_IF | FLAG_DF | FLAG_OF;
+//En el 8086 el bit 1 y los bits 12-15 se leen siempre a 1 (por ejemplo con PUSHF)
+pub const FLAGS_RESERVED: u16 = 0b1111_0000_0000_0010;
 
-///ADD///
+///ADD y ADC///
 //Funcion que recibe dos numeros ya sean positivos o negativos y devuelve el resultado de la suma
 // si hay overflow y el carry
 pub fn add_8bit_complemento_a2(a: u8, b: u8) -> (u8, bool, bool, bool) {
-    let (result, overflow) = a.overflowing_add(b);
-    let carry = ((a as u16 + b as u16) & 0x0100) != 0;
-    let aux = (a & 0xF) + (b & 0xF) > 0xF; // Check for auxiliary flag
-    (result, overflow, carry, aux)
+    adc_8bit_complemento_a2(a, b, false)
 }
 
 pub fn add_16bit_complemento_a2(a: u16, b: u16) -> (u16, bool, bool, bool) {
-    let (result, overflow) = a.overflowing_add(b);
-    let carry = ((a as u32 + b as u32) & 0x0001_0000) != 0;
-    let aux = (a & 0xF) + (b & 0xF) > 0xF; // Check for auxiliary flag
+    adc_16bit_complemento_a2(a, b, false)
+}
+
+//Suma a + b + carry. Hay overflow cuando los dos operandos tienen el mismo signo y el resultado no
+pub fn adc_8bit_complemento_a2(a: u8, b: u8, carry: bool) -> (u8, bool, bool, bool) {
+    let carry_in = carry as u16;
+    let sum = a as u16 + b as u16 + carry_in;
+    let result = sum as u8;
+    let overflow = (!(a ^ b) & (a ^ result) & 0x80) != 0;
+    let carry = (sum & 0x0100) != 0;
+    let aux = (a & 0xF) as u16 + (b & 0xF) as u16 + carry_in > 0xF; // Check for auxiliary flag
+    (result, overflow, carry, aux)
+}
+
+pub fn adc_16bit_complemento_a2(a: u16, b: u16, carry: bool) -> (u16, bool, bool, bool) {
+    let carry_in = carry as u32;
+    let sum = a as u32 + b as u32 + carry_in;
+    let result = sum as u16;
+    let overflow = (!(a ^ b) & (a ^ result) & 0x8000) != 0;
+    let carry = (sum & 0x0001_0000) != 0;
+    let aux = (a & 0xF) as u32 + (b & 0xF) as u32 + carry_in > 0xF; // Check for auxiliary flag
     (result, overflow, carry, aux)
 }
 
 
 //Función que actualiza las flags de la operación de suma
-pub fn actualizar_flags_add(flags: &mut u16, resultado: u16, overflow: bool, carry: bool, aux: bool) {
-    // Sign flag
-    *flags &= !(FLAG_CF | FLAG_PF | FLAG_AF | FLAG_ZF | FLAG_SF | FLAG_OF); //Limpiamos las flags
-    if (resultado & 0x8000) != 0 {
-        *flags |= FLAG_SF;
-    } else {
-        *flags &= !FLAG_SF;
-    }
-    
-    // Zero flag
-    if resultado == 0 {
-        *flags |= FLAG_ZF;
-    } else {
-        *flags &= !FLAG_ZF;
-    }
+pub fn actualizar_flags_add(flags: &mut u16, resultado: u16, es_byte: bool, overflow: bool, carry: bool, aux: bool) {
+    *flags &= !(FLAG_CF | FLAG_AF | FLAG_OF); //Limpiamos las flags
+
+    // Sign, zero y parity según el tamaño del operando
+    actualizar_flags_szp(flags, resultado, es_byte);
 
     // Overflow flag
     if overflow {
         *flags |= FLAG_OF;
-    } else {
-        *flags &= !FLAG_OF;
     }
-    
+
     // Carry flag
     if carry {
         *flags |= FLAG_CF;
-    } else {
-        *flags &= !FLAG_CF;
     }
 
-    // Parity flag: verificar si el número de bits establecidos en el byte de menor peso es par
-    let mut count = 0;
-    for i in 0..8 {
-        if (resultado & (1 << i)) != 0 {
-            count += 1;
-        }
-    }
-    if count % 2 == 0 {
-        *flags |= FLAG_PF;
-    } else {
-        *flags &= !FLAG_PF;
-    }
-    
     // Auxiliary flag: verifica si hay un carry de los primeros 4 bits
     if aux {
         *flags |= FLAG_AF;
-    } else {
-        *flags &= !FLAG_AF;
     }
 }
 
-///AND
-pub fn actualizar_flags_and(flags: &mut u16, resultado: u16) {
-    // Limpiamos las flags relevantes
-    *flags &= !(FLAG_CF | FLAG_PF | FLAG_ZF | FLAG_SF | FLAG_OF);
+///SUB, SBB y CMP///
+//Resta dos numeros en complemento a 2 (a - b - borrow) y devuelve el resultado,
+//si hay overflow, el carry (préstamo del bit más alto) y el auxiliar (préstamo del bit 3)
+pub fn sub_8bit_complemento_a2(a: u8, b: u8, borrow: bool) -> (u8, bool, bool, bool) {
+    let borrow = borrow as u8;
+    let result = a.wrapping_sub(b).wrapping_sub(borrow);
+    let overflow = ((a ^ b) & (a ^ result) & 0x80) != 0; // Operandos de distinto signo y el signo del resultado cambia
+    let carry = (a as u16) < (b as u16) + (borrow as u16);
+    let aux = (a & 0xF) < (b & 0xF) + borrow;
+    (result, overflow, carry, aux)
+}
+
+pub fn sub_16bit_complemento_a2(a: u16, b: u16, borrow: bool) -> (u16, bool, bool, bool) {
+    let borrow = borrow as u16;
+    let result = a.wrapping_sub(b).wrapping_sub(borrow);
+    let overflow = ((a ^ b) & (a ^ result) & 0x8000) != 0;
+    let carry = (a as u32) < (b as u32) + (borrow as u32);
+    let aux = (a & 0xF) < (b & 0xF) + borrow;
+    (result, overflow, carry, aux)
+}
 
-    // CF y OF siempre son 0 después de una operación AND
-    *flags &= !FLAG_CF;
-    *flags &= !FLAG_OF;
+//Actualiza SF, ZF y PF según el tamaño del operando (byte o word)
+pub fn actualizar_flags_szp(flags: &mut u16, resultado: u16, es_byte: bool) {
+    *flags &= !(FLAG_PF | FLAG_ZF | FLAG_SF);
+    let (resultado, signo) = if es_byte { (resultado & 0x00FF, 0x0080) } else { (resultado, 0x8000) };
 
-    // SF: establecida si el bit más significativo es 1
-    if (resultado & 0x8000) != 0 {
+    // SF: bit más significativo del operando
+    if (resultado & signo) != 0 {
         *flags |= FLAG_SF;
-    } else {
-        *flags &= !FLAG_SF;
     }
 
-    // ZF: establecida si el resultado es 0
+    // ZF
     if resultado == 0 {
         *flags |= FLAG_ZF;
-    } else {
-        *flags &= !FLAG_ZF;
     }
 
-    // PF: establecida si el número de bits establecidos en el byte menos significativo es par
-    let mut count = 0;
-    for i in 0..8 {
-        if (resultado & (1 << i)) != 0 {
-            count += 1;
-        }
-    }
-    if count % 2 == 0 {
+    // PF: solo se tiene en cuenta el byte de menor peso
+    if (resultado & 0x00FF).count_ones().is_multiple_of(2) {
         *flags |= FLAG_PF;
-    } else {
-        *flags &= !FLAG_PF;
     }
+}
 
-    // AF no se define para la operación AND, por lo que no la modificamos
+//Función que actualiza las flags de las operaciones de resta (SUB, SBB y CMP)
+//Se actualizan igual que en la suma, el carry indica el préstamo
+pub fn actualizar_flags_sub(flags: &mut u16, resultado: u16, es_byte: bool, overflow: bool, carry: bool, aux: bool) {
+    actualizar_flags_add(flags, resultado, es_byte, overflow, carry, aux);
+}
+
+///AND, OR, XOR y TEST
+pub fn actualizar_flags_logic(flags: &mut u16, resultado: u16, es_byte: bool) {
+    // CF y OF siempre son 0 después de una operación lógica
+    // AF queda indefinida según el manual, la dejamos siempre a 0 para que sea determinista
+    *flags &= !(FLAG_CF | FLAG_OF | FLAG_AF);
+
+    // SF, ZF y PF según el resultado
+    actualizar_flags_szp(flags, resultado, es_byte);
+}
+
+///Rotaciones y desplazamientos (grupo D0-D3)
+//Operaciones según el campo reg del ModRM
+pub const ROL: u8 = 0b000;
+pub const ROR: u8 = 0b001;
+pub const RCL: u8 = 0b010;
+pub const RCR: u8 = 0b011;
+pub const SHL: u8 = 0b100;
+pub const SHR: u8 = 0b101;
+//No documentada: en el 8086 /6 es SETMO, deja el operando con todos los bits a 1
+//(no es un alias de SHL como en algunas tablas). Se emula así, con CF, OF y AF a 0 y SF, ZF y PF según el resultado
+pub const SETMO: u8 = 0b110;
+pub const SAR: u8 = 0b111;
+
+//Aplica count veces la operación de un bit sobre value, igual que el microcódigo del 8086 que no
+//enmascara el contador (CL puede valer hasta 255). Devuelve el resultado y las flags actualizadas.
+//Con count 0 no se modifica nada. OF se calcula en cada paso, así que con count > 1 queda el valor del último
+//(el manual lo deja indefinido). AF queda indefinida en los desplazamientos y se deja a 0.
+pub fn shift_rotate(operation: u8, value: u16, count: u8, es_byte: bool, flags: u16) -> (u16, u16) {
+    if count == 0 {
+        return (value, flags);
+    }
+    let (mask, msb) = if es_byte { (0x00FFu16, 0x0080u16) } else { (0xFFFFu16, 0x8000u16) };
+    let mut result = value & mask;
+    let mut carry = flags & FLAG_CF != 0;
+    for _ in 0..count {
+        let (new_result, new_carry) = match operation {
+            ROL => (((result << 1) | (result & msb != 0) as u16) & mask, result & msb != 0),
+            ROR => ((result >> 1) | if result & 1 != 0 { msb } else { 0 }, result & 1 != 0),
+            RCL => (((result << 1) | carry as u16) & mask, result & msb != 0),
+            RCR => ((result >> 1) | if carry { msb } else { 0 }, result & 1 != 0),
+            SHL => ((result << 1) & mask, result & msb != 0),
+            SHR => (result >> 1, result & 1 != 0),
+            SETMO => (mask, false),
+            _ => ((result >> 1) | (result & msb), result & 1 != 0), // SAR
+        };
+        result = new_result;
+        carry = new_carry;
+    }
+
+    let mut flags = flags & !(FLAG_CF | FLAG_OF);
+    if carry {
+        flags |= FLAG_CF;
+    }
+    let overflow = match operation {
+        // Desplazamientos a la izquierda: el bit de signo ha cambiado si no coincide con el que ha salido
+        ROL | RCL | SHL => (result & msb != 0) != carry,
+        SETMO => false,
+        // A la derecha: los dos bits más altos del resultado son distintos
+        _ => (result & msb != 0) != (result & (msb >> 1) != 0),
+    };
+    if overflow {
+        flags |= FLAG_OF;
+    }
+    if operation >= SHL {
+        flags &= !FLAG_AF;
+        actualizar_flags_szp(&mut flags, result, es_byte);
+    }
+    (result, flags)
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -131,13 +201,20 @@ mod tests {
         assert_eq!(carry, false);
         assert_eq!(aux, false);
 
-        // Test con overflow y carry
+        // Test con carry: -56 + 100 = 44 no desborda en complemento a 2
         let (result, overflow, carry, aux) = add_8bit_complemento_a2(200, 100);
         assert_eq!(result, 44); // 300 % 256
-        assert_eq!(overflow, true);
+        assert_eq!(overflow, false);
         assert_eq!(carry, true);
         assert_eq!(aux, false);
 
+        // Test con overflow: 100 + 100 = 200 (-56)
+        let (result, overflow, carry, aux) = add_8bit_complemento_a2(100, 100);
+        assert_eq!(result, 200);
+        assert_eq!(overflow, true);
+        assert_eq!(carry, false);
+        assert_eq!(aux, false);
+
         // Test con aux
         let (result, overflow, carry, aux) = add_8bit_complemento_a2(0x0F, 0x01);
         assert_eq!(result, 0x10);
@@ -155,9 +232,16 @@ mod tests {
         assert_eq!(carry, false);
         assert_eq!(aux, false);
 
-        // Test con overflow y carry
+        // Test con carry: -25536 + 30000 = 4464 no desborda en complemento a 2
         let (result, overflow, carry, aux) = add_16bit_complemento_a2(40000, 30000);
         assert_eq!(result, 4464); // 70000 % 65536
+        assert_eq!(overflow, false);
+        assert_eq!(carry, true);
+        assert_eq!(aux, false);
+
+        // Test con overflow y carry: -32768 + -32768
+        let (result, overflow, carry, aux) = add_16bit_complemento_a2(0x8000, 0x8000);
+        assert_eq!(result, 0);
         assert_eq!(overflow, true);
         assert_eq!(carry, true);
         assert_eq!(aux, false);
@@ -169,4 +253,178 @@ mod tests {
         assert_eq!(carry, false);
         assert_eq!(aux, true);
     }
+
+    #[test]
+    fn test_adc_complemento_a2() {
+        // El carry de entrada se suma también al nibble bajo
+        let (result, overflow, carry, aux) = adc_8bit_complemento_a2(0x0E, 0x01, true);
+        assert_eq!(result, 0x10);
+        assert_eq!(overflow, false);
+        assert_eq!(carry, false);
+        assert_eq!(aux, true);
+
+        // 0x7F + 0x00 + 1 desborda
+        let (result, overflow, carry, _) = adc_8bit_complemento_a2(0x7F, 0x00, true);
+        assert_eq!(result, 0x80);
+        assert_eq!(overflow, true);
+        assert_eq!(carry, false);
+
+        // 0xFFFF + 0x0000 + 1 da carry y no overflow
+        let (result, overflow, carry, aux) = adc_16bit_complemento_a2(0xFFFF, 0x0000, true);
+        assert_eq!(result, 0);
+        assert_eq!(overflow, false);
+        assert_eq!(carry, true);
+        assert_eq!(aux, true);
+    }
+
+    #[test]
+    fn test_sub_8bit_complemento_a2() {
+        // Test normal sin overflow, carry o aux
+        let (result, overflow, carry, aux) = sub_8bit_complemento_a2(0x35, 0x12, false);
+        assert_eq!(result, 0x23);
+        assert_eq!(overflow, false);
+        assert_eq!(carry, false);
+        assert_eq!(aux, false);
+
+        // Test con préstamo: 0 - 1
+        let (result, overflow, carry, aux) = sub_8bit_complemento_a2(0x00, 0x01, false);
+        assert_eq!(result, 0xFF);
+        assert_eq!(overflow, false);
+        assert_eq!(carry, true);
+        assert_eq!(aux, true);
+
+        // Test con overflow: -128 - 1
+        let (result, overflow, carry, aux) = sub_8bit_complemento_a2(0x80, 0x01, false);
+        assert_eq!(result, 0x7F);
+        assert_eq!(overflow, true);
+        assert_eq!(carry, false);
+        assert_eq!(aux, true);
+
+        // Test con borrow de entrada (SBB): 0x10 - 0x0F - 1
+        let (result, overflow, carry, aux) = sub_8bit_complemento_a2(0x10, 0x0F, true);
+        assert_eq!(result, 0x00);
+        assert_eq!(overflow, false);
+        assert_eq!(carry, false);
+        assert_eq!(aux, true);
+
+        // El borrow de entrada también puede provocar el carry: 0x05 - 0x05 - 1
+        let (result, _, carry, _) = sub_8bit_complemento_a2(0x05, 0x05, true);
+        assert_eq!(result, 0xFF);
+        assert_eq!(carry, true);
+    }
+
+    #[test]
+    fn test_sub_16bit_complemento_a2() {
+        let (result, overflow, carry, aux) = sub_16bit_complemento_a2(7500, 2500, false);
+        assert_eq!(result, 5000);
+        assert_eq!(overflow, false);
+        assert_eq!(carry, false);
+        assert_eq!(aux, false);
+
+        // Test con overflow: -32768 - 1
+        let (result, overflow, carry, aux) = sub_16bit_complemento_a2(0x8000, 0x0001, false);
+        assert_eq!(result, 0x7FFF);
+        assert_eq!(overflow, true);
+        assert_eq!(carry, false);
+        assert_eq!(aux, true);
+
+        // Test con préstamo y borrow de entrada
+        let (result, overflow, carry, aux) = sub_16bit_complemento_a2(0x0001, 0x0001, true);
+        assert_eq!(result, 0xFFFF);
+        assert_eq!(overflow, false);
+        assert_eq!(carry, true);
+        assert_eq!(aux, true);
+    }
+
+    #[test]
+    fn test_actualizar_flags_sub() {
+        let mut flags = FLAG_IF;
+        // Un resultado de byte negativo debe activar SF aunque el word no lo sea
+        actualizar_flags_sub(&mut flags, 0x00FF, true, false, true, true);
+        assert_eq!(flags, FLAG_IF | FLAG_SF | FLAG_PF | FLAG_CF | FLAG_AF);
+
+        actualizar_flags_sub(&mut flags, 0x0000, false, false, false, false);
+        assert_eq!(flags, FLAG_IF | FLAG_ZF | FLAG_PF);
+
+        actualizar_flags_sub(&mut flags, 0x7F01, false, true, false, false);
+        assert_eq!(flags, FLAG_IF | FLAG_OF);
+    }
+
+    #[test]
+    fn test_actualizar_flags_logic() {
+        let mut flags = FLAG_IF | FLAG_CF | FLAG_OF | FLAG_AF;
+        actualizar_flags_logic(&mut flags, 0x0080, true);
+        assert_eq!(flags, FLAG_IF | FLAG_SF);
+
+        actualizar_flags_logic(&mut flags, 0x0080, false);
+        assert_eq!(flags, FLAG_IF);
+
+        actualizar_flags_logic(&mut flags, 0x0000, false);
+        assert_eq!(flags, FLAG_IF | FLAG_ZF | FLAG_PF);
+    }
+
+    #[test]
+    fn test_shift_rotate_un_bit() {
+        // SHL 0x81: sale un 1 y el signo cambia
+        let (result, flags) = shift_rotate(SHL, 0x81, 1, true, 0);
+        assert_eq!(result, 0x02);
+        assert_eq!(flags, FLAG_CF | FLAG_OF);
+
+        // SHR 0x80: OF es el bit de signo original
+        let (result, flags) = shift_rotate(SHR, 0x80, 1, true, 0);
+        assert_eq!(result, 0x40);
+        assert_eq!(flags, FLAG_OF);
+
+        // SAR conserva el signo y nunca desborda
+        let (result, flags) = shift_rotate(SAR, 0x8001, 1, false, 0);
+        assert_eq!(result, 0xC000);
+        assert_eq!(flags, FLAG_CF | FLAG_SF | FLAG_PF);
+
+        // ROL y ROR solo tocan CF y OF
+        let (result, flags) = shift_rotate(ROL, 0x80, 1, true, FLAG_ZF);
+        assert_eq!(result, 0x01);
+        assert_eq!(flags, FLAG_ZF | FLAG_CF | FLAG_OF);
+        let (result, flags) = shift_rotate(ROR, 0x0001, 1, false, 0);
+        assert_eq!(result, 0x8000);
+        assert_eq!(flags, FLAG_CF | FLAG_OF);
+
+        // RCL y RCR pasan por el carry
+        let (result, flags) = shift_rotate(RCL, 0x00, 1, true, FLAG_CF);
+        assert_eq!(result, 0x01);
+        assert_eq!(flags, 0);
+        let (result, flags) = shift_rotate(RCR, 0x01, 1, true, 0);
+        assert_eq!(result, 0x00);
+        assert_eq!(flags, FLAG_CF);
+    }
+
+    #[test]
+    fn test_shift_rotate_contador() {
+        // El contador no se enmascara: 17 rotaciones RCL de 16 bits dejan el valor igual
+        let (result, flags) = shift_rotate(RCL, 0x1234, 17, false, 0);
+        assert_eq!(result, 0x1234);
+        assert_eq!(flags & FLAG_CF, 0);
+
+        // 255 desplazamientos dejan el operando a 0
+        let (result, flags) = shift_rotate(SHL, 0xFFFF, 255, false, 0);
+        assert_eq!(result, 0);
+        assert_eq!(flags & (FLAG_ZF | FLAG_CF), FLAG_ZF);
+
+        // Contador 0 no modifica nada
+        let (result, flags) = shift_rotate(SHR, 0x00FF, 0, true, FLAG_CF | FLAG_AF);
+        assert_eq!(result, 0x00FF);
+        assert_eq!(flags, FLAG_CF | FLAG_AF);
+
+        // ROR 4 de un byte intercambia los nibbles
+        let (result, _) = shift_rotate(ROR, 0x12, 4, true, 0);
+        assert_eq!(result, 0x21);
+    }
+
+    #[test]
+    fn test_setmo() {
+        let (result, flags) = shift_rotate(SETMO, 0x1234, 1, false, FLAG_CF | FLAG_OF | FLAG_AF);
+        assert_eq!(result, 0xFFFF);
+        assert_eq!(flags, FLAG_SF | FLAG_PF);
+        let (result, _) = shift_rotate(SETMO, 0x00, 3, true, 0);
+        assert_eq!(result, 0xFF);
+    }
 }
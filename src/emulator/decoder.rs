@@ -0,0 +1,329 @@
+use crate::emulator::opcodes::{Entry, OPCODE_TABLE};
+
+//Un segmento lleno de prefijos no debe dejar colgado al decodificador
+const MAX_PREFIXES: u16 = 0xFFFE;
+
+//Tamaño de un operando. Dword solo aparece en los punteros lejanos segmento:offset en memoria
+//(CALL y JMP far indirectos, LDS y LES)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandSize {
+    Byte,
+    Word,
+    Dword,
+}
+
+//Operando de una instrucción ya decodificado
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    None,
+    //Registro de propósito general por su indice (AX..DI o AL..BH según el tamaño)
+    Register { index: u8, size: OperandSize },
+    //Registro de segmento: 00 ES  01 CS  10 SS  11 DS
+    Segment(u8),
+    //Operando en memoria. base es el campo r/m del ModRM (None en las direcciones directas), el desplazamiento
+    //ya está extendido con signo y segment es el indice del segmento, el del prefijo o el que toca por defecto
+    Memory { size: OperandSize, base: Option<u8>, displacement: u16, segment: u8 },
+    Immediate { value: u16, size: OperandSize },
+    //Desplazamiento de un salto relativo, se suma al IP de la instrucción siguiente
+    Relative(u16),
+    //Dirección lejana inmediata segmento:offset (CALL y JMP far directos)
+    Far { segment: u16, offset: u16 },
+}
+
+//Prefijos que preceden al opcode. count es el número de bytes de prefijo, el 8086 admite repetidos
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Prefixes {
+    pub segment: Option<u8>,
+    pub rep: Option<u8>,
+    pub lock: bool,
+    pub count: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Aaa, Aad, Aam, Aas, Adc, Add, And, Call, CallFar, Cbw, Clc, Cld, Cli, Cmc, Cmp, Cmpsb, Cmpsw, Cwd,
+    Daa, Das, Dec, Div, Esc, Hlt, Idiv, Imul, In, Inc, Int, Int3, Into, Iret,
+    Jo, Jno, Jb, Jae, Je, Jne, Jbe, Ja, Js, Jns, Jp, Jnp, Jl, Jge, Jle, Jg, Jcxz, Jmp, JmpFar,
+    Lahf, Lds, Lea, Les, Lodsb, Lodsw, Loop, Loopz, Loopnz, Mov, Movsb, Movsw, Mul, Neg, Nop, Not,
+    Or, Out, Pop, Popf, Push, Pushf, Rcl, Rcr, Ret, Retf, Rol, Ror, Sahf, Sar, Sbb, Scasb, Scasw,
+    Setmo, Shl, Shr, Stc, Std, Sti, Stosb, Stosw, Sub, Test, Wait, Xchg, Xlat, Xor,
+    //Opcode o extensión del ModRM que no existe en el 8086
+    Invalid,
+}
+
+impl Mnemonic {
+    //Nombre en minúsculas como lo escriben los ensambladores. CALL y JMP far se escriben como call y jmp
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mnemonic::Aaa => "aaa", Mnemonic::Aad => "aad", Mnemonic::Aam => "aam", Mnemonic::Aas => "aas",
+            Mnemonic::Adc => "adc", Mnemonic::Add => "add", Mnemonic::And => "and", Mnemonic::Call => "call",
+            Mnemonic::CallFar => "call", Mnemonic::Cbw => "cbw", Mnemonic::Clc => "clc", Mnemonic::Cld => "cld",
+            Mnemonic::Cli => "cli", Mnemonic::Cmc => "cmc", Mnemonic::Cmp => "cmp", Mnemonic::Cmpsb => "cmpsb",
+            Mnemonic::Cmpsw => "cmpsw", Mnemonic::Cwd => "cwd", Mnemonic::Daa => "daa", Mnemonic::Das => "das",
+            Mnemonic::Dec => "dec", Mnemonic::Div => "div", Mnemonic::Esc => "esc", Mnemonic::Hlt => "hlt",
+            Mnemonic::Idiv => "idiv", Mnemonic::Imul => "imul", Mnemonic::In => "in", Mnemonic::Inc => "inc",
+            Mnemonic::Int => "int", Mnemonic::Int3 => "int3", Mnemonic::Into => "into", Mnemonic::Iret => "iret",
+            Mnemonic::Jo => "jo", Mnemonic::Jno => "jno", Mnemonic::Jb => "jb", Mnemonic::Jae => "jae",
+            Mnemonic::Je => "je", Mnemonic::Jne => "jne", Mnemonic::Jbe => "jbe", Mnemonic::Ja => "ja",
+            Mnemonic::Js => "js", Mnemonic::Jns => "jns", Mnemonic::Jp => "jp", Mnemonic::Jnp => "jnp",
+            Mnemonic::Jl => "jl", Mnemonic::Jge => "jge", Mnemonic::Jle => "jle", Mnemonic::Jg => "jg",
+            Mnemonic::Jcxz => "jcxz", Mnemonic::Jmp => "jmp", Mnemonic::JmpFar => "jmp", Mnemonic::Lahf => "lahf",
+            Mnemonic::Lds => "lds", Mnemonic::Lea => "lea", Mnemonic::Les => "les", Mnemonic::Lodsb => "lodsb",
+            Mnemonic::Lodsw => "lodsw", Mnemonic::Loop => "loop", Mnemonic::Loopz => "loopz", Mnemonic::Loopnz => "loopnz",
+            Mnemonic::Mov => "mov", Mnemonic::Movsb => "movsb", Mnemonic::Movsw => "movsw", Mnemonic::Mul => "mul",
+            Mnemonic::Neg => "neg", Mnemonic::Nop => "nop", Mnemonic::Not => "not", Mnemonic::Or => "or",
+            Mnemonic::Out => "out", Mnemonic::Pop => "pop", Mnemonic::Popf => "popf", Mnemonic::Push => "push",
+            Mnemonic::Pushf => "pushf", Mnemonic::Rcl => "rcl", Mnemonic::Rcr => "rcr", Mnemonic::Ret => "ret",
+            Mnemonic::Retf => "retf", Mnemonic::Rol => "rol", Mnemonic::Ror => "ror", Mnemonic::Sahf => "sahf",
+            Mnemonic::Sar => "sar", Mnemonic::Sbb => "sbb", Mnemonic::Scasb => "scasb", Mnemonic::Scasw => "scasw",
+            Mnemonic::Setmo => "setmo", Mnemonic::Shl => "shl", Mnemonic::Shr => "shr", Mnemonic::Stc => "stc",
+            Mnemonic::Std => "std", Mnemonic::Sti => "sti", Mnemonic::Stosb => "stosb", Mnemonic::Stosw => "stosw",
+            Mnemonic::Sub => "sub", Mnemonic::Test => "test", Mnemonic::Wait => "wait", Mnemonic::Xchg => "xchg",
+            Mnemonic::Xlat => "xlatb", Mnemonic::Xor => "xor", Mnemonic::Invalid => "db",
+        }
+    }
+}
+
+//Forma de cada operando en la tabla de opcodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pattern {
+    None,
+    //r/m del ModRM de 8 y 16 bits, y puntero lejano en memoria
+    Eb, Ew, Ep,
+    //Registro del campo reg del ModRM y registro de segmento del campo reg
+    Gb, Gw, Sw,
+    //Inmediatos de 8 y 16 bits, Is es un inmediato de 8 bits extendido con signo a 16
+    Ib, Iw, Is,
+    //Desplazamientos relativos de 8 y 16 bits
+    Rb, Rw,
+    //Dirección lejana inmediata segmento:offset
+    Ap,
+    //Dirección directa de 16 bits (MOV con el acumulador)
+    Ob, Ow,
+    //Registros fijos
+    Al, Ax, Cl, Dx,
+    //Registro en los 3 bits bajos del opcode y segmento en los bits 3-4
+    Zb, Zw, Zs,
+    //Constante 1 de las rotaciones
+    One,
+    //Código de operación del coprocesador en ESC, bits bajos del opcode y campo reg
+    Cop,
+}
+
+impl Pattern {
+    fn uses_modrm(&self) -> bool {
+        matches!(self, Pattern::Eb | Pattern::Ew | Pattern::Ep | Pattern::Gb | Pattern::Gw | Pattern::Sw | Pattern::Cop)
+    }
+}
+
+//Instrucción decodificada. length incluye los prefijos
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub prefixes: Prefixes,
+    pub opcode: u8,
+    pub modrm: Option<u8>,
+    pub mnemonic: Mnemonic,
+    pub operands: [Operand; 2],
+    pub length: u16,
+}
+
+impl Instruction {
+    //Campo reg del ModRM, en los grupos es la extensión del opcode
+    pub fn reg_field(&self) -> u8 {
+        self.modrm.map_or(0, |modrm| (modrm >> 3) & 0x07)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.mnemonic != Mnemonic::Invalid
+    }
+}
+
+//Lee los bytes de la instrucción contando cuántos se han consumido
+struct Reader<F> {
+    next: F,
+    length: u16,
+}
+
+impl<F: FnMut() -> u8> Reader<F> {
+    fn byte(&mut self) -> u8 {
+        self.length = self.length.wrapping_add(1);
+        (self.next)()
+    }
+
+    fn word(&mut self) -> u16 {
+        let low_byte = self.byte();
+        let high_byte = self.byte();
+        (high_byte as u16) << 8 | low_byte as u16
+    }
+}
+
+//Decodifica la instrucción que empieza en CS:IP. IP da la vuelta dentro del segmento como al ejecutar,
+//y lo que quede fuera de memory se lee como 0
+pub fn decode(memory: &[u8], cs: u16, ip: u16) -> Instruction {
+    let mut offset = ip;
+    decode_from(|| {
+        let address = (((cs as usize) << 4) + offset as usize) & 0xFFFFF;
+        offset = offset.wrapping_add(1);
+        memory.get(address).copied().unwrap_or(0)
+    })
+}
+
+//Decodifica una instrucción pidiendo sus bytes de uno en uno a next, empezando por el primer prefijo u opcode
+pub fn decode_from<F: FnMut() -> u8>(next: F) -> Instruction {
+    let mut reader = Reader { next, length: 0 };
+    let mut prefixes = Prefixes::default();
+    let mut opcode = reader.byte();
+    while matches!(OPCODE_TABLE[opcode as usize], Entry::Prefix) && prefixes.count < MAX_PREFIXES {
+        match opcode {
+            0x26 | 0x2E | 0x36 | 0x3E => prefixes.segment = Some((opcode >> 3) & 0x03),
+            0xF2 | 0xF3 => prefixes.rep = Some(opcode),
+            _ => prefixes.lock = true,
+        }
+        prefixes.count += 1;
+        opcode = reader.byte();
+    }
+    let (mnemonic, first, second, modrm) = match OPCODE_TABLE[opcode as usize] {
+        Entry::Op(mnemonic, first, second) => {
+            let modrm = if first.uses_modrm() || second.uses_modrm() { Some(reader.byte()) } else { None };
+            (mnemonic, first, second, modrm)
+        },
+        Entry::Group(table) => {
+            let modrm = reader.byte();
+            let (mnemonic, first, second) = table[((modrm >> 3) & 0x07) as usize];
+            (mnemonic, first, second, Some(modrm))
+        },
+        Entry::Prefix => (Mnemonic::Invalid, Pattern::None, Pattern::None, None),
+    };
+    //El desplazamiento del ModRM va siempre antes que el inmediato, y el r/m es el primer operando que lee bytes
+    let first = decode_operand(&mut reader, first, opcode, modrm, prefixes.segment);
+    let second = decode_operand(&mut reader, second, opcode, modrm, prefixes.segment);
+    Instruction { prefixes, opcode, modrm, mnemonic, operands: [first, second], length: reader.length }
+}
+
+fn decode_operand<F: FnMut() -> u8>(reader: &mut Reader<F>, pattern: Pattern, opcode: u8, modrm: Option<u8>, segment: Option<u8>) -> Operand {
+    let modrm = modrm.unwrap_or(0);
+    let reg_field = (modrm >> 3) & 0x07;
+    match pattern {
+        Pattern::None => Operand::None,
+        Pattern::Eb => decode_rm(reader, modrm, OperandSize::Byte, segment),
+        Pattern::Ew => decode_rm(reader, modrm, OperandSize::Word, segment),
+        Pattern::Ep => decode_rm(reader, modrm, OperandSize::Dword, segment),
+        Pattern::Gb => Operand::Register { index: reg_field, size: OperandSize::Byte },
+        Pattern::Gw => Operand::Register { index: reg_field, size: OperandSize::Word },
+        //El 8086 solo mira los 2 bits bajos del campo reg para elegir el segmento
+        Pattern::Sw => Operand::Segment(reg_field & 0x03),
+        Pattern::Ib => Operand::Immediate { value: reader.byte() as u16, size: OperandSize::Byte },
+        Pattern::Iw => Operand::Immediate { value: reader.word(), size: OperandSize::Word },
+        Pattern::Is => Operand::Immediate { value: reader.byte() as i8 as u16, size: OperandSize::Word },
+        Pattern::Rb => Operand::Relative(reader.byte() as i8 as u16),
+        Pattern::Rw => Operand::Relative(reader.word()),
+        Pattern::Ap => {
+            let offset = reader.word();
+            let segment = reader.word();
+            Operand::Far { segment, offset }
+        },
+        Pattern::Ob | Pattern::Ow => {
+            let size = if pattern == Pattern::Ob { OperandSize::Byte } else { OperandSize::Word };
+            Operand::Memory { size, base: None, displacement: reader.word(), segment: segment.unwrap_or(0b11) }
+        },
+        Pattern::Al => Operand::Register { index: 0, size: OperandSize::Byte },
+        Pattern::Ax => Operand::Register { index: 0, size: OperandSize::Word },
+        Pattern::Cl => Operand::Register { index: 1, size: OperandSize::Byte },
+        Pattern::Dx => Operand::Register { index: 2, size: OperandSize::Word },
+        Pattern::Zb => Operand::Register { index: opcode & 0x07, size: OperandSize::Byte },
+        Pattern::Zw => Operand::Register { index: opcode & 0x07, size: OperandSize::Word },
+        Pattern::Zs => Operand::Segment((opcode >> 3) & 0x03),
+        Pattern::One => Operand::Immediate { value: 1, size: OperandSize::Byte },
+        Pattern::Cop => Operand::Immediate { value: ((opcode & 0x07) << 3 | reg_field) as u16, size: OperandSize::Byte },
+    }
+}
+
+//Operando r/m del ModRM, registro con mod 11 o memoria consumiendo el desplazamiento que haya detrás.
+//Los modos basados en BP usan SS por defecto y el resto DS, salvo que haya prefijo de segmento
+fn decode_rm<F: FnMut() -> u8>(reader: &mut Reader<F>, modrm: u8, size: OperandSize, segment: Option<u8>) -> Operand {
+    let mod_field = modrm >> 6;
+    let rm_field = modrm & 0x07;
+    if mod_field == 0x03 {
+        let size = if size == OperandSize::Byte { OperandSize::Byte } else { OperandSize::Word };
+        return Operand::Register { index: rm_field, size };
+    }
+    //mod 00 con r/m 110 no usa BP, es una dirección directa de 16 bits
+    let direct = mod_field == 0x00 && rm_field == 0b110;
+    let displacement = match mod_field {
+        0x00 if direct => reader.word(),
+        0x00 => 0,
+        //El desplazamiento de 8 bits se extiende con signo
+        0x01 => reader.byte() as i8 as u16,
+        _ => reader.word(),
+    };
+    let uses_bp = matches!(rm_field, 0b010 | 0b011) || (rm_field == 0b110 && !direct);
+    let default_segment = if uses_bp { 0b10 } else { 0b11 };
+    Operand::Memory {
+        size,
+        base: if direct { None } else { Some(rm_field) },
+        displacement,
+        segment: segment.unwrap_or(default_segment),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decodifica_modrm_con_desplazamiento() {
+        //ES: ADD [BP+DI-2],AX
+        let instruction = decode(&[0x26, 0x01, 0x43, 0xFE], 0, 0);
+        assert_eq!(instruction.mnemonic, Mnemonic::Add);
+        assert_eq!(instruction.prefixes.segment, Some(0));
+        assert_eq!(instruction.length, 4);
+        assert_eq!(instruction.operands, [
+            Operand::Memory { size: OperandSize::Word, base: Some(0b011), displacement: 0xFFFE, segment: 0 },
+            Operand::Register { index: 0, size: OperandSize::Word },
+        ]);
+        //MOV AX,[BP] usa SS y [0x1234] es una dirección directa en DS
+        let instruction = decode(&[0x8B, 0x46, 0x00], 0, 0);
+        assert!(matches!(instruction.operands[1], Operand::Memory { base: Some(0b110), segment: 0b10, .. }));
+        let instruction = decode(&[0x8B, 0x06, 0x34, 0x12], 0, 0);
+        assert!(matches!(instruction.operands[1], Operand::Memory { base: None, displacement: 0x1234, segment: 0b11, .. }));
+    }
+
+    #[test]
+    fn test_decodifica_grupos_e_inmediatos() {
+        //ADD WORD [BX+SI+0x10],-1 con el inmediato de 83 extendido con signo
+        let instruction = decode(&[0x83, 0x40, 0x10, 0xFF], 0, 0);
+        assert_eq!(instruction.mnemonic, Mnemonic::Add);
+        assert_eq!(instruction.length, 4);
+        assert_eq!(instruction.operands[1], Operand::Immediate { value: 0xFFFF, size: OperandSize::Word });
+        //TEST lleva inmediato en el grupo F6, NOT no
+        assert_eq!(decode(&[0xF6, 0xC3, 0x0F], 0, 0).length, 3);
+        assert_eq!(decode(&[0xF6, 0xD3], 0, 0).mnemonic, Mnemonic::Not);
+        //FE /7 no existe pero se consume su desplazamiento
+        let instruction = decode(&[0xFE, 0xBF, 0x34, 0x12], 0, 0);
+        assert!(!instruction.is_valid());
+        assert_eq!(instruction.length, 4);
+    }
+
+    #[test]
+    fn test_decodifica_saltos_y_prefijos() {
+        let instruction = decode(&[0xEB, 0xFE], 0, 0);
+        assert_eq!((instruction.mnemonic, instruction.operands[0]), (Mnemonic::Jmp, Operand::Relative(0xFFFE)));
+        let instruction = decode(&[0xEA, 0x00, 0x01, 0x00, 0x07], 0, 0);
+        assert_eq!(instruction.operands[0], Operand::Far { segment: 0x0700, offset: 0x0100 });
+        //REP CS: MOVSW
+        let instruction = decode(&[0xF3, 0x2E, 0xA5], 0, 0);
+        assert_eq!(instruction.mnemonic, Mnemonic::Movsw);
+        assert_eq!(instruction.prefixes, Prefixes { segment: Some(1), rep: Some(0xF3), lock: false, count: 2 });
+        assert_eq!(instruction.length, 3);
+    }
+
+    #[test]
+    fn test_decode_ip_da_la_vuelta() {
+        //MOV AL,0x42 empezando en el último byte del segmento
+        let mut memory = vec![0; 0x30000];
+        memory[0x2FFFF] = 0xB0;
+        memory[0x20000] = 0x42;
+        let instruction = decode(&memory, 0x2000, 0xFFFF);
+        assert_eq!(instruction.operands[1], Operand::Immediate { value: 0x42, size: OperandSize::Byte });
+    }
+}
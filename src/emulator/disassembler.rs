@@ -0,0 +1,228 @@
+use std::fmt::Write;
+use crate::emulator::decoder::{decode, Instruction, Mnemonic, Operand, OperandSize};
+use crate::emulator::registers::{BASE_ADDRESS_NAMES, REGISTER_NAMES_B, REGISTER_NAMES_W};
+
+const SEGMENT_NAMES: [&str; 4] = ["es", "cs", "ss", "ds"];
+
+//Sintaxis del desensamblado. Fasm escribe los números como 0x11 y el segmento dentro de los corchetes
+//(word [es:bx+0x02]), Intel los escribe como 11h y usa ptr (word ptr es:[bx+02h])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    #[default]
+    Fasm,
+    Intel,
+}
+
+//Desensambla la instrucción de CS:IP con sintaxis FASM, devuelve el texto y su longitud en bytes
+pub fn disassemble(memory: &[u8], cs: u16, ip: u16) -> (String, u16) {
+    disassemble_with(memory, cs, ip, Syntax::Fasm)
+}
+
+pub fn disassemble_with(memory: &[u8], cs: u16, ip: u16, syntax: Syntax) -> (String, u16) {
+    let instruction = decode(memory, cs, ip);
+    let text = if instruction.is_valid() {
+        format_instruction(&instruction, ip, syntax)
+    }else{
+        //Los bytes que no forman una instrucción del 8086 se muestran como datos
+        let bytes: Vec<String> = instruction_bytes(memory, cs, ip, instruction.length)
+            .iter()
+            .map(|byte| number(*byte as u16, OperandSize::Byte, syntax))
+            .collect();
+        format!("db {}", bytes.join(","))
+    };
+    (text, instruction.length)
+}
+
+//Listado de un programa cargado en el offset origin con el formato de los .list de emu8086:
+//dirección, bytes de la instrucción e instrucción, una por línea
+pub fn listing(program: &[u8], origin: u16, syntax: Syntax) -> String {
+    let mut memory = vec![0; origin as usize];
+    memory.extend_from_slice(program);
+    let mut output = String::new();
+    let mut ip = origin as usize;
+    while ip < memory.len() && ip <= 0xFFFF {
+        let (text, length) = disassemble_with(&memory, 0, ip as u16, syntax);
+        let end = (ip + length as usize).min(memory.len());
+        let bytes: Vec<String> = memory[ip..end].iter().map(|byte| format!("{:02X}", byte)).collect();
+        let _ = writeln!(output, "{:04X}: {:<38}{}", ip, bytes.join(" "), text);
+        ip += length as usize;
+    }
+    output
+}
+
+fn instruction_bytes(memory: &[u8], cs: u16, ip: u16, length: u16) -> Vec<u8> {
+    (0..length)
+        .map(|i| {
+            let address = (((cs as usize) << 4) + ip.wrapping_add(i) as usize) & 0xFFFFF;
+            memory.get(address).copied().unwrap_or(0)
+        })
+        .collect()
+}
+
+fn format_instruction(instruction: &Instruction, ip: u16, syntax: Syntax) -> String {
+    let mut text = String::new();
+    let prefixes = instruction.prefixes;
+    if prefixes.lock {
+        text.push_str("lock ");
+    }
+    match prefixes.rep {
+        Some(0xF2) => text.push_str("repne "),
+        Some(_) if matches!(instruction.mnemonic, Mnemonic::Cmpsb | Mnemonic::Cmpsw | Mnemonic::Scasb | Mnemonic::Scasw) => text.push_str("repe "),
+        Some(_) => text.push_str("rep "),
+        None => {},
+    }
+    //Sin operando en memoria el prefijo de segmento se escribe delante, como en cs movsb
+    let has_memory = instruction.operands.iter().any(|operand| matches!(operand, Operand::Memory { .. }));
+    if let (Some(segment), false) = (prefixes.segment, has_memory) {
+        text.push_str(SEGMENT_NAMES[(segment & 0x03) as usize]);
+        text.push(' ');
+    }
+    text.push_str(instruction.mnemonic.name());
+    let operands: Vec<String> = instruction.operands.iter()
+        .filter(|operand| **operand != Operand::None)
+        .map(|operand| format_operand(instruction, *operand, ip, syntax))
+        .collect();
+    //AAM y AAD con la base 10 de siempre se escriben sin operando
+    let default_base = matches!(instruction.mnemonic, Mnemonic::Aam | Mnemonic::Aad)
+        && instruction.operands[0] == Operand::Immediate { value: 0x0A, size: OperandSize::Byte };
+    if !operands.is_empty() && !default_base {
+        text.push(' ');
+        text.push_str(&operands.join(","));
+    }
+    text
+}
+
+fn format_operand(instruction: &Instruction, operand: Operand, ip: u16, syntax: Syntax) -> String {
+    match operand {
+        Operand::None => String::new(),
+        Operand::Register { index, size: OperandSize::Byte } => REGISTER_NAMES_B[(index & 0x07) as usize].to_lowercase(),
+        Operand::Register { index, .. } => REGISTER_NAMES_W[(index & 0x07) as usize].to_lowercase(),
+        Operand::Segment(index) => SEGMENT_NAMES[(index & 0x03) as usize].to_string(),
+        //Las rotaciones de D0 y D1 llevan el 1 implícito
+        Operand::Immediate { value: 1, .. } if matches!(instruction.opcode, 0xD0 | 0xD1) => "1".to_string(),
+        Operand::Immediate { value, size } => number(value, size, syntax),
+        Operand::Relative(displacement) => {
+            let target = ip.wrapping_add(instruction.length).wrapping_add(displacement);
+            number(target, OperandSize::Word, syntax)
+        },
+        Operand::Far { segment, offset } => format!("{}:{}", number(segment, OperandSize::Word, syntax), number(offset, OperandSize::Word, syntax)),
+        Operand::Memory { size, base, displacement, segment } => {
+            let mut address = String::new();
+            match base {
+                Some(rm_field) => {
+                    address.push_str(&BASE_ADDRESS_NAMES[(rm_field & 0x07) as usize].to_lowercase());
+                    let signed = displacement as i16;
+                    if signed != 0 {
+                        address.push(if signed < 0 { '-' } else { '+' });
+                        let magnitude = signed.unsigned_abs();
+                        let size = if magnitude <= 0x80 { OperandSize::Byte } else { OperandSize::Word };
+                        address.push_str(&number(magnitude, size, syntax));
+                    }
+                },
+                None => address.push_str(&number(displacement, OperandSize::Word, syntax)),
+            }
+            let segment = instruction.prefixes.segment.map(|_| SEGMENT_NAMES[(segment & 0x03) as usize]);
+            let size = memory_size(instruction, size);
+            match syntax {
+                Syntax::Fasm => format!(
+                    "{}[{}{}]",
+                    size.map(|size| format!("{} ", size)).unwrap_or_default(),
+                    segment.map(|segment| format!("{}:", segment)).unwrap_or_default(),
+                    address
+                ),
+                Syntax::Intel => format!(
+                    "{}{}[{}]",
+                    size.map(|size| format!("{} ptr ", size)).unwrap_or_default(),
+                    segment.map(|segment| format!("{}:", segment)).unwrap_or_default(),
+                    address
+                ),
+            }
+        },
+    }
+}
+
+//Tamaño que hay que escribir delante de un operando en memoria, solo cuando el otro operando no lo deja claro.
+//Los punteros lejanos lo llevan siempre para distinguir CALL y JMP far de los near
+fn memory_size(instruction: &Instruction, size: OperandSize) -> Option<&'static str> {
+    if size == OperandSize::Dword && matches!(instruction.mnemonic, Mnemonic::CallFar | Mnemonic::JmpFar) {
+        return Some("dword");
+    }
+    if matches!(instruction.mnemonic, Mnemonic::Lea | Mnemonic::Lds | Mnemonic::Les | Mnemonic::Esc) {
+        return None;
+    }
+    let sized_by_register = instruction.operands.iter().any(|operand| matches!(operand, Operand::Register { .. } | Operand::Segment(_)))
+        && !matches!(instruction.opcode, 0xD2 | 0xD3);
+    match size {
+        _ if sized_by_register => None,
+        OperandSize::Byte => Some("byte"),
+        OperandSize::Word => Some("word"),
+        OperandSize::Dword => Some("dword"),
+    }
+}
+
+fn number(value: u16, size: OperandSize, syntax: Syntax) -> String {
+    let digits = if size == OperandSize::Byte && value <= 0xFF { format!("{:02X}", value) } else { format!("{:04X}", value) };
+    match syntax {
+        Syntax::Fasm => format!("0x{}", digits),
+        //En la sintaxis de Intel un número no puede empezar por letra
+        Syntax::Intel if digits.starts_with(|c: char| c.is_ascii_alphabetic()) => format!("0{}h", digits),
+        Syntax::Intel => format!("{}h", digits),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texto(bytes: &[u8]) -> String {
+        disassemble(bytes, 0, 0).0
+    }
+
+    #[test]
+    fn test_desensambla_operandos() {
+        assert_eq!(texto(&[0xB4, 0x11]), "mov ah,0x11");
+        assert_eq!(texto(&[0x8C, 0x0E, 0x00, 0x02]), "mov [0x0200],cs");
+        assert_eq!(texto(&[0x26, 0x01, 0x43, 0xFE]), "add [es:bp+di-0x02],ax");
+        assert_eq!(texto(&[0x83, 0x40, 0x10, 0xFF]), "add word [bx+si+0x10],0xFFFF");
+        assert_eq!(texto(&[0xD0, 0xE0]), "shl al,1");
+        assert_eq!(texto(&[0xD3, 0x27]), "shl word [bx],cl");
+        assert_eq!(texto(&[0xFF, 0x1F]), "call dword [bx]");
+        assert_eq!(texto(&[0xD4, 0x0A]), "aam");
+        assert_eq!(texto(&[0xF3, 0xA6]), "repe cmpsb");
+        assert_eq!(texto(&[0x2E, 0xA4]), "cs movsb");
+        assert_eq!(texto(&[0xFE, 0xFF]), "db 0xFE,0xFF");
+    }
+
+    #[test]
+    fn test_desensambla_saltos() {
+        //JMP $ y CALL a la instrucción siguiente, el destino es absoluto
+        assert_eq!(disassemble(&[0xEB, 0xFE], 0, 0), ("jmp 0x0000".to_string(), 2));
+        let mut memory = vec![0; 0x0200];
+        memory[0x0100..0x0103].copy_from_slice(&[0xE8, 0x00, 0x00]);
+        assert_eq!(disassemble(&memory, 0, 0x0100), ("call 0x0103".to_string(), 3));
+        assert_eq!(texto(&[0xEA, 0x00, 0x01, 0x00, 0x07]), "jmp 0x0700:0x0100");
+    }
+
+    #[test]
+    fn test_sintaxis_intel() {
+        assert_eq!(disassemble_with(&[0x26, 0xC7, 0x47, 0x02, 0xFF, 0x00], 0, 0, Syntax::Intel).0, "mov word ptr es:[bx+02h],00FFh");
+        assert_eq!(disassemble_with(&[0xB0, 0xAB], 0, 0, Syntax::Intel).0, "mov al,0ABh");
+    }
+
+    //El listado coincide, sin distinguir mayúsculas, con las líneas de código de los .list generados por emu8086
+    #[test]
+    fn test_listado_igual_que_emu8086() {
+        for name in ["MOV_LOW_REG", "MOV_HIGH_REG"] {
+            let program = std::fs::read(format!("./tests/mov/{}.com", name)).unwrap();
+            let reference = std::fs::read_to_string(format!("./tests/mov/{}.com.list", name)).unwrap();
+            let expected: Vec<String> = reference.lines()
+                .filter_map(|line| line.split_once("]    ").map(|(_, code)| code))
+                //Las líneas sin código y la cabecera empiezan por espacios
+                .filter(|code| !code.starts_with(' '))
+                .map(|code| code.trim_end().to_lowercase())
+                .collect();
+            let lines: Vec<String> = listing(&program, 0x0100, Syntax::Fasm).lines().map(|line| line.to_lowercase()).collect();
+            assert_eq!(lines, expected);
+        }
+    }
+}
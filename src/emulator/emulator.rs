@@ -1,11 +1,58 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Read;
-use crate::emulator::registers::Registers;
-use crate::emulator::opcodes::*;
+use crate::emulator::registers::{Registers, BASE_ADDRESS_NAMES, REGISTER_NAMES_B, REGISTER_NAMES_W};
+use crate::emulator::decoder::{decode_from, Instruction, Mnemonic, Operand};
 
 use crate::emulator::auxiliar::*;
+use crate::emulator::error::EmulatorError;
+use crate::emulator::trace::{TraceEvent, TraceLevel, TraceSink};
 const MEM_SIZE: usize = 1 << 20;
-const COM_START: usize = 0x7100;
+//Segmento del PSP de los .com y de los .exe si no se indica otro. El .com empieza en PSP:0100
+const PSP_SEGMENT: u16 = 0x0700;
+const COM_START: usize = ((PSP_SEGMENT as usize) << 4) + 0x0100;
+//Final de la memoria convencional (640 KiB), los programas no pueden reservar memoria por encima
+const MEMORY_TOP: u16 = 0xA000;
+//Tamaño mínimo de la cabecera MZ en bytes, hasta el número de overlay
+const MZ_HEADER_SIZE: usize = 0x1C;
+
+//Operando r/m ya decodificado a partir del byte ModRM
+//Register lleva el indice del registro y Memory el segmento y el offset de la dirección efectiva
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RmOperand {
+    Register(u8),
+    Memory(u16, u16),
+}
+
+//Resultado de ejecutar una instrucción con step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepOutcome {
+    //La instrucción se ha ejecutado y se puede seguir
+    Running,
+    //El procesador se ha parado con HLT
+    Halted,
+    //El programa ha terminado (INT 20h o INT 21h con AH=4Ch) con el código de salida indicado
+    Exited(u8),
+    //CS:IP ha llegado a un punto de ruptura, la instrucción de esa dirección todavía no se ha ejecutado
+    Breakpoint,
+}
+
+//Motivo por el que se ha parado run
+#[derive(Debug)]
+pub enum StopReason {
+    //Se han ejecutado todas las instrucciones permitidas
+    BudgetExhausted,
+    Halted,
+    Exited(u8),
+    Breakpoint,
+    Error(EmulatorError),
+}
+
+//Función del host que atiende una interrupción en lugar del manejador del programa.
+//Devuelve true si la ha atendido, con false se sigue con el vector de la tabla de interrupciones
+pub type InterruptHook = Box<dyn FnMut(&mut Emulator8086) -> bool>;
+
 pub struct Emulator8086 {
     // Registros
     pub registers: Registers,
@@ -13,87 +60,615 @@ pub struct Emulator8086 {
     pub memory: Vec<u8>,
     //Ciclos de espera pendientes de emular
     pub pending_cycles: u64,
+    //El programa ha terminado (INT 20h o INT 21h AH=4Ch), el bucle de ejecución debe pararse
+    pub terminated: bool,
+    //Código de salida del programa, el AL de INT 21h AH=4Ch (INT 20h sale con 0)
+    pub exit_code: u8,
+    //El procesador está parado por HLT hasta que llegue una interrupción
+    pub halted: bool,
+    //Prefijos de la instrucción en curso: segmento (indice ES/CS/SS/DS) y repetición (F2/F3)
+    segment_override: Option<u8>,
+    rep_prefix: Option<u8>,
+    //Hay una instrucción REP a medias, IP apunta a su opcode y los prefijos se conservan
+    rep_pending: bool,
+    //Tras cargar un registro de segmento con MOV o POP el 8086 no atiende interrupciones hasta terminar
+    //la instrucción siguiente, para que MOV SS / MOV SP se ejecuten juntos
+    pub interrupt_shadow: bool,
+    //Líneas de interrupción externas: NMI pendiente y petición INTR con el vector que daría el PIC
+    nmi_pending: bool,
+    intr_pending: Option<u8>,
+    //Última dirección efectiva calculada (segmento y offset), LEA/LDS/LES con mod 11 la reutilizan
+    last_effective_address: (u16, u16),
+    //Interrupciones interceptadas por el host, por vector
+    interrupt_hooks: HashMap<u8, InterruptHook>,
+    //CS:IP del primer byte (prefijos incluidos) de la instrucción en curso
+    instruction_start: (u16, u16),
+    //Destino de las trazas (sin destino no se traza nada) y nivel máximo de detalle.
+    //Va en un RefCell para poder trazar desde los métodos que solo leen el estado
+    trace_sink: RefCell<Option<Box<dyn TraceSink>>>,
+    trace_level: TraceLevel,
+    //Puntos de ruptura (CS:IP) en los que se para run
+    breakpoints: HashSet<(u16, u16)>,
+}
+
+impl Default for Emulator8086{
+    fn default()->Self{
+        Self::new()
+    }
 }
 
 impl Emulator8086{
     pub fn new()->Self{
-        Self{
+        let mut emulator = Self{
             registers: Registers::initialize(),
             memory: vec![0; MEM_SIZE],
             pending_cycles: 0,
+            terminated: false,
+            exit_code: 0,
+            halted: false,
+            segment_override: None,
+            rep_prefix: None,
+            rep_pending: false,
+            interrupt_shadow: false,
+            nmi_pending: false,
+            intr_pending: None,
+            last_effective_address: (0, 0),
+            interrupt_hooks: HashMap::new(),
+            instruction_start: (0, 0),
+            trace_sink: RefCell::new(None),
+            trace_level: TraceLevel::Instruction,
+            breakpoints: HashSet::new(),
+        };
+        //Sin DOS debajo, INT 20h e INT 21h AH=4Ch terminan el programa. El resto de funciones de INT 21h
+        //van a la tabla de interrupciones. El host puede sustituirlos por sus propios manejadores
+        emulator.set_interrupt_hook(0x20, |emulator| {
+            emulator.terminated = true;
+            emulator.exit_code = 0;
+            true
+        });
+        emulator.set_interrupt_hook(0x21, |emulator| {
+            if emulator.registers.get_high_byte(emulator.registers.ax) != 0x4C {
+                return false;
+            }
+            emulator.terminated = true;
+            emulator.exit_code = emulator.registers.get_low_byte(emulator.registers.ax);
+            true
+        });
+        emulator
+    }
+
+    //Intercepta el vector indicado: cada vez que se produzca la interrupción se llama a hook
+    pub fn set_interrupt_hook<F>(&mut self, vector: u8, hook: F)
+    where
+        F: FnMut(&mut Emulator8086) -> bool + 'static,
+    {
+        self.interrupt_hooks.insert(vector, Box::new(hook));
+    }
+
+    //Deja de interceptar el vector, la interrupción vuelve a ir al manejador de la tabla
+    pub fn remove_interrupt_hook(&mut self, vector: u8){
+        self.interrupt_hooks.remove(&vector);
+    }
+
+    //Llama al hook del vector si lo hay. Se saca del mapa mientras se ejecuta para poder pasarle el emulador
+    fn run_interrupt_hook(&mut self, vector: u8)->bool{
+        let Some(mut hook) = self.interrupt_hooks.remove(&vector) else {
+            return false;
+        };
+        let handled = hook(self);
+        //Si el hook ha instalado otro para el mismo vector se conserva el nuevo
+        self.interrupt_hooks.entry(vector).or_insert(hook);
+        handled
+    }
+
+    //Envía las trazas hasta el nivel indicado a sink
+    pub fn set_trace_sink<S: TraceSink + 'static>(&mut self, sink: S, level: TraceLevel){
+        self.trace_sink = RefCell::new(Some(Box::new(sink)));
+        self.trace_level = level;
+    }
+
+    //Desactiva las trazas
+    pub fn clear_trace_sink(&mut self){
+        self.trace_sink = RefCell::new(None);
+    }
+
+    fn trace(&self, event: TraceEvent){
+        if event.level() > self.trace_level {
+            return;
+        }
+        if let Some(sink) = self.trace_sink.borrow_mut().as_mut() {
+            sink.event(&event);
+        }
+    }
+
+    pub fn load_com(&mut self, path: & str)-> Result<(), EmulatorError> {
+        let mut archivo = File::open(path)?;
+        let mut buffer = Vec::new();
+        archivo.read_to_end(&mut buffer)?;
+        self.load_com_bytes(&buffer)
+    }
+
+    //Carga un .com ya leído y prepara el entorno como lo haría DOS: todos los segmentos apuntan al PSP,
+    //IP a 0x0100 y SP a 0xFFFE. En CS:0000 (PSP) hay un INT 20h y en la cima de la pila un 0, así que
+    //el RET final del programa salta al PSP y termina la ejecución
+    pub fn load_com_bytes(&mut self, program: &[u8])->Result<(), EmulatorError>{
+        self.check_memory()?;
+        //El .com y su PSP tienen que caber en un único segmento
+        if program.len() > 0x10000 - 0x0100 {
+            let error = std::io::Error::new(std::io::ErrorKind::InvalidData, "el programa .com no cabe en un segmento de 64 KiB");
+            return Err(EmulatorError::Load(error));
         }
+        self.reset_cpu();
+        self.registers.cs = PSP_SEGMENT;
+        self.registers.ds = PSP_SEGMENT;
+        self.registers.es = PSP_SEGMENT;
+        self.registers.ss = PSP_SEGMENT;
+        self.registers.ip = 0x0100;
+        self.registers.sp = 0xFFFE;
+        self.memory[COM_START..COM_START + program.len()].copy_from_slice(program);
+        self.build_psp(PSP_SEGMENT, MEMORY_TOP);
+        self.set_w_in_memory(self.registers.ss, self.registers.sp, 0x0000);
+        Ok(())
+    }
+
+    //Deja el procesador como recién encendido antes de cargar un programa: registros iniciales, sin
+    //HLT ni programa terminado y sin prefijos, REP a medias ni interrupciones pendientes
+    fn reset_cpu(&mut self){
+        self.registers = Registers::initialize();
+        self.terminated = false;
+        self.exit_code = 0;
+        self.halted = false;
+        self.segment_override = None;
+        self.rep_prefix = None;
+        self.rep_pending = false;
+        self.interrupt_shadow = false;
+        self.nmi_pending = false;
+        self.intr_pending = None;
     }
 
-    pub fn load_com(&mut self, path: & str)-> std::io::Result<()> {
+    pub fn load_exe(&mut self, path: &str)->Result<(), EmulatorError>{
         let mut archivo = File::open(path)?;
         let mut buffer = Vec::new();
         archivo.read_to_end(&mut buffer)?;
-        for (i, &byte) in buffer.iter().enumerate() {
-            self.memory[COM_START + i] = byte;
+        self.load_exe_bytes(&buffer, PSP_SEGMENT)
+    }
+
+    //Carga un ejecutable MZ con su PSP en load_segment, como lo haría DOS: la imagen va detrás del PSP,
+    //se le suma su segmento a cada word de la tabla de relocalización y CS:IP y SS:SP salen de la cabecera.
+    //Se reserva la memoria que pide la cabecera (como mucho hasta el final de la memoria convencional)
+    //y DS y ES apuntan al PSP
+    pub fn load_exe_bytes(&mut self, program: &[u8], load_segment: u16)->Result<(), EmulatorError>{
+        self.check_memory()?;
+        let invalid = |reason: String| Err(EmulatorError::InvalidExe(reason));
+        //El PSP (0x10 párrafos) y la imagen tienen que quedar por debajo del final de la memoria convencional
+        if load_segment as usize + 0x10 >= MEMORY_TOP as usize {
+            return invalid(format!("segmento de carga {:04X} por encima de la memoria convencional", load_segment));
+        }
+        if program.len() < MZ_HEADER_SIZE {
+            return invalid(format!("el archivo tiene {} bytes, menos que la cabecera", program.len()));
+        }
+        let word = |offset: usize| u16::from_le_bytes([program[offset], program[offset + 1]]);
+        if &program[0..2] != b"MZ" && &program[0..2] != b"ZM" {
+            return invalid("falta la firma MZ".to_string());
+        }
+        let last_page_bytes = word(0x02) as usize;
+        let pages = word(0x04) as usize;
+        let relocations = word(0x06) as usize;
+        let header_size = word(0x08) as usize * 16;
+        let min_alloc = word(0x0A) as usize;
+        let max_alloc = word(0x0C) as usize;
+        let (initial_ss, initial_sp) = (word(0x0E), word(0x10));
+        let (initial_ip, initial_cs) = (word(0x14), word(0x16));
+        let relocation_table = word(0x18) as usize;
+        //El tamaño del archivo va en páginas de 512 bytes, la última puede estar a medias
+        if last_page_bytes >= 512 {
+            return invalid(format!("bytes de la última página no válidos: {}", last_page_bytes));
+        }
+        let file_size = match (pages, last_page_bytes) {
+            (0, _) => return invalid("el archivo no tiene páginas".to_string()),
+            (pages, 0) => pages * 512,
+            (pages, bytes) => (pages - 1) * 512 + bytes,
+        };
+        if file_size > program.len() {
+            return invalid(format!("la cabecera indica {} bytes y el archivo tiene {}", file_size, program.len()));
+        }
+        if header_size < MZ_HEADER_SIZE || header_size > file_size {
+            return invalid(format!("tamaño de cabecera no válido: {} bytes", header_size));
+        }
+        if relocation_table + relocations * 4 > header_size {
+            return invalid("la tabla de relocalización se sale de la cabecera".to_string());
+        }
+        let image = &program[header_size..file_size];
+        //La imagen y la memoria mínima tienen que caber entre el PSP y el final de la memoria convencional
+        let image_segment = load_segment as usize + 0x10;
+        let image_paragraphs = image.len().div_ceil(16);
+        let available = (MEMORY_TOP as usize).saturating_sub(image_segment);
+        if image_paragraphs + min_alloc > available {
+            return invalid(format!(
+                "el programa necesita {} párrafos y solo hay {} libres",
+                0x10 + image_paragraphs + min_alloc, available + 0x10
+            ));
+        }
+        //Se comprueba toda la tabla antes de tocar la memoria
+        let mut addresses = Vec::with_capacity(relocations);
+        for entry in 0..relocations {
+            let offset = word(relocation_table + entry * 4) as usize;
+            let segment = word(relocation_table + entry * 4 + 2) as usize;
+            let address = (segment << 4) + offset;
+            if address + 2 > image.len() {
+                return invalid(format!("la relocalización {} apunta fuera de la imagen ({:04X}:{:04X})", entry, segment, offset));
+            }
+            addresses.push(address);
+        }
+        let allocated = (image_paragraphs + max_alloc.max(min_alloc)).min(available);
+        let start = image_segment << 4;
+        self.memory[start..start + image.len()].copy_from_slice(image);
+        //La memoria reservada detrás de la imagen empieza a cero
+        self.memory[start + image.len()..start + (allocated << 4)].fill(0);
+        for address in addresses {
+            let value = u16::from_le_bytes([self.memory[start + address], self.memory[start + address + 1]]);
+            let value = value.wrapping_add(image_segment as u16).to_le_bytes();
+            self.memory[start + address..start + address + 2].copy_from_slice(&value);
         }
+        self.build_psp(load_segment, (image_segment + allocated) as u16);
+        self.reset_cpu();
+        self.registers.cs = initial_cs.wrapping_add(image_segment as u16);
+        self.registers.ip = initial_ip;
+        self.registers.ss = initial_ss.wrapping_add(image_segment as u16);
+        self.registers.sp = initial_sp;
+        self.registers.ds = load_segment;
+        self.registers.es = load_segment;
         Ok(())
     }
 
+    //Prepara el PSP en segment: INT 20h al principio para terminar con un salto a PSP:0000, el segmento
+    //en el que acaba la memoria del programa, el INT 21h / RETF de PSP:0050 y la línea de órdenes vacía
+    fn build_psp(&mut self, segment: u16, memory_end: u16){
+        self.set_b_in_memory(segment, 0x0000, 0xCD);
+        self.set_b_in_memory(segment, 0x0001, 0x20);
+        self.set_w_in_memory(segment, 0x0002, memory_end);
+        self.set_b_in_memory(segment, 0x0050, 0xCD);
+        self.set_b_in_memory(segment, 0x0051, 0x21);
+        self.set_b_in_memory(segment, 0x0052, 0xCB);
+        self.set_b_in_memory(segment, 0x0080, 0x00);
+        self.set_b_in_memory(segment, 0x0081, 0x0D);
+    }
+
+    //La memoria es pública y el host la puede sustituir, con menos de 1 MiB un acceso podría salirse
+    fn check_memory(&self)->Result<(), EmulatorError>{
+        if self.memory.len() < MEM_SIZE {
+            return Err(EmulatorError::MemoryFault { address: self.memory.len() });
+        }
+        Ok(())
+    }
+
+    pub fn add_breakpoint(&mut self, cs: u16, ip: u16){
+        self.breakpoints.insert((cs, ip));
+    }
+
+    pub fn remove_breakpoint(&mut self, cs: u16, ip: u16){
+        self.breakpoints.remove(&(cs, ip));
+    }
+
+    //Ejecuta una instrucción completa (o una iteración de un REP) y atiende las interrupciones pendientes.
+    //Con el programa ya terminado no ejecuta nada
+    pub fn step(&mut self)->Result<StepOutcome, EmulatorError>{
+        if self.terminated {
+            return Ok(StepOutcome::Exited(self.exit_code));
+        }
+        if self.halted {
+            return Err(EmulatorError::Halted);
+        }
+        self.check_memory()?;
+        let opcode = self.fetch();
+        self.decode_and_execute(opcode)?;
+        Ok(if self.terminated {
+            StepOutcome::Exited(self.exit_code)
+        }else if self.halted {
+            StepOutcome::Halted
+        }else if self.breakpoints.contains(&(self.registers.cs, self.registers.ip)) {
+            StepOutcome::Breakpoint
+        }else{
+            StepOutcome::Running
+        })
+    }
+
+    //Ejecuta como mucho limit instrucciones. La primera se ejecuta aunque haya un punto de ruptura
+    //en ella, así se puede continuar después de pararse en uno
+    pub fn run(&mut self, limit: u64)->StopReason{
+        if self.halted && !self.terminated {
+            return StopReason::Halted;
+        }
+        for _ in 0..limit {
+            match self.step() {
+                Ok(StepOutcome::Running) => {},
+                Ok(StepOutcome::Halted) => return StopReason::Halted,
+                Ok(StepOutcome::Exited(code)) => return StopReason::Exited(code),
+                Ok(StepOutcome::Breakpoint) => return StopReason::Breakpoint,
+                Err(error) => return StopReason::Error(error),
+            }
+        }
+        StopReason::BudgetExhausted
+    }
+
     //Función que se ejecuta después de cada instrucción para la emulación del retardo
     pub fn run_pending_cycles(&self){
 
     }
 
+    //Dirección física de 20 bits: segmento * 16 + offset. Como en el 8086, si pasa de 0xFFFFF
+    //da la vuelta al principio de la memoria (FFFF:0010 es la dirección 0)
+    pub fn physical_address(base:u16, offset:u16)->usize{
+        (((base as usize) << 4) + (offset as usize)) & (MEM_SIZE - 1)
+    }
+
     pub fn fetch(&mut self)->u8{
-        let base_address = (self.registers.cs as usize) << 4;
-        let offset = self.registers.ip as usize;
-        let effective_address = base_address + offset;
-        self.registers.ip += 1;
+        let effective_address = Self::physical_address(self.registers.cs, self.registers.ip);
+        self.registers.ip = self.registers.ip.wrapping_add(1);
         self.memory[effective_address] //Leer 1 byte de memoria del 8086 tarda 4 ciclos de reloj
     }
 
     //Coger un byte de memoria
     pub fn get_b_from_memory(&self, base:u16, offset:u16)->u8{
-        let base_address = (base as usize) << 4;
-        let effective_address = base_address + (offset as usize);
-        self.memory[effective_address]
+        let address = Self::physical_address(base, offset);
+        let value = self.memory[address];
+        self.trace(TraceEvent::MemoryRead { address, value: value as u16, word: false });
+        value
     }
 
     //Coger un word de memoria
+    //Un word en el offset 0xFFFF no pasa al segmento siguiente, el byte alto se lee del offset 0 del mismo segmento
     pub fn get_w_from_memory(&self, base:u16, offset:u16)->u16{
-        let base_address = (base as usize) << 4;
-        let effective_address = base_address + (offset as usize);
-        println!("Direccion efectiva: 0x{:04x}", effective_address);
+        let effective_address = Self::physical_address(base, offset);
         let low_byte = self.memory[effective_address];
-        let high_byte = self.memory[effective_address + 1];
-        (high_byte as u16) << 8 | low_byte as u16
+        let high_byte = self.memory[Self::physical_address(base, offset.wrapping_add(1))];
+        let value = (high_byte as u16) << 8 | low_byte as u16;
+        self.trace(TraceEvent::MemoryRead { address: effective_address, value, word: true });
+        value
+    }
+
+    //Escribir un byte en memoria
+    pub fn set_b_in_memory(&mut self, base:u16, offset:u16, value:u8){
+        let address = Self::physical_address(base, offset);
+        self.trace(TraceEvent::MemoryWrite { address, value: value as u16, word: false });
+        self.memory[address] = value;
+    }
+
+    //Escribir un word en memoria (little endian), con la misma vuelta dentro del segmento que get_w_from_memory
+    pub fn set_w_in_memory(&mut self, base:u16, offset:u16, value:u16){
+        let address = Self::physical_address(base, offset);
+        self.trace(TraceEvent::MemoryWrite { address, value, word: true });
+        self.memory[address] = (value & 0x00FF) as u8;
+        self.memory[Self::physical_address(base, offset.wrapping_add(1))] = (value >> 8) as u8;
+    }
+
+    //Convierte un operando registro o memoria del decodificador en RmOperand, calculando la dirección efectiva
+    //con los registros actuales. Se guarda como última dirección efectiva para LEA, LDS y LES con mod 11
+    fn resolve_rm(&mut self, operand: Operand)->RmOperand{
+        match operand{
+            Operand::Memory { base, displacement, segment, .. } => {
+                let offset = match base{
+                    Some(rm_field) => self.base_address(rm_field).wrapping_add(displacement),
+                    None => displacement,
+                };
+                let segment = self.get_segment_by_index(segment);
+                self.last_effective_address = (segment, offset);
+                RmOperand::Memory(segment, offset)
+            },
+            Operand::Register { index, .. } => RmOperand::Register(index),
+            //El decodificador solo pone registros y memoria en las posiciones r/m
+            _ => RmOperand::Register(0),
+        }
+    }
+
+    //Valor de un inmediato, desplazamiento de un salto o dirección directa
+    fn operand_value(operand: Operand)->u16{
+        match operand{
+            Operand::Immediate { value, .. } | Operand::Relative(value) => value,
+            Operand::Memory { displacement, .. } => displacement,
+            _ => 0,
+        }
+    }
+
+    //Acceso a los registros de propósito general por su indice, dejando traza
+    fn read_reg_w(&self, index: u8)->u16{
+        let value = self.registers.get_register_by_index(index);
+        self.trace(TraceEvent::RegisterRead { register: REGISTER_NAMES_W[(index & 0x07) as usize], value });
+        value
+    }
+
+    fn read_reg_b(&self, index: u8)->u8{
+        let value = self.registers.get_register_by_index_byte(index);
+        self.trace(TraceEvent::RegisterRead { register: REGISTER_NAMES_B[(index & 0x07) as usize], value: value as u16 });
+        value
+    }
+
+    fn write_reg_w(&mut self, index: u8, value: u16){
+        self.trace(TraceEvent::RegisterWrite { register: REGISTER_NAMES_W[(index & 0x07) as usize], value });
+        self.registers.write_register_by_index(index, value);
+    }
+
+    fn write_reg_b(&mut self, index: u8, value: u8){
+        self.trace(TraceEvent::RegisterWrite { register: REGISTER_NAMES_B[(index & 0x07) as usize], value: value as u16 });
+        self.registers.write_register_by_index_byte(index, value);
+    }
+
+    fn base_address(&self, rm_field: u8)->u16{
+        let value = self.registers.get_base_address_from_code(rm_field);
+        self.trace(TraceEvent::BaseAddress { base: BASE_ADDRESS_NAMES[(rm_field & 0x07) as usize], value });
+        value
+    }
+
+    fn read_rm_b(&self, operand: RmOperand)->u8{
+        match operand{
+            RmOperand::Register(index) => self.read_reg_b(index),
+            RmOperand::Memory(segment, offset) => self.get_b_from_memory(segment, offset),
+        }
+    }
+
+    fn read_rm_w(&self, operand: RmOperand)->u16{
+        match operand{
+            RmOperand::Register(index) => self.read_reg_w(index),
+            RmOperand::Memory(segment, offset) => self.get_w_from_memory(segment, offset),
+        }
+    }
+
+    fn write_rm_b(&mut self, operand: RmOperand, value: u8){
+        match operand{
+            RmOperand::Register(index) => self.write_reg_b(index, value),
+            RmOperand::Memory(segment, offset) => self.set_b_in_memory(segment, offset, value),
+        }
+    }
+
+    fn write_rm_w(&mut self, operand: RmOperand, value: u16){
+        match operand{
+            RmOperand::Register(index) => self.write_reg_w(index, value),
+            RmOperand::Memory(segment, offset) => self.set_w_in_memory(segment, offset, value),
+        }
+    }
+
+    //Lectura y escritura de un r/m de 8 o 16 bits según is_word
+    fn read_rm(&self, operand: RmOperand, is_word: bool)->u16{
+        if is_word { self.read_rm_w(operand) } else { self.read_rm_b(operand) as u16 }
+    }
+
+    fn write_rm(&mut self, operand: RmOperand, value: u16, is_word: bool){
+        if is_word {
+            self.write_rm_w(operand, value);
+        }else{
+            self.write_rm_b(operand, value as u8);
+        }
+    }
+
+    //Decodifica la instrucción cuyo primer byte (prefijo u opcode) ya se ha leído y la ejecuta.
+    //El decodificador lee el resto de bytes avanzando IP
+    pub fn decode_and_execute(&mut self, opcode: u8)->Result<(), EmulatorError>{
+        self.instruction_start = (self.registers.cs, self.registers.ip.wrapping_sub(1));
+        self.trace(TraceEvent::Instruction { cs: self.instruction_start.0, ip: self.instruction_start.1, opcode });
+        self.interrupt_shadow = false;
+        //El paso a paso depende de TF al empezar la instrucción, así no se interrumpe la instrucción
+        //(POPF o IRET) que lo activa y sí la que lo desactiva
+        let trap = self.registers.flags & FLAG_TF != 0;
+        let mut first_byte = Some(opcode);
+        let instruction = decode_from(|| match first_byte.take() {
+            Some(byte) => byte,
+            None => self.fetch(),
+        });
+        if self.rep_pending {
+            //Siguiente iteración de un REP: IP apunta al opcode y los prefijos de la instrucción siguen activos
+            self.rep_pending = false;
+        }else{
+            self.segment_override = instruction.prefixes.segment;
+            self.rep_prefix = instruction.prefixes.rep;
+        }
+        //LOCK solo bloquea el bus, sin otros procesadores no tiene efecto. Cada prefijo tarda 2 ciclos
+        self.pending_cycles += 2 * instruction.prefixes.count as u64;
+        self.execute(&instruction)?;
+        self.service_interrupts(trap);
+        Ok(())
+    }
+
+    //Error de opcode no implementado para la instrucción en curso. La instrucción no se ejecuta
+    //y CS:IP vuelve a apuntar a su primer byte
+    fn unimplemented_opcode(&mut self)->EmulatorError{
+        let (cs, ip) = self.instruction_start;
+        let length = self.registers.ip.wrapping_sub(ip);
+        let bytes = (0..length).map(|i| self.get_b_from_memory(cs, ip.wrapping_add(i))).collect();
+        self.registers.cs = cs;
+        self.registers.ip = ip;
+        EmulatorError::UnimplementedOpcode { cs, ip, bytes }
+    }
+
+    //Atiende las interrupciones pendientes al terminar una instrucción, por orden de prioridad
+    //NMI, INTR (solo con IF activa) y paso a paso. Solo se atiende una, las demás siguen pendientes
+    //y el manejador de la primera se ejecuta con IF y TF a 0
+    fn service_interrupts(&mut self, trap: bool){
+        if self.interrupt_shadow || self.terminated {
+            return;
+        }
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.interrupt(2);
+        }else if self.intr_pending.is_some() && self.registers.flags & FLAG_IF != 0 {
+            let vector = self.intr_pending.take().unwrap_or_default();
+            self.interrupt(vector);
+        }else if trap {
+            self.interrupt(1);
+        }
     }
 
-    //Decode ModRM
-    //addressing_mode,registro destino,origen
-    fn decode_modrm(modrm: u8) -> (u8, u8, u8) {
-        let addressing_mode = (modrm & 0b11000000) >> 6; 
-        let reg = (modrm & 0b00111000) >> 3;
-        let rm = modrm & 0b00000111;
-        (addressing_mode, reg, rm)
+    //Activa la línea NMI, se atiende siempre (INT 2) aunque IF esté a 0
+    pub fn nmi(&mut self){
+        self.nmi_pending = true;
+        if self.halted {
+            self.service_interrupts(false);
+        }
+    }
+
+    //Petición de interrupción enmascarable por INTR, vector es el que el controlador de interrupciones
+    //pondría en el bus durante el reconocimiento. Queda pendiente mientras IF esté a 0
+    pub fn intr(&mut self, vector: u8){
+        self.intr_pending = Some(vector);
+        if self.halted {
+            self.service_interrupts(false);
+        }
     }
 
-    pub fn decode_and_execute(&mut self, opcode: u8){
+    fn execute(&mut self, instruction: &Instruction)->Result<(), EmulatorError>{
+        if !instruction.is_valid() {
+            return Err(self.unimplemented_opcode());
+        }
+        let opcode = instruction.opcode;
         match opcode {
             0x37 => self.aaa(),
-            0xD5 => self.aad(),
+            0xD5 => self.aad(instruction),
             0xD4 => self.aam(),
             0x3F => self.aas(),
-            _ => {
-                if (0x00..=0x05).contains(&opcode){
-                    self.add(opcode);
-                }else if (0x10..=0x15).contains(&opcode){
-                    self.adc(opcode)
-                }else if OPCODES_AND.contains(&opcode){
-                    self.and(opcode)
-                }else if OPCODES_MOV.contains(&opcode){
-                    self.mov(opcode);
-                }
-                else {
-                    panic!("Opcode no implementado: 0x{:02x}", opcode);
-                }
-            }
+            0x27 => self.daa(),
+            0x2F => self.das(),
+            0x40..=0x4F => self.inc_dec_register(opcode),
+            0x80..=0x83 => self.group_80_83(instruction),
+            0x06 | 0x0E | 0x16 | 0x1E => self.push_segment(opcode),
+            0x07 | 0x0F | 0x17 | 0x1F => self.pop_segment(opcode),
+            //ADD, OR, ADC, SBB, AND, SUB, XOR y CMP, los bits 3-5 del opcode indican la operación
+            0x00..=0x3D if opcode & 0x07 < 0x06 => self.alu(instruction, (opcode >> 3) & 0x07, true),
+            0x50..=0x57 => self.push_register(opcode),
+            0x58..=0x5F => self.pop_register(opcode),
+            0x70..=0x7F => self.jcc(instruction),
+            0x84 | 0x85 | 0xA8 | 0xA9 => self.test(instruction),
+            0x86 | 0x87 => self.xchg_rm(instruction),
+            0x88..=0x8C | 0x8E | 0xA0..=0xA3 | 0xB0..=0xBF | 0xC6 | 0xC7 => self.mov(instruction),
+            0x8D => self.lea(instruction),
+            0x8F => self.pop_rm(instruction),
+            0x90 => self.pending_cycles += 3,
+            0x91..=0x97 => self.xchg_ax(opcode),
+            0x9A | 0xE8 => self.call(instruction),
+            0x98 => self.cbw(),
+            0x9B => self.pending_cycles += 3,
+            0x99 => self.cwd(),
+            0x9C => self.pushf(),
+            0x9D => self.popf(),
+            0x9E => self.sahf(),
+            0x9F => self.lahf(),
+            0xA4..=0xA7 | 0xAA..=0xAF => self.string(opcode),
+            0xC2 | 0xC3 => self.ret(instruction),
+            0xC4 | 0xC5 => self.load_far_pointer(instruction),
+            0xCA | 0xCB => self.retf(instruction),
+            0xCC..=0xCE => self.int(instruction),
+            0xCF => self.iret(),
+            0xD0..=0xD3 => self.shift_rotate(instruction),
+            0xD7 => self.xlat(),
+            0xD8..=0xDF => self.esc(instruction),
+            0xE0..=0xE3 => self.loop_cx(instruction),
+            0xE9..=0xEB => self.jmp(instruction),
+            0xF4 => self.hlt(),
+            0xF5 | 0xF8..=0xFD => self.flag_control(opcode),
+            0xF6 | 0xF7 => self.group_f6_f7(instruction)?,
+            0xFE => self.group_fe(instruction),
+            0xFF => self.group_ff(instruction)?,
+            _ => return Err(self.unimplemented_opcode()),
         }
+        Ok(())
     }
 
     pub fn imprimir_estado_memoria(&self, inicio: usize, fin: usize) {
@@ -101,7 +676,7 @@ impl Emulator8086{
 
         for i in inicio..=fin {
             print!("0x{:02x} ", self.memory[i]);
-            if (i - inicio + 1) % 16 == 0 {
+            if (i - inicio + 1).is_multiple_of(16) {
                 println!(); // Imprime una nueva línea cada 16 bytes para que sea más legible
             }
         }
@@ -160,34 +735,29 @@ impl Emulator8086{
     }
 
 
-    //AAD ASCII adjust for division
-    fn aad(&mut self){
-        let ah = (self.registers.ax & 0xFF00) >> 8;
-        let al = self.registers.ax & 0x00FF;
-        let temp = al.wrapping_add(ah*0xA)&0xFF00;
-        self.registers.ax = (self.registers.ax & 0xFF00) | temp;
-        self.registers.ax &= 0x00FF;
-        self.registers.flags &= !(FLAG_PF | FLAG_SF | FLAG_ZF);
-        if temp == 0{
-            self.registers.flags |= FLAG_ZF;
-        }
-        self.registers.ax = (ah as u16) << 8 | al as u16;
+    //AAD ASCII adjust for division: AL = AL + AH*base y AH = 0. La base es el byte que sigue al opcode (10 en AAD)
+    fn aad(&mut self, instruction: &Instruction){
+        let base = Self::operand_value(instruction.operands[0]) as u8;
+        let ah = self.registers.get_high_byte(self.registers.ax);
+        let al = self.registers.get_low_byte(self.registers.ax);
+        let al = al.wrapping_add(ah.wrapping_mul(base));
+        self.registers.ax = al as u16;
+        actualizar_flags_szp(&mut self.registers.flags, al as u16, true);
         self.pending_cycles += 60;
     }
 
     //AAM ASCII adjust for multiplication
     fn aam(&mut self){
         let mut al = self.registers.get_low_byte(self.registers.ax);
-        let mut ah = self.registers.get_high_byte(self.registers.ax);
-        ah = al / 0xA;
-        al = al % 0xA;
+        let ah = al / 0xA;
+        al %= 0xA;
         self.registers.flags &= !(FLAG_PF | FLAG_SF | FLAG_ZF);
         if al == 0 {self.registers.flags |= FLAG_ZF;}
         if al & 0x80 != 0 {
             self.registers.flags |= FLAG_SF;  // Establecer SF si el bit más significativo de AL es 1
         }
         let parity = al.count_ones();
-        if parity % 2 == 0 {
+        if parity.is_multiple_of(2) {
             self.registers.flags |= FLAG_PF;
         }
         self.registers.ax = (ah as u16) << 8 | al as u16;
@@ -210,391 +780,1159 @@ impl Emulator8086{
         self.pending_cycles += 4;
     }
 
-    //ADC Add with carry
-    fn adc(&mut self, opcode: u8){
+    //DAA Decimal adjust for addition, corrige AL tras sumar dos números BCD empaquetados
+    fn daa(&mut self){
+        self.decimal_adjust(false);
+    }
 
+    //DAS Decimal adjust for subtraction
+    fn das(&mut self){
+        self.decimal_adjust(true);
     }
 
-    //Reformar el carry y el overflow hay que controlar si son dos numeros negativos
-    //ADD Add Mirar como funciona ADD mal funcionamiento
-    fn add(&mut self,opcode: u8){
-        match opcode{
-            0x00 =>{
+    //DAA y DAS solo se diferencian en si el ajuste se suma o se resta. OF queda indefinida y no se toca.
+    //En DAS el préstamo de AL-6 también activa CF (en DAA el acarreo de AL+6 solo puede darse con AL > 0x99)
+    fn decimal_adjust(&mut self, subtract: bool){
+        let old_al = self.registers.get_low_byte(self.registers.ax);
+        let old_carry = (self.registers.flags & FLAG_CF) != 0;
+        let adjust = |value: u8, amount: u8| if subtract { value.wrapping_sub(amount) } else { value.wrapping_add(amount) };
+        let mut al = old_al;
+        let mut carry = false;
+        if (al & 0x0F > 9) || (self.registers.flags & FLAG_AF != 0) {
+            al = adjust(al, 0x06);
+            carry = subtract && old_al < 0x06;
+            self.registers.flags |= FLAG_AF;
+        }else{
+            self.registers.flags &= !FLAG_AF;
+        }
+        if old_al > 0x99 || old_carry {
+            al = adjust(al, 0x60);
+            carry = true;
+        }
+        if carry {
+            self.registers.flags |= FLAG_CF;
+        }else{
+            self.registers.flags &= !FLAG_CF;
+        }
+        actualizar_flags_szp(&mut self.registers.flags, al as u16, true);
+        self.registers.ax = self.registers.write_low_byte(self.registers.ax, al);
+        self.pending_cycles += 4;
+    }
 
-            },
-            0x01 =>{
+    //CBW Convert byte to word, extiende el signo de AL a AH
+    fn cbw(&mut self){
+        self.registers.ax = self.registers.get_low_byte(self.registers.ax) as i8 as u16;
+        self.pending_cycles += 2;
+    }
 
-            },
-            0x02 =>{
-                //Igual que el 3 pero con byte en vez de word
-                let mod_rm = self.fetch(); //Leer el byte que nos dice el modo de direccionamiento
-                let (mod_field, reg_field, rm_field) = Self::decode_modrm(mod_rm);
-                println!("Mod: 0x{:02x}, Reg: 0x{:02x}, R/M: 0x{:02x}", mod_field, reg_field, rm_field);
-                match mod_field{
-                    0x00=>{
-                        let aux = self.fetch();
-                    },
-                    0x01=>{
-                        let aux = self.fetch();
-                    },
-                    0x02=>{
-                        let aux = self.fetch();
-                    },
-                    0x03 => {
-                        //ADD AL,CL registro a registro
-                        let origin_register_value = self.registers.get_low_byte(self.registers.get_register_by_index(rm_field));
-                        let destination_register_value = self.registers.get_low_byte(self.registers.get_register_by_index(reg_field));
-                        let (new_value, overflow) = destination_register_value.overflowing_add(origin_register_value);
-                        //Poner el la parte baja de AX el resultado
-                        self.registers.ax = (self.registers.ax & 0xFF00) | new_value as u16;
-                        self.registers.write_register_by_index(reg_field, (self.registers.get_register_by_index(rm_field)& 0xFF00)| new_value as u16);
-                        if overflow {
-                            self.registers.flags |= FLAG_OF;
-                        }
-                    },
-                    _ => {}
-                }
-                
-            },
-            0x03 =>{
-                // 7   6   5   4   3   2   1   0
-                // +---+---+---+---+---+---+---+---+
-                // | Mod   |   Reg/Opcode  |  R/M   |
-                // +---+---+---+---+---+---+---+---+
-                let mod_rm = self.fetch(); //Leer el byte que nos dice el modo de direccionamiento
-                let (mod_field, reg_field, rm_field) = Self::decode_modrm(mod_rm);
-                match mod_field{
-                    0x00=>{
-                        //Accedemos a memoria usando un registro como indice
-                        let origin_register_value = self.registers.get_base_address_from_code(rm_field);
-                        let destination_register_value = self.registers.get_register_by_index(reg_field);
-                        let memory_value = self.get_w_from_memory(self.registers.ds, origin_register_value);
-                        let(new_value,overflow,carry, aux) = add_16bit_complemento_a2(destination_register_value, memory_value);
-                        self.registers.write_register_by_index(reg_field, new_value);
-                        actualizar_flags_add(&mut self.registers.flags, new_value, overflow, carry, aux);
-                        self.pending_cycles += 9;
-                    },
-                    0x01=>{
-                        //Accedemos a memoria usando un registro como indice y un desplazamiento de 8 bits
-                        let origin_register_value = self.registers.get_base_address_from_code(rm_field);
-                        let displacement = self.fetch();
-                        let destination_register_value = self.registers.get_register_by_index(reg_field);
-                        let memory_value = self.get_w_from_memory(self.registers.ds, origin_register_value + displacement as u16);
-                        let (new_value,overflow,carry,aux) = add_16bit_complemento_a2(destination_register_value, memory_value);
-                        self.registers.write_register_by_index(reg_field, new_value);
-                        actualizar_flags_add(&mut self.registers.flags, new_value, overflow, carry, aux);
-                        self.pending_cycles += 9;
-                    },
-                    0x02=>{
-                        //Accedemos a memoria usando un registro como indice y un desplazamiento de 16 bits
-                        let origin_register_value = self.registers.get_base_address_from_code(rm_field);
-                        let displacement_l = self.fetch();
-                        let displacement_h = self.fetch();
-                        let displacement = (displacement_h as u16) << 8 | displacement_l as u16;
-                        let destination_register_value = self.registers.get_register_by_index(reg_field);
-                        let memory_value = self.get_w_from_memory(self.registers.ds, origin_register_value + displacement);
-                        let (new_value,overflow,carry,aux) = add_16bit_complemento_a2(destination_register_value, memory_value);
-                        self.registers.write_register_by_index(reg_field, new_value);
-                        actualizar_flags_add(&mut self.registers.flags, new_value, overflow, carry, aux);
-                        self.pending_cycles += 9;
-                    },
-                    0x03=>{
-                        let origin_register_value = self.registers.get_register_by_index(rm_field);
-                        let destination_register_value = self.registers.get_register_by_index(reg_field);
-                        let (new_value,overflow,carry,aux) = add_16bit_complemento_a2(destination_register_value, origin_register_value);
-                        self.registers.write_register_by_index(reg_field, new_value);
-                        actualizar_flags_add(&mut self.registers.flags, new_value, overflow, carry, aux);
-                        self.pending_cycles += 3;
-                    },
-                    _ => {}
-                }
-            },
-            0x04 =>{
-                let inmediate_value = self.fetch();
-                let al = self.registers.get_low_byte(self.registers.ax);
-                let (new_al,overflow,carry, aux) = add_8bit_complemento_a2(al, inmediate_value);
-                self.registers.ax = (self.registers.ax & 0xFF00) | new_al as u16;
-                actualizar_flags_add(&mut self.registers.flags, new_al as u16, overflow, carry, aux);
-                self.pending_cycles += 4;
-            },
-            0x05 =>{
-                let inmediate_value_l = self.fetch();
-                let inmediate_value_h = self.fetch();
-                let inmediate_value = (inmediate_value_h as u16) << 8 | inmediate_value_l as u16;
-                let(new_ax,overflow,carry, aux) = add_16bit_complemento_a2(self.registers.ax, inmediate_value);
-                self.registers.ax = new_ax;
-                actualizar_flags_add(&mut self.registers.flags, new_ax, overflow, carry, aux);
-                self.pending_cycles += 4;
-            },
-            _ => {}
-        }
+    //CWD Convert word to double word, extiende el signo de AX a DX
+    fn cwd(&mut self){
+        self.registers.dx = if self.registers.ax & 0x8000 != 0 { 0xFFFF } else { 0x0000 };
+        self.pending_cycles += 5;
     }
 
-    fn and(&mut self,opcode: u8){
-        match opcode{
-            0x20=>{
+    //XLAT Translate, AL = [BX + AL] en DS (admite prefijo de segmento)
+    fn xlat(&mut self){
+        let al = self.registers.get_low_byte(self.registers.ax);
+        let offset = self.registers.bx.wrapping_add(al as u16);
+        let value = self.get_b_from_memory(self.data_segment(), offset);
+        self.registers.ax = self.registers.write_low_byte(self.registers.ax, value);
+        self.pending_cycles += 11;
+    }
 
-            },
-            0x21=>{
+    //LAHF Load AH from flags, copia SF ZF AF PF CF. El bit 1 siempre se lee a 1
+    fn lahf(&mut self){
+        let flags = ((self.registers.flags | FLAGS_RESERVED) & 0x00FF) as u8;
+        self.registers.ax = self.registers.write_high_byte(self.registers.ax, flags);
+        self.pending_cycles += 4;
+    }
 
-            },
-            0x22=>{
+    //SAHF Store AH into flags, solo afecta a SF ZF AF PF CF
+    fn sahf(&mut self){
+        let mask = FLAG_SF | FLAG_ZF | FLAG_AF | FLAG_PF | FLAG_CF;
+        let ah = self.registers.get_high_byte(self.registers.ax) as u16;
+        self.registers.flags = (self.registers.flags & !mask) | (ah & mask);
+        self.pending_cycles += 4;
+    }
 
-            },
-            0x23=>{
+    //ADD, OR, ADC, SBB, AND, SUB, XOR y CMP con dos operandos comparten codificación, los 3 bits bajos
+    //del opcode indican la forma:
+    // x0 rmb,rb   x1 rmw,rw   x2 rb,rmb   x3 rw,rmw   x4 AL,ib   x5 AX,iw
+    //operation es la operación de arithmetic y store indica si se guarda el resultado (TEST no lo guarda)
+    fn alu(&mut self, instruction: &Instruction, operation: u8, store: bool){
+        let [destination, source] = instruction.operands;
+        let is_word = instruction.opcode & 0x01 != 0;
+        if let Operand::Immediate { value, .. } = source {
+            //Formas con el acumulador
+            let accumulator = if is_word { self.registers.ax } else { self.registers.get_low_byte(self.registers.ax) as u16 };
+            if let Some(new_value) = self.arithmetic(operation, accumulator, value, is_word).filter(|_| store) {
+                self.write_rm(RmOperand::Register(0), new_value, is_word);
+            }
+            self.pending_cycles += 4;
+            return;
+        }
+        let destination = self.resolve_rm(destination);
+        let source = self.resolve_rm(source);
+        let destination_value = self.read_rm(destination, is_word);
+        let source_value = self.read_rm(source, is_word);
+        let new_value = self.arithmetic(operation, destination_value, source_value, is_word).filter(|_| store);
+        if let Some(new_value) = new_value {
+            self.write_rm(destination, new_value, is_word);
+        }
+        let writes_memory = new_value.is_some() && matches!(destination, RmOperand::Memory(..));
+        let rm_operand = if matches!(source, RmOperand::Memory(..)) { source } else { destination };
+        self.pending_cycles += Self::alu_cycles(rm_operand, writes_memory);
+    }
+
+    //Ciclos de las operaciones aritméticas y lógicas: reg,reg 3, reg,mem 9 y mem,reg 16 (sin contar el cálculo de la EA)
+    fn alu_cycles(operand: RmOperand, writes_memory: bool)->u64{
+        match operand{
+            RmOperand::Register(_) => 3,
+            RmOperand::Memory(..) if writes_memory => 16,
+            RmOperand::Memory(..) => 9,
+        }
+    }
+
+    //TEST AND sin guardar el resultado, 84/85 usan la forma r/m,reg y A8/A9 la del acumulador
+    fn test(&mut self, instruction: &Instruction){
+        self.alu(instruction, 0b100, false);
+    }
+
+    //Grupo 80-83, operaciones aritméticas y lógicas con inmediato. El campo reg selecciona la operación
+    // /0 ADD  /1 OR  /2 ADC  /3 SBB  /4 AND  /5 SUB  /6 XOR  /7 CMP
+    //80 r/m8,imm8  81 r/m16,imm16  82 alias de 80 en el 8086  83 r/m16,imm8 con extensión de signo
+    fn group_80_83(&mut self, instruction: &Instruction){
+        let [destination, source] = instruction.operands;
+        let is_word = instruction.opcode & 0x01 != 0;
+        let operand = self.resolve_rm(destination);
+        let value = self.read_rm(operand, is_word);
+        let new_value = self.arithmetic(instruction.reg_field(), value, Self::operand_value(source), is_word);
+        if let Some(new_value) = new_value {
+            self.write_rm(operand, new_value, is_word);
+        }
+        self.pending_cycles += match operand {
+            RmOperand::Register(_) => 4,
+            RmOperand::Memory(..) if new_value.is_some() => 17,
+            RmOperand::Memory(..) => 10,
+        };
+    }
 
+    //Aplica la operación aritmética o lógica indicada por los 3 bits de operation (mismo orden que los
+    //opcodes 00-3D) y actualiza las flags. Devuelve el resultado, o None si la operación es CMP
+    fn arithmetic(&mut self, operation: u8, destination: u16, source: u16, is_word: bool)->Option<u16>{
+        let flags = &mut self.registers.flags;
+        let carry_in = (*flags & FLAG_CF) != 0;
+        match operation{
+            0b000 | 0b010 => {
+                let carry = operation == 0b010 && carry_in;
+                let (new_value, overflow, carry, aux) = if is_word {
+                    adc_16bit_complemento_a2(destination, source, carry)
+                }else{
+                    let (new_value, overflow, carry, aux) = adc_8bit_complemento_a2(destination as u8, source as u8, carry);
+                    (new_value as u16, overflow, carry, aux)
+                };
+                actualizar_flags_add(flags, new_value, !is_word, overflow, carry, aux);
+                Some(new_value)
             },
-            0x24=>{
-                let inmediate_value = self.fetch();
-                let al = self.registers.get_low_byte(self.registers.ax);
-                let new_al = al & inmediate_value;
-                self.registers.ax = (self.registers.ax & 0xFF00) | new_al as u16;
-                actualizar_flags_and(&mut self.registers.flags, new_al as u16);
-                self.pending_cycles += 4;
+            0b011 | 0b101 | 0b111 => {
+                let borrow = operation == 0b011 && carry_in;
+                let (new_value, overflow, carry, aux) = if is_word {
+                    sub_16bit_complemento_a2(destination, source, borrow)
+                }else{
+                    let (new_value, overflow, carry, aux) = sub_8bit_complemento_a2(destination as u8, source as u8, borrow);
+                    (new_value as u16, overflow, carry, aux)
+                };
+                actualizar_flags_sub(flags, new_value, !is_word, overflow, carry, aux);
+                if operation == 0b111 { None } else { Some(new_value) }
             },
-            0x25=>{
-                let inmediate_value_l = self.fetch();
-                let inmediate_value_h = self.fetch();
-                let inmediate_value = (inmediate_value_h as u16) << 8 | inmediate_value_l as u16;
-                let new_ax = self.registers.ax & inmediate_value;
-                self.registers.ax = new_ax;
-                actualizar_flags_and(&mut self.registers.flags, new_ax);
-                self.pending_cycles += 4;
+            _ => {
+                let new_value = match operation{
+                    0b001 => destination | source,
+                    0b100 => destination & source,
+                    _ => destination ^ source,
+                };
+                let new_value = if is_word { new_value } else { new_value & 0x00FF };
+                actualizar_flags_logic(flags, new_value, !is_word);
+                Some(new_value)
             },
-            _=>{}
         }
     }
 
-    //TODO: Implementar las instrucciones de mov
-    // MOV     rb,rmb      8A mr d0 d1    B   2~4    -------- //implementada
-    // MOV     rmb,rb      88 mr d0 d1    B   2~4    -------- //implementada
-    // MOV     rmw,rw      89 mr d0 d1    W   2~4    --------
-    // MOV     rw,rmw      8B mr d0 d1    W   2~4    --------
-    // MOV     rmw,sr      8C mr d0 d1        2~4    --------
-    // MOV     sr,rmw      8E mr d0 d1        2~4    --------
-    // MOV     AL,rmb      A0 d0 d1      B   3    --------
-    // MOV     AX,rmw      A1 d0 d1      W   3    --------
-    // MOV     rmb,AL      A2 d0 d1      B   3    --------
-    // MOV     rmw,AX      A3 d0 d1      W   3    --------
-    // MOV     AL,ib       B0 i0         B   2    --------
-    // MOV     CL,ib       B1 i0         B   2    --------
-    // MOV     DL,ib       B2 i0         B   2    --------
-    // MOV     BL,ib       B3 i0         B   2    --------
-    // MOV     AH,ib       B4 i0         B   2    --------
-    // MOV     CH,ib       B5 i0         B   2    --------
-    // MOV     DH,ib       B6 i0         B   2    --------
-    // MOV     BH,ib       B7 i0         B   2    --------
-    // MOV     AX,iw       B8 i0 i1      W   3    --------
-    // MOV     CX,iw       B9 i0 i1      W   3    --------
-    // MOV     DX,iw       BA i0 i1      W   3    --------
-    // MOV     BX,iw       BB i0 i1      W   3    --------
-    // MOV     SP,iw       BC i0 i1      W   3    --------
-    // MOV     BP,iw       BD i0 i1      W   3    --------
-    // MOV     SI,iw       BE i0 i1      W   3    --------
-    // MOV     DI,iw       BF i0 i1      W   3    --------
-    // MOV     rmb,ib      C6 mr d0 d1 i0 B   3~5    --------
-    // MOV     rmw,iw      C7 mr d0 d1 i0 i1   W   4~6    --------
-    fn mov(&mut self,opcode:u8){
-        match opcode{
-            0x88=>{
-                let mod_rm = self.fetch();
-                println!("ModRM: 0x{:02x}", mod_rm);
-                // 7   6   5   4   3   2   1   0
-                // +---+---+---+---+---+---+---+---+
-                // | Mod   |   Reg/Opcode  |  R/M   |
-                // +---+---+---+---+---+---+---+---+
-                let (mod_field, reg_field, rm_field) = Self::decode_modrm(mod_rm);
-                println!("Mod: 0x{:02x}, Reg: 0x{:02x}, R/M: 0x{:02x}", mod_field, reg_field, rm_field);
-                let src = self.registers.get_register_by_index_byte(reg_field);
-                let dst = self.registers.get_base_address_from_code(rm_field);
-                match mod_field{
-                    0x00 => {
-                        println!("0x00 ejecutando");
-                        self.memory[dst as usize] = src as u8;
-                        self.pending_cycles += 3;
-                    },
-                    0x01 => {
-                        let aux = self.fetch();
-                        println!("Desplazamiento: 0x{:02x}", aux);
-                        let dst = dst + aux as u16;
-                        println!("Dirección efectiva: 0x{:04x}", dst);
-                        self.memory[dst as usize] = src as u8;
-                        self.pending_cycles += 3;
-                    },
-                    0x02 => {
-                        let aux_l = self.fetch();
-                        let aux_h = self.fetch();
-                        let aux = (aux_h as u16) << 8 | aux_l as u16;
-                        let dst = dst + aux;
-                        self.memory[dst as usize] = src as u8;
-                        self.pending_cycles += 3;
-                    },
-                    0x03 => {
-                        println!("Ejecutando 0x03");
-                    },
-                    _ => {}
+    //INC y DEC suman o restan 1 sin modificar CF
+    fn inc_dec(&mut self, value: u16, decrement: bool, is_word: bool)->u16{
+        let carry = self.registers.flags & FLAG_CF;
+        let operation = if decrement { 0b101 } else { 0b000 };
+        let new_value = self.arithmetic(operation, value, 1, is_word).unwrap_or(value);
+        self.registers.flags = (self.registers.flags & !FLAG_CF) | carry;
+        new_value
+    }
+
+    //40-47 INC r16  48-4F DEC r16
+    fn inc_dec_register(&mut self, opcode: u8){
+        let index = opcode & 0x07;
+        let value = self.read_reg_w(index);
+        let new_value = self.inc_dec(value, opcode & 0x08 != 0, true);
+        self.write_reg_w(index, new_value);
+        self.pending_cycles += 2;
+    }
+
+    //Grupo FE, solo /0 INC r/m8 y /1 DEC r/m8 están definidos
+    fn group_fe(&mut self, instruction: &Instruction){
+        let operand = self.resolve_rm(instruction.operands[0]);
+        let value = self.read_rm_b(operand) as u16;
+        let new_value = self.inc_dec(value, instruction.mnemonic == Mnemonic::Dec, false);
+        self.write_rm_b(operand, new_value as u8);
+        self.pending_cycles += match operand { RmOperand::Register(_) => 3, RmOperand::Memory(..) => 15 };
+    }
+
+    //Grupo F6/F7, el campo reg del ModRM selecciona la operación
+    // /0 TEST r/m,imm  /1 alias no documentado de TEST  /2 NOT  /3 NEG  /4 MUL  /5 IMUL  /6 DIV  /7 IDIV
+    fn group_f6_f7(&mut self, instruction: &Instruction)->Result<(), EmulatorError>{
+        let operand = self.resolve_rm(instruction.operands[0]);
+        let is_word = instruction.opcode & 0x01 != 0;
+        match instruction.mnemonic{
+            Mnemonic::Test => {
+                let new_value = self.read_rm(operand, is_word) & Self::operand_value(instruction.operands[1]);
+                actualizar_flags_logic(&mut self.registers.flags, new_value, !is_word);
+                self.pending_cycles += match operand { RmOperand::Register(_) => 5, RmOperand::Memory(..) => 11 };
+            },
+            Mnemonic::Not => {
+                //NOT no modifica ninguna flag
+                if is_word {
+                    let new_value = !self.read_rm_w(operand);
+                    self.write_rm_w(operand, new_value);
+                }else{
+                    let new_value = !self.read_rm_b(operand);
+                    self.write_rm_b(operand, new_value);
                 }
+                self.pending_cycles += match operand { RmOperand::Register(_) => 3, RmOperand::Memory(..) => 16 };
             },
-            0x8A => {
-                let mod_rm = self.fetch();
-                let (mod_field, reg_field, rm_field) = Self::decode_modrm(mod_rm);
-                match mod_field{
-                    0x00 => {
-                        println!("0x00 ejecutando");
-                        let src = self.registers.get_base_address_from_code(rm_field);
-                        self.registers.write_register_by_index_byte(reg_field, self.memory[src as usize]);
-                        self.pending_cycles += 3;
-                    },
-                    0x01 => {
-                        println!("0x01 ejecutando");
-                        let src = self.registers.get_base_address_from_code(rm_field);
-                        let aux = self.fetch();
-                        let src = src + aux as u16;
-                        self.registers.write_register_by_index_byte(reg_field, self.memory[src as usize]);
-                        self.pending_cycles += 3;
-                    },
-                    0x02 => {
-                        println!("0x02 ejecutando");
-                        let src = self.registers.get_base_address_from_code(rm_field);
-                        let aux_l = self.fetch();
-                        let aux_h = self.fetch();
-                        let aux = (aux_h as u16) << 8 | aux_l as u16;
-                        let src = src + aux;
-                        self.registers.write_register_by_index_byte(reg_field, self.memory[src as usize]);
-                        self.pending_cycles += 3;
-                    },
-                    0x03 => {
-                        println!("0x03 ejecutando");
-                        self.registers.write_register_by_index_byte(reg_field, self.registers.get_register_by_index_byte(rm_field));
-                    },
-                    _=>{}
+            Mnemonic::Neg => {
+                //NEG resta el operando a 0, CF queda a 1 salvo que el operando sea 0
+                if is_word {
+                    let value = self.read_rm_w(operand);
+                    let new_value = self.arithmetic(0b101, 0, value, true).unwrap_or(0);
+                    self.write_rm_w(operand, new_value);
+                }else{
+                    let value = self.read_rm_b(operand) as u16;
+                    let new_value = self.arithmetic(0b101, 0, value, false).unwrap_or(0);
+                    self.write_rm_b(operand, new_value as u8);
                 }
+                self.pending_cycles += match operand { RmOperand::Register(_) => 3, RmOperand::Memory(..) => 16 };
             },
-            0xB0 => {
-                let inmediate_value = self.fetch();
-                self.registers.ax = self.registers.write_low_byte(self.registers.ax,inmediate_value);
-                self.pending_cycles += 2;
-            },
-            0xB1 => {
-                let inmediate_value = self.fetch();
-                self.registers.cx = self.registers.write_low_byte(self.registers.cx,inmediate_value);
-                self.pending_cycles += 2;
-            },
-            0xB2 => {
-                let inmediate_value = self.fetch();
-                self.registers.dx = self.registers.write_low_byte(self.registers.dx,inmediate_value);
-                self.pending_cycles += 2;
-            },
-            0xB3 => {
-                let inmediate_value = self.fetch();
-                self.registers.bx = self.registers.write_low_byte(self.registers.bx,inmediate_value);
-                self.pending_cycles += 2;
-            },
-            0xB4 => {
-                let inmediate_value = self.fetch();
-                self.registers.ax = self.registers.write_high_byte(self.registers.ax,inmediate_value);
-                self.pending_cycles += 2;
-            },
-            0xB5 => {
-                let inmediate_value = self.fetch();
-                self.registers.cx = self.registers.write_high_byte(self.registers.cx,inmediate_value);
-                self.pending_cycles += 2;
-            },
-            0xB6 => {
-                let inmediate_value = self.fetch();
-                self.registers.dx = self.registers.write_high_byte(self.registers.dx,inmediate_value);
-                self.pending_cycles += 2;
-            },
-            0xB7 => {
-                let inmediate_value = self.fetch();
-                self.registers.bx = self.registers.write_high_byte(self.registers.bx,inmediate_value);
-                self.pending_cycles += 2;
-            },
-            0xB8 => {
-                let inmediate_value_l = self.fetch();
-                let inmediate_value_h = self.fetch();
-                let inmediate_value = (inmediate_value_h as u16) << 8 | inmediate_value_l as u16;
-                self.registers.ax = inmediate_value;
-                self.pending_cycles += 3;
-            },
-            0xB9 => {
-                let inmediate_value_l = self.fetch();
-                let inmediate_value_h = self.fetch();
-                let inmediate_value = (inmediate_value_h as u16) << 8 | inmediate_value_l as u16;
-                self.registers.cx = inmediate_value;
-                self.pending_cycles += 3;
-            },
-            0xBA => {
-                let inmediate_value_l = self.fetch();
-                let inmediate_value_h = self.fetch();
-                let inmediate_value = (inmediate_value_h as u16) << 8 | inmediate_value_l as u16;
-                self.registers.dx = inmediate_value;
-                self.pending_cycles += 3;
-            },
-            0xBB => {
-                let inmediate_value_l = self.fetch();
-                let inmediate_value_h = self.fetch();
-                let inmediate_value = (inmediate_value_h as u16) << 8 | inmediate_value_l as u16;
-                self.registers.bx = inmediate_value;
-                self.pending_cycles += 3;
-            },
-            0xBC => {
-                let inmediate_value_l = self.fetch();
-                let inmediate_value_h = self.fetch();
-                let inmediate_value = (inmediate_value_h as u16) << 8 | inmediate_value_l as u16;
-                self.registers.sp = inmediate_value;
-                self.pending_cycles += 3;
-            },
-            0xBD => {
-                let inmediate_value_l = self.fetch();
-                let inmediate_value_h = self.fetch();
-                let inmediate_value = (inmediate_value_h as u16) << 8 | inmediate_value_l as u16;
-                self.registers.bp = inmediate_value;
-                self.pending_cycles += 3;
-            },
-            0xBE => {
-                let inmediate_value_l = self.fetch();
-                let inmediate_value_h = self.fetch();
-                let inmediate_value = (inmediate_value_h as u16) << 8 | inmediate_value_l as u16;
-                self.registers.si = inmediate_value;
-                self.pending_cycles += 3;
-            },
-            0xBF => {
-                let inmediate_value_l = self.fetch();
-                let inmediate_value_h = self.fetch();
-                let inmediate_value = (inmediate_value_h as u16) << 8 | inmediate_value_l as u16;
-                self.registers.di = inmediate_value;
-                self.pending_cycles += 3;
-            },
-            _=>{}
+            Mnemonic::Mul => self.mul(operand, is_word),
+            Mnemonic::Imul => self.imul(operand, is_word),
+            Mnemonic::Div => self.div(operand, is_word),
+            Mnemonic::Idiv => self.idiv(operand, is_word),
+            _ => return Err(self.unimplemented_opcode()),
         }
+        Ok(())
     }
-}
-
 
-#[cfg(test)]
-mod tests{
-    use super::*;
+    //Las multiplicaciones y divisiones tardan 6 ciclos más con operando en memoria
+    fn mul_div_cycles(operand: RmOperand, cycles: u64)->u64{
+        match operand{
+            RmOperand::Register(_) => cycles,
+            RmOperand::Memory(..) => cycles + 6,
+        }
+    }
 
-    #[test]
-    fn start_emulator(){
-        let emulator = Emulator8086::new();
-        assert_eq!(emulator.registers.ax, 0);
-        assert_eq!(emulator.registers.bx, 0);
-        assert!(emulator.memory.iter().all(|&byte| byte == 0));
-        assert_eq!(emulator.pending_cycles, 0);
+    //Activa CF y OF si la parte alta del resultado es significativa, el resto de flags quedan indefinidas
+    //en el manual y aquí no se modifican
+    fn set_mul_flags(&mut self, significant_high: bool){
+        if significant_high {
+            self.registers.flags |= FLAG_CF | FLAG_OF;
+        }else{
+            self.registers.flags &= !(FLAG_CF | FLAG_OF);
+        }
     }
 
-    #[test]
-    fn load_com(){
-        let mut emulator = Emulator8086::new();
-        if let Err(e) = emulator.load_com("./tests/load_com_test.com") {
-            panic!("Error al cargar el programa: {:?}", e);
+    //MUL multiplicación sin signo: AX = AL * r/m8 o DX:AX = AX * r/m16
+    fn mul(&mut self, operand: RmOperand, is_word: bool){
+        if is_word {
+            let result = self.registers.ax as u32 * self.read_rm_w(operand) as u32;
+            self.registers.ax = result as u16;
+            self.registers.dx = (result >> 16) as u16;
+            self.set_mul_flags(self.registers.dx != 0);
+            self.pending_cycles += Self::mul_div_cycles(operand, 118);
+        }else{
+            let al = self.registers.get_low_byte(self.registers.ax);
+            self.registers.ax = al as u16 * self.read_rm_b(operand) as u16;
+            self.set_mul_flags(self.registers.ax & 0xFF00 != 0);
+            self.pending_cycles += Self::mul_div_cycles(operand, 70);
+        }
+    }
+
+    //IMUL multiplicación con signo, CF y OF indican que la parte alta no es la extensión de signo de la baja
+    fn imul(&mut self, operand: RmOperand, is_word: bool){
+        if is_word {
+            let result = self.registers.ax as i16 as i32 * self.read_rm_w(operand) as i16 as i32;
+            self.registers.ax = result as u16;
+            self.registers.dx = (result >> 16) as u16;
+            self.set_mul_flags(result != result as i16 as i32);
+            self.pending_cycles += Self::mul_div_cycles(operand, 128);
+        }else{
+            let al = self.registers.get_low_byte(self.registers.ax);
+            let result = al as i8 as i16 * self.read_rm_b(operand) as i8 as i16;
+            self.registers.ax = result as u16;
+            self.set_mul_flags(result != result as i8 as i16);
+            self.pending_cycles += Self::mul_div_cycles(operand, 80);
+        }
+    }
+
+    //DIV división sin signo: AX / r/m8 -> AL cociente, AH resto o DX:AX / r/m16 -> AX cociente, DX resto
+    //Si el divisor es 0 o el cociente no cabe se lanza la interrupción 0 (divide error)
+    fn div(&mut self, operand: RmOperand, is_word: bool){
+        if is_word {
+            let divisor = self.read_rm_w(operand) as u32;
+            self.pending_cycles += Self::mul_div_cycles(operand, 144);
+            let dividend = (self.registers.dx as u32) << 16 | self.registers.ax as u32;
+            if divisor == 0 || dividend / divisor > 0xFFFF {
+                self.interrupt(0);
+                return;
+            }
+            self.registers.ax = (dividend / divisor) as u16;
+            self.registers.dx = (dividend % divisor) as u16;
+        }else{
+            let divisor = self.read_rm_b(operand) as u16;
+            self.pending_cycles += Self::mul_div_cycles(operand, 80);
+            let dividend = self.registers.ax;
+            if divisor == 0 || dividend / divisor > 0xFF {
+                self.interrupt(0);
+                return;
+            }
+            self.registers.ax = (dividend % divisor) << 8 | (dividend / divisor);
+        }
+    }
+
+    //IDIV división con signo, el resto tiene el signo del dividendo
+    //El 8086 no admite los cocientes -128 y -32768 (a partir del 80286 sí), también lanzan divide error
+    fn idiv(&mut self, operand: RmOperand, is_word: bool){
+        if is_word {
+            let divisor = self.read_rm_w(operand) as i16 as i32;
+            self.pending_cycles += Self::mul_div_cycles(operand, 165);
+            let dividend = ((self.registers.dx as u32) << 16 | self.registers.ax as u32) as i32;
+            if divisor == 0 {
+                self.interrupt(0);
+                return;
+            }
+            //El cociente se calcula en 64 bits porque -2^31 / -1 desborda un i32
+            let quotient = dividend as i64 / divisor as i64;
+            if !(-0x7FFF..=0x7FFF).contains(&quotient) {
+                self.interrupt(0);
+                return;
+            }
+            self.registers.ax = quotient as u16;
+            self.registers.dx = (dividend as i64 % divisor as i64) as u16;
+        }else{
+            let divisor = self.read_rm_b(operand) as i8 as i16;
+            self.pending_cycles += Self::mul_div_cycles(operand, 101);
+            let dividend = self.registers.ax as i16;
+            if divisor == 0 {
+                self.interrupt(0);
+                return;
+            }
+            let quotient = dividend as i32 / divisor as i32;
+            if !(-0x7F..=0x7F).contains(&quotient) {
+                self.interrupt(0);
+                return;
+            }
+            let remainder = dividend as i32 % divisor as i32;
+            self.registers.ax = (remainder as u8 as u16) << 8 | quotient as u8 as u16;
+        }
+    }
+
+    //Grupo D0-D3 de rotaciones y desplazamientos
+    // D0 r/m8,1  D1 r/m16,1  D2 r/m8,CL  D3 r/m16,CL
+    // /0 ROL  /1 ROR  /2 RCL  /3 RCR  /4 SHL/SAL  /5 SHR  /6 SETMO (no documentada)  /7 SAR
+    fn shift_rotate(&mut self, instruction: &Instruction){
+        let operand = self.resolve_rm(instruction.operands[0]);
+        let is_word = instruction.opcode & 0x01 != 0;
+        let by_cl = instruction.opcode & 0x02 != 0;
+        let count = if by_cl { self.registers.get_low_byte(self.registers.cx) } else { 1 };
+        let value = if is_word { self.read_rm_w(operand) } else { self.read_rm_b(operand) as u16 };
+        let (new_value, new_flags) = shift_rotate(instruction.reg_field(), value, count, !is_word, self.registers.flags);
+        self.registers.flags = new_flags;
+        if is_word {
+            self.write_rm_w(operand, new_value);
+        }else{
+            self.write_rm_b(operand, new_value as u8);
+        }
+        self.pending_cycles += match (operand, by_cl) {
+            (RmOperand::Register(_), false) => 2,
+            (RmOperand::Memory(..), false) => 15,
+            (RmOperand::Register(_), true) => 8 + 4 * count as u64,
+            (RmOperand::Memory(..), true) => 20 + 4 * count as u64,
+        };
+    }
+
+    //Segmento de los operandos de datos: DS salvo que haya prefijo de segmento
+    fn data_segment(&self)->u16{
+        match self.segment_override{
+            Some(index) => self.get_segment_by_index(index),
+            None => self.registers.ds,
+        }
+    }
+
+    //Instrucciones de cadena, el bit 0 del opcode indica byte o word
+    // A4/A5 MOVS  A6/A7 CMPS  AA/AB STOS  AC/AD LODS  AE/AF SCAS
+    //El origen es DS:SI (admite prefijo de segmento) y el destino siempre ES:DI
+    //Con REP se ejecuta una iteración cada vez y se vuelve a apuntar al opcode si quedan más,
+    //para que se puedan atender interrupciones entre iteraciones como en el 8086
+    fn string(&mut self, opcode: u8){
+        let is_word = opcode & 0x01 != 0;
+        let repeat = self.rep_prefix.is_some();
+        let opcode_ip = self.registers.ip.wrapping_sub(1);
+        if repeat && self.registers.cx == 0 {
+            self.pending_cycles += 9;
+            return;
+        }
+        let size: u16 = if is_word { 2 } else { 1 };
+        let delta = if self.registers.flags & FLAG_DF != 0 { size.wrapping_neg() } else { size };
+        let source_segment = self.data_segment();
+        let (uses_si, uses_di) = match opcode & 0xFE {
+            0xA4 => {
+                if is_word {
+                    let value = self.get_w_from_memory(source_segment, self.registers.si);
+                    self.set_w_in_memory(self.registers.es, self.registers.di, value);
+                }else{
+                    let value = self.get_b_from_memory(source_segment, self.registers.si);
+                    self.set_b_in_memory(self.registers.es, self.registers.di, value);
+                }
+                self.pending_cycles += if repeat { 17 } else { 18 };
+                (true, true)
+            },
+            0xA6 => {
+                if is_word {
+                    let source = self.get_w_from_memory(source_segment, self.registers.si);
+                    let destination = self.get_w_from_memory(self.registers.es, self.registers.di);
+                    let (result, overflow, carry, aux) = sub_16bit_complemento_a2(source, destination, false);
+                    actualizar_flags_sub(&mut self.registers.flags, result, false, overflow, carry, aux);
+                }else{
+                    let source = self.get_b_from_memory(source_segment, self.registers.si);
+                    let destination = self.get_b_from_memory(self.registers.es, self.registers.di);
+                    let (result, overflow, carry, aux) = sub_8bit_complemento_a2(source, destination, false);
+                    actualizar_flags_sub(&mut self.registers.flags, result as u16, true, overflow, carry, aux);
+                }
+                self.pending_cycles += 22;
+                (true, true)
+            },
+            0xAA => {
+                if is_word {
+                    self.set_w_in_memory(self.registers.es, self.registers.di, self.registers.ax);
+                }else{
+                    let al = self.registers.get_low_byte(self.registers.ax);
+                    self.set_b_in_memory(self.registers.es, self.registers.di, al);
+                }
+                self.pending_cycles += if repeat { 10 } else { 11 };
+                (false, true)
+            },
+            0xAC => {
+                if is_word {
+                    self.registers.ax = self.get_w_from_memory(source_segment, self.registers.si);
+                }else{
+                    let value = self.get_b_from_memory(source_segment, self.registers.si);
+                    self.registers.ax = self.registers.write_low_byte(self.registers.ax, value);
+                }
+                self.pending_cycles += if repeat { 13 } else { 12 };
+                (true, false)
+            },
+            _ => {
+                if is_word {
+                    let destination = self.get_w_from_memory(self.registers.es, self.registers.di);
+                    let (result, overflow, carry, aux) = sub_16bit_complemento_a2(self.registers.ax, destination, false);
+                    actualizar_flags_sub(&mut self.registers.flags, result, false, overflow, carry, aux);
+                }else{
+                    let al = self.registers.get_low_byte(self.registers.ax);
+                    let destination = self.get_b_from_memory(self.registers.es, self.registers.di);
+                    let (result, overflow, carry, aux) = sub_8bit_complemento_a2(al, destination, false);
+                    actualizar_flags_sub(&mut self.registers.flags, result as u16, true, overflow, carry, aux);
+                }
+                self.pending_cycles += 15;
+                (false, true)
+            },
+        };
+        if uses_si {
+            self.registers.si = self.registers.si.wrapping_add(delta);
+        }
+        if uses_di {
+            self.registers.di = self.registers.di.wrapping_add(delta);
+        }
+        if let Some(prefix) = self.rep_prefix {
+            self.registers.cx = self.registers.cx.wrapping_sub(1);
+            //CMPS y SCAS terminan también según ZF: REPE (F3) mientras ZF=1 y REPNE (F2) mientras ZF=0
+            let compares = matches!(opcode & 0xFE, 0xA6 | 0xAE);
+            let zf = self.registers.flags & FLAG_ZF != 0;
+            let stop_by_zf = compares && (zf != (prefix == 0xF3));
+            if self.registers.cx != 0 && !stop_by_zf {
+                self.rep_pending = true;
+                self.registers.ip = opcode_ip;
+            }
+        }
+    }
+
+    //Grupo FF, el campo reg del ModRM selecciona la operación
+    // /0 INC r/m16  /1 DEC r/m16  /2 CALL near indirecto  /3 CALL far indirecto  /4 JMP near indirecto
+    // /5 JMP far indirecto  /6 PUSH r/m16
+    fn group_ff(&mut self, instruction: &Instruction)->Result<(), EmulatorError>{
+        let operand = self.resolve_rm(instruction.operands[0]);
+        match instruction.mnemonic{
+            Mnemonic::Inc | Mnemonic::Dec => {
+                let value = self.read_rm_w(operand);
+                let new_value = self.inc_dec(value, instruction.mnemonic == Mnemonic::Dec, true);
+                self.write_rm_w(operand, new_value);
+                self.pending_cycles += match operand { RmOperand::Register(_) => 3, RmOperand::Memory(..) => 15 };
+            },
+            Mnemonic::Call => {
+                let target = self.read_rm_w(operand);
+                self.push(self.registers.ip);
+                self.registers.ip = target;
+                self.pending_cycles += match operand { RmOperand::Register(_) => 16, RmOperand::Memory(..) => 21 };
+            },
+            Mnemonic::CallFar => {
+                let RmOperand::Memory(segment, offset) = operand else {
+                    return Err(self.unimplemented_opcode());
+                };
+                let target_ip = self.get_w_from_memory(segment, offset);
+                let target_cs = self.get_w_from_memory(segment, offset.wrapping_add(2));
+                self.push(self.registers.cs);
+                self.push(self.registers.ip);
+                self.registers.ip = target_ip;
+                self.registers.cs = target_cs;
+                self.pending_cycles += 37;
+            },
+            Mnemonic::Jmp => {
+                self.registers.ip = self.read_rm_w(operand);
+                self.pending_cycles += match operand { RmOperand::Register(_) => 11, RmOperand::Memory(..) => 18 };
+            },
+            Mnemonic::JmpFar => {
+                //JMP far a la dirección m16:16, con un registro como operando no hay dirección que leer
+                let RmOperand::Memory(segment, offset) = operand else {
+                    return Err(self.unimplemented_opcode());
+                };
+                self.registers.ip = self.get_w_from_memory(segment, offset);
+                self.registers.cs = self.get_w_from_memory(segment, offset.wrapping_add(2));
+                self.pending_cycles += 24;
+            },
+            Mnemonic::Push => {
+                let value = self.read_rm_w(operand);
+                self.push(value);
+                self.pending_cycles += match operand { RmOperand::Register(_) => 11, RmOperand::Memory(..) => 16 };
+            },
+            _ => return Err(self.unimplemented_opcode()),
+        }
+        Ok(())
+    }
+
+    //Decodifica el ModRM de LEA, LDS y LES devolviendo el registro destino y la dirección efectiva.
+    //Con mod 11 no hay dirección que calcular: el 8086 no lo detecta y usa la última dirección
+    //efectiva que calculó, así que se reutiliza la de la instrucción anterior con operando en memoria
+    fn decode_address_operand(&mut self, instruction: &Instruction)->(u8, u16, u16){
+        let (segment, offset) = match self.resolve_rm(instruction.operands[1]){
+            RmOperand::Memory(segment, offset) => (segment, offset),
+            RmOperand::Register(_) => self.last_effective_address,
+        };
+        (instruction.reg_field(), segment, offset)
+    }
+
+    //LEA Load effective address, guarda el offset sin acceder a memoria
+    fn lea(&mut self, instruction: &Instruction){
+        let (reg_field, _, offset) = self.decode_address_operand(instruction);
+        self.write_reg_w(reg_field, offset);
+        self.pending_cycles += 2;
+    }
+
+    //LES (C4) y LDS (C5) cargan un puntero lejano offset:segmento de memoria en reg y ES o DS
+    fn load_far_pointer(&mut self, instruction: &Instruction){
+        let (reg_field, segment, offset) = self.decode_address_operand(instruction);
+        let pointer_offset = self.get_w_from_memory(segment, offset);
+        let pointer_segment = self.get_w_from_memory(segment, offset.wrapping_add(2));
+        self.write_reg_w(reg_field, pointer_offset);
+        if instruction.opcode == 0xC4 {
+            self.registers.es = pointer_segment;
+        }else{
+            self.registers.ds = pointer_segment;
+        }
+        self.pending_cycles += 16;
+    }
+
+    //XCHG r/m,reg (86 byte, 87 word), el orden de los operandos no importa
+    fn xchg_rm(&mut self, instruction: &Instruction){
+        let operand = self.resolve_rm(instruction.operands[0]);
+        let reg_field = instruction.reg_field();
+        if instruction.opcode == 0x86 {
+            let reg_value = self.read_reg_b(reg_field);
+            let rm_value = self.read_rm_b(operand);
+            self.write_rm_b(operand, reg_value);
+            self.write_reg_b(reg_field, rm_value);
+        }else{
+            let reg_value = self.read_reg_w(reg_field);
+            let rm_value = self.read_rm_w(operand);
+            self.write_rm_w(operand, reg_value);
+            self.write_reg_w(reg_field, rm_value);
+        }
+        self.pending_cycles += match operand { RmOperand::Register(_) => 4, RmOperand::Memory(..) => 17 };
+    }
+
+    //XCHG AX,r16 (91-97). 90 sería XCHG AX,AX y se trata como NOP
+    fn xchg_ax(&mut self, opcode: u8){
+        let index = opcode & 0x07;
+        let value = self.read_reg_w(index);
+        self.write_reg_w(index, self.registers.ax);
+        self.registers.ax = value;
+        self.pending_cycles += 3;
+    }
+
+    //Instrucciones de control de flags
+    // F5 CMC  F8 CLC  F9 STC  FA CLI  FB STI  FC CLD  FD STD
+    fn flag_control(&mut self, opcode: u8){
+        match opcode{
+            0xF5 => self.registers.flags ^= FLAG_CF,
+            0xF8 => self.registers.flags &= !FLAG_CF,
+            0xF9 => self.registers.flags |= FLAG_CF,
+            0xFA => self.registers.flags &= !FLAG_IF,
+            0xFB => {
+                //Como en el 8086 las interrupciones no se atienden hasta después de la instrucción
+                //siguiente a STI, así STI ; HLT no pierde la interrupción
+                self.registers.flags |= FLAG_IF;
+                self.interrupt_shadow = true;
+            },
+            0xFC => self.registers.flags &= !FLAG_DF,
+            _ => self.registers.flags |= FLAG_DF,
+        }
+        self.pending_cycles += 2;
+    }
+
+    //HLT para el procesador, IP queda apuntando a la instrucción siguiente
+    fn hlt(&mut self){
+        self.halted = true;
+        self.pending_cycles += 2;
+    }
+
+    //ESC (D8-DF) pasa la instrucción al coprocesador. Sin coprocesador solo se decodifica el ModRM
+    //para consumir el desplazamiento, y con operando en memoria el 8086 hace la lectura igualmente
+    fn esc(&mut self, instruction: &Instruction){
+        match self.resolve_rm(instruction.operands[1]){
+            RmOperand::Register(_) => self.pending_cycles += 2,
+            RmOperand::Memory(segment, offset) => {
+                let _ = self.get_w_from_memory(segment, offset);
+                self.pending_cycles += 8;
+            },
+        }
+    }
+
+    //Pila: siempre se accede a través de SS:SP y crece hacia direcciones bajas
+    pub fn push(&mut self, value: u16){
+        self.registers.sp = self.registers.sp.wrapping_sub(2);
+        self.set_w_in_memory(self.registers.ss, self.registers.sp, value);
+    }
+
+    pub fn pop(&mut self)->u16{
+        let value = self.get_w_from_memory(self.registers.ss, self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(2);
+        value
+    }
+
+    //PUSH r16 (50-57)
+    fn push_register(&mut self, opcode: u8){
+        let index = opcode & 0x07;
+        if index == 0b100 {
+            //En el 8086 PUSH SP guarda el valor de SP ya decrementado (a partir del 80286 guarda el original)
+            self.registers.sp = self.registers.sp.wrapping_sub(2);
+            self.set_w_in_memory(self.registers.ss, self.registers.sp, self.registers.sp);
+        }else{
+            let value = self.read_reg_w(index);
+            self.push(value);
+        }
+        self.pending_cycles += 11;
+    }
+
+    //POP r16 (58-5F), POP SP deja en SP el valor leído de la pila
+    fn pop_register(&mut self, opcode: u8){
+        let value = self.pop();
+        self.write_reg_w(opcode & 0x07, value);
+        self.pending_cycles += 8;
+    }
+
+    //Registro de segmento codificado en los bits 3-4 del opcode o en el campo reg del ModRM
+    // 00 ES  01 CS  10 SS  11 DS
+    fn get_segment_by_index(&self, index: u8)->u16{
+        match index & 0x03{
+            0b00 => self.registers.es,
+            0b01 => self.registers.cs,
+            0b10 => self.registers.ss,
+            _ => self.registers.ds,
+        }
+    }
+
+    fn write_segment_by_index(&mut self, index: u8, value: u16){
+        match index & 0x03{
+            0b00 => self.registers.es = value,
+            0b01 => self.registers.cs = value,
+            0b10 => self.registers.ss = value,
+            _ => self.registers.ds = value,
+        }
+    }
+
+    //PUSH ES (06), PUSH CS (0E), PUSH SS (16), PUSH DS (1E)
+    fn push_segment(&mut self, opcode: u8){
+        let value = self.get_segment_by_index(opcode >> 3);
+        self.push(value);
+        self.pending_cycles += 10;
+    }
+
+    //POP ES (07), POP CS (0F), POP SS (17), POP DS (1F)
+    //POP CS solo existe en el 8086/8088 (en el 80186 0F pasa a ser un prefijo), salta a CS:IP sin cambiar IP
+    fn pop_segment(&mut self, opcode: u8){
+        let value = self.pop();
+        self.write_segment_by_index(opcode >> 3, value);
+        self.interrupt_shadow = true;
+        self.pending_cycles += 8;
+    }
+
+    //POP r/m16 (8F /0)
+    fn pop_rm(&mut self, instruction: &Instruction){
+        let operand = self.resolve_rm(instruction.operands[0]);
+        let value = self.pop();
+        self.write_rm_w(operand, value);
+        self.pending_cycles += match operand { RmOperand::Register(_) => 8, RmOperand::Memory(..) => 17 };
+    }
+
+    //PUSHF, los bits reservados se guardan a 1 como en el 8086
+    fn pushf(&mut self){
+        let value = self.registers.flags | FLAGS_RESERVED;
+        self.push(value);
+        self.pending_cycles += 10;
+    }
+
+    //POPF, solo se cargan los bits de flags que existen
+    fn popf(&mut self){
+        let value = self.pop();
+        self.registers.flags = value & FLAGS_MASK;
+        self.pending_cycles += 8;
+    }
+
+    //Evalua la condición de un salto condicional según los 4 bits bajos del opcode (70-7F)
+    pub fn condition(&self, code: u8)->bool{
+        let flags = self.registers.flags;
+        let cf = flags & FLAG_CF != 0;
+        let zf = flags & FLAG_ZF != 0;
+        let sf = flags & FLAG_SF != 0;
+        let of = flags & FLAG_OF != 0;
+        let pf = flags & FLAG_PF != 0;
+        //Los códigos pares comprueban la condición y los impares su negación
+        let result = match code >> 1 {
+            0b000 => of,              // JO / JNO
+            0b001 => cf,              // JB / JNB
+            0b010 => zf,              // JZ / JNZ
+            0b011 => cf || zf,        // JBE / JA
+            0b100 => sf,              // JS / JNS
+            0b101 => pf,              // JP / JNP
+            0b110 => sf != of,        // JL / JGE
+            _ => zf || (sf != of),    // JLE / JG
+        };
+        result != (code & 0x01 != 0)
+    }
+
+    //Suma a IP el desplazamiento de un salto relativo, ya extendido con signo por el decodificador
+    fn jump_relative(&mut self, displacement: u16){
+        self.registers.ip = self.registers.ip.wrapping_add(displacement);
+    }
+
+    //Jcc Saltos condicionales cortos (70-7F)
+    fn jcc(&mut self, instruction: &Instruction){
+        if self.condition(instruction.opcode & 0x0F) {
+            self.jump_relative(Self::operand_value(instruction.operands[0]));
+            self.pending_cycles += 16;
+        }else{
+            self.pending_cycles += 4;
+        }
+    }
+
+    //JMP
+    // E9 near con desplazamiento de 16 bits, EA far a seg:off inmediato, EB short
+    fn jmp(&mut self, instruction: &Instruction){
+        match instruction.operands[0]{
+            Operand::Far { segment, offset } => {
+                self.registers.ip = offset;
+                self.registers.cs = segment;
+            },
+            operand => self.jump_relative(Self::operand_value(operand)),
+        }
+        self.pending_cycles += 15;
+    }
+
+    //CALL
+    // E8 near con desplazamiento de 16 bits, 9A far a seg:off inmediato
+    //Se guarda en la pila la dirección de la instrucción siguiente
+    fn call(&mut self, instruction: &Instruction){
+        match instruction.operands[0]{
+            Operand::Far { segment, offset } => {
+                self.push(self.registers.cs);
+                self.push(self.registers.ip);
+                self.registers.ip = offset;
+                self.registers.cs = segment;
+                self.pending_cycles += 28;
+            },
+            operand => {
+                self.push(self.registers.ip);
+                self.jump_relative(Self::operand_value(operand));
+                self.pending_cycles += 19;
+            },
+        }
+    }
+
+    //RET near (C3) y RET imm16 (C2), que además libera imm16 bytes de parámetros de la pila
+    fn ret(&mut self, instruction: &Instruction){
+        let release = Self::operand_value(instruction.operands[0]);
+        self.registers.ip = self.pop();
+        self.registers.sp = self.registers.sp.wrapping_add(release);
+        self.pending_cycles += if instruction.opcode == 0xC2 { 12 } else { 8 };
+    }
+
+    //RETF (CB) y RETF imm16 (CA)
+    fn retf(&mut self, instruction: &Instruction){
+        let release = Self::operand_value(instruction.operands[0]);
+        self.registers.ip = self.pop();
+        self.registers.cs = self.pop();
+        self.registers.sp = self.registers.sp.wrapping_add(release);
+        self.pending_cycles += if instruction.opcode == 0xCA { 17 } else { 18 };
+    }
+
+    //IRET recupera IP, CS y las flags en ese orden
+    fn iret(&mut self){
+        self.registers.ip = self.pop();
+        self.registers.cs = self.pop();
+        self.registers.flags = self.pop() & FLAGS_MASK;
+        self.pending_cycles += 24;
+    }
+
+    //Secuencia de interrupción del 8086: guarda FLAGS, CS e IP, limpia IF y TF
+    //y salta a la dirección del vector n de la tabla de interrupciones (0000:n*4)
+    //Si se interrumpe un REP a medias el 8086 guarda la dirección del último prefijo en lugar de la del primero,
+    //así que al volver con IRET se pierden el resto de prefijos (por ejemplo el de segmento en ES: REP MOVSB)
+    //Si el host intercepta el vector no se toca la pila ni CS:IP, el programa sigue como si volviera con IRET
+    pub fn interrupt(&mut self, vector: u8){
+        self.pending_cycles += 51;
+        if self.run_interrupt_hook(vector) {
+            self.halted = false;
+            return;
+        }
+        if self.rep_pending {
+            self.rep_pending = false;
+            self.registers.ip = self.registers.ip.wrapping_sub(1);
+        }
+        //Una interrupción saca al procesador del estado HLT, IP ya apunta a la instrucción siguiente
+        self.halted = false;
+        self.push(self.registers.flags | FLAGS_RESERVED);
+        self.push(self.registers.cs);
+        self.push(self.registers.ip);
+        self.registers.flags &= !(FLAG_IF | FLAG_TF);
+        let vector_offset = (vector as u16) * 4;
+        self.registers.ip = self.get_w_from_memory(0x0000, vector_offset);
+        self.registers.cs = self.get_w_from_memory(0x0000, vector_offset + 2);
+    }
+
+    //INT imm8 (CD), INT 3 (CC, un solo byte para los breakpoints) e INTO (CE, INT 4 si OF está activa)
+    fn int(&mut self, instruction: &Instruction){
+        match instruction.opcode{
+            0xCC => {
+                self.interrupt(3);
+                self.pending_cycles += 1;
+            },
+            0xCD => {
+                let vector = Self::operand_value(instruction.operands[0]) as u8;
+                self.interrupt(vector);
+            },
+            _ => {
+                if self.registers.flags & FLAG_OF != 0 {
+                    self.interrupt(4);
+                    self.pending_cycles += 2;
+                }else{
+                    self.pending_cycles += 4;
+                }
+            },
+        }
+    }
+
+    //LOOPNZ (E0), LOOPZ (E1), LOOP (E2) y JCXZ (E3)
+    //Los LOOP decrementan CX sin modificar las flags antes de comprobar la condición
+    fn loop_cx(&mut self, instruction: &Instruction){
+        let opcode = instruction.opcode;
+        let zf = self.registers.flags & FLAG_ZF != 0;
+        if opcode != 0xE3 {
+            self.registers.cx = self.registers.cx.wrapping_sub(1);
+        }
+        let cx_not_zero = self.registers.cx != 0;
+        let (taken, cycles_taken, cycles_not_taken) = match opcode{
+            0xE0 => (cx_not_zero && !zf, 19, 5),
+            0xE1 => (cx_not_zero && zf, 18, 6),
+            0xE2 => (cx_not_zero, 17, 5),
+            _ => (!cx_not_zero, 18, 6),
+        };
+        if taken {
+            self.jump_relative(Self::operand_value(instruction.operands[0]));
+            self.pending_cycles += cycles_taken;
+        }else{
+            self.pending_cycles += cycles_not_taken;
+        }
+    }
+
+    //Instrucciones de mov
+    // MOV     rb,rmb      8A mr d0 d1    B   2~4    --------
+    // MOV     rmb,rb      88 mr d0 d1    B   2~4    --------
+    // MOV     rmw,rw      89 mr d0 d1    W   2~4    --------
+    // MOV     rw,rmw      8B mr d0 d1    W   2~4    --------
+    // MOV     rmw,sr      8C mr d0 d1        2~4    --------
+    // MOV     sr,rmw      8E mr d0 d1        2~4    --------
+    // MOV     AL,rmb      A0 d0 d1      B   3    --------
+    // MOV     AX,rmw      A1 d0 d1      W   3    --------
+    // MOV     rmb,AL      A2 d0 d1      B   3    --------
+    // MOV     rmw,AX      A3 d0 d1      W   3    --------
+    // MOV     AL,ib       B0 i0         B   2    --------
+    // MOV     CL,ib       B1 i0         B   2    --------
+    // MOV     DL,ib       B2 i0         B   2    --------
+    // MOV     BL,ib       B3 i0         B   2    --------
+    // MOV     AH,ib       B4 i0         B   2    --------
+    // MOV     CH,ib       B5 i0         B   2    --------
+    // MOV     DH,ib       B6 i0         B   2    --------
+    // MOV     BH,ib       B7 i0         B   2    --------
+    // MOV     AX,iw       B8 i0 i1      W   3    --------
+    // MOV     CX,iw       B9 i0 i1      W   3    --------
+    // MOV     DX,iw       BA i0 i1      W   3    --------
+    // MOV     BX,iw       BB i0 i1      W   3    --------
+    // MOV     SP,iw       BC i0 i1      W   3    --------
+    // MOV     BP,iw       BD i0 i1      W   3    --------
+    // MOV     SI,iw       BE i0 i1      W   3    --------
+    // MOV     DI,iw       BF i0 i1      W   3    --------
+    // MOV     rmb,ib      C6 mr d0 d1 i0 B   3~5    --------
+    // MOV     rmw,iw      C7 mr d0 d1 i0 i1   W   4~6    --------
+    fn mov(&mut self, instruction: &Instruction){
+        let opcode = instruction.opcode;
+        let [destination, source] = instruction.operands;
+        match opcode{
+            0x8C => {
+                //MOV r/m,sr
+                let operand = self.resolve_rm(destination);
+                let value = self.get_segment_by_index(instruction.reg_field());
+                self.write_rm_w(operand, value);
+                self.pending_cycles += match operand { RmOperand::Register(_) => 2, RmOperand::Memory(..) => 9 };
+            },
+            0x8E => {
+                //MOV sr,r/m. MOV CS,r/m existe en el 8086 (desaparece en el 80186) y actúa como un salto
+                //a CS:IP con el IP de la instrucción siguiente
+                let operand = self.resolve_rm(source);
+                let value = self.read_rm_w(operand);
+                self.write_segment_by_index(instruction.reg_field(), value);
+                self.interrupt_shadow = true;
+                self.pending_cycles += match operand { RmOperand::Register(_) => 2, RmOperand::Memory(..) => 8 };
+            },
+            0xA0..=0xA3 => {
+                //MOV entre el acumulador y una dirección directa de 16 bits (admite prefijo de segmento)
+                let offset = Self::operand_value(if opcode < 0xA2 { source } else { destination });
+                let segment = self.data_segment();
+                match opcode{
+                    0xA0 => {
+                        let value = self.get_b_from_memory(segment, offset);
+                        self.registers.ax = self.registers.write_low_byte(self.registers.ax, value);
+                    },
+                    0xA1 => self.registers.ax = self.get_w_from_memory(segment, offset),
+                    0xA2 => {
+                        let al = self.registers.get_low_byte(self.registers.ax);
+                        self.set_b_in_memory(segment, offset, al);
+                    },
+                    _ => self.set_w_in_memory(segment, offset, self.registers.ax),
+                }
+                self.pending_cycles += 10;
+            },
+            0xB0..=0xBF => {
+                //MOV reg,imm, el registro va en los 3 bits bajos del opcode y el bit 3 indica word
+                let value = Self::operand_value(source);
+                let is_word = opcode & 0x08 != 0;
+                self.write_rm(RmOperand::Register(opcode & 0x07), value, is_word);
+                self.pending_cycles += if is_word { 3 } else { 2 };
+            },
+            0xC6 | 0xC7 => {
+                //MOV r/m,imm: el inmediato va después del desplazamiento. El 8086 ignora el campo reg
+                let operand = self.resolve_rm(destination);
+                self.write_rm(operand, Self::operand_value(source), opcode == 0xC7);
+                self.pending_cycles += match operand { RmOperand::Register(_) => 4, RmOperand::Memory(..) => 10 };
+            },
+            _ => {
+                //88-8B MOV r/m,reg y MOV reg,r/m
+                let is_word = opcode & 0x01 != 0;
+                let destination = self.resolve_rm(destination);
+                let source = self.resolve_rm(source);
+                let value = self.read_rm(source, is_word);
+                self.write_rm(destination, value, is_word);
+                self.pending_cycles += match (destination, source) {
+                    (RmOperand::Memory(..), _) => 9,
+                    (_, RmOperand::Memory(..)) => 8,
+                    _ => 2,
+                };
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::emulator::testing::run_asm;
+
+    //Carga el programa en memoria como un .com
+    fn load_program(program: &[u8])->Emulator8086{
+        let mut emulator = Emulator8086::new();
+        emulator.load_com_bytes(program).unwrap();
+        emulator
+    }
+
+    //Ejecuta el programa cargado hasta que termine o se pare con HLT
+    fn run_program(emulator: &mut Emulator8086){
+        match emulator.run(1_000_000) {
+            StopReason::Exited(_) | StopReason::Halted => {},
+            motivo => panic!("El programa no ha terminado: {:?}", motivo),
+        }
+    }
+
+    //Offset de datos usado por los tests de memoria (DS:0x0200)
+    const DATA: u16 = 0x0200;
+
+    #[test]
+    fn start_emulator(){
+        let emulator = Emulator8086::new();
+        assert_eq!(emulator.registers.ax, 0);
+        assert_eq!(emulator.registers.bx, 0);
+        assert!(emulator.memory.iter().all(|&byte| byte == 0));
+        assert_eq!(emulator.pending_cycles, 0);
+    }
+
+    #[test]
+    fn load_com(){
+        let mut emulator = Emulator8086::new();
+        if let Err(e) = emulator.load_com("./tests/load_com_test.com") {
+            panic!("Error al cargar el programa: {:?}", e);
+        }
+        assert_eq!(emulator.memory[COM_START], 0x05);
+        assert_eq!(emulator.memory[COM_START + 1], 0x0F);
+        assert_eq!(emulator.memory[COM_START + 2], 0x00);
+        assert_eq!(emulator.memory[COM_START + 3], 0xC3);
+    }
+
+    #[test]
+    fn test_load_com_reinicia_el_procesador(){
+        //Emulador usado por un .exe que ha terminado y con un HLT y un REP a medias
+        let mut emulator = Emulator8086::new();
+        emulator.load_exe_bytes(&exe_program(), 0x0800).unwrap();
+        run_program(&mut emulator);
+        emulator.halted = true;
+        emulator.rep_pending = true;
+        emulator.segment_override = Some(0);
+        //MOV AX,4C07 ; INT 21
+        emulator.load_com_bytes(&[0xB8, 0x07, 0x4C, 0xCD, 0x21]).unwrap();
+        assert_eq!((emulator.registers.cs, emulator.registers.ip), (PSP_SEGMENT, 0x0100));
+        assert_eq!((emulator.registers.ss, emulator.registers.sp), (PSP_SEGMENT, 0xFFFE));
+        assert_eq!((emulator.registers.ds, emulator.registers.es), (PSP_SEGMENT, PSP_SEGMENT));
+        assert!(!emulator.halted && !emulator.terminated && !emulator.rep_pending);
+        assert_eq!(emulator.segment_override, None);
+        assert_eq!(emulator.exit_code, 0);
+        run_program(&mut emulator);
+        assert_eq!(emulator.exit_code, 0x07);
+    }
+
+    //Ejecutable MZ con la imagen y las relocalizaciones (offset, segmento) indicadas. Pide 0x10 párrafos
+    //como mínimo y toda la memoria como máximo, la pila en 0002:0100 y la entrada en 0000:0000
+    fn mz(image: &[u8], relocations: &[(u16, u16)])->Vec<u8>{
+        let header_paragraphs = (MZ_HEADER_SIZE + relocations.len() * 4).div_ceil(16);
+        let file_size = header_paragraphs * 16 + image.len();
+        let mut exe = vec![0; header_paragraphs * 16];
+        let mut write = |offset: usize, value: u16| exe[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        write(0x00, u16::from_le_bytes(*b"MZ"));
+        write(0x02, (file_size % 512) as u16);
+        write(0x04, file_size.div_ceil(512) as u16);
+        write(0x06, relocations.len() as u16);
+        write(0x08, header_paragraphs as u16);
+        write(0x0A, 0x0010);
+        write(0x0C, 0xFFFF);
+        write(0x0E, 0x0002);
+        write(0x10, 0x0100);
+        write(0x18, MZ_HEADER_SIZE as u16);
+        for (i, (offset, segment)) in relocations.iter().enumerate() {
+            write(MZ_HEADER_SIZE + i * 4, *offset);
+            write(MZ_HEADER_SIZE + i * 4 + 2, *segment);
+        }
+        exe.extend_from_slice(image);
+        exe
+    }
+
+    //MOV AX,SEG datos ; MOV DS,AX ; MOV AL,[0] ; MOV AH,4C ; INT 21 con los datos en el párrafo 1 de la imagen
+    fn exe_program()->Vec<u8>{
+        let mut image = vec![0xB8, 0x01, 0x00, 0x8E, 0xD8, 0xA0, 0x00, 0x00, 0xB4, 0x4C, 0xCD, 0x21, 0x00, 0x00, 0x00, 0x00];
+        image.push(0x2A);
+        mz(&image, &[(0x0001, 0x0000)])
+    }
+
+    #[test]
+    fn test_load_exe(){
+        let mut emulator = Emulator8086::new();
+        emulator.load_exe_bytes(&exe_program(), 0x0700).unwrap();
+        //La imagen empieza detrás del PSP, en 0710:0000
+        assert_eq!((emulator.registers.cs, emulator.registers.ip), (0x0710, 0x0000));
+        assert_eq!((emulator.registers.ss, emulator.registers.sp), (0x0712, 0x0100));
+        assert_eq!((emulator.registers.ds, emulator.registers.es), (0x0700, 0x0700));
+        assert_eq!(emulator.get_w_from_memory(0x0710, 0x0001), 0x0711);
+        //PSP: INT 20h, final de la memoria reservada (toda la convencional) y línea de órdenes vacía
+        assert_eq!(emulator.get_w_from_memory(0x0700, 0x0000), 0x20CD);
+        assert_eq!(emulator.get_w_from_memory(0x0700, 0x0002), MEMORY_TOP);
+        assert_eq!(emulator.get_b_from_memory(0x0700, 0x0081), 0x0D);
+        run_program(&mut emulator);
+        assert_eq!(emulator.exit_code, 0x2A);
+        assert_eq!(emulator.registers.ds, 0x0711);
+    }
+
+    #[test]
+    fn test_load_exe_en_otro_segmento_con_memoria_maxima(){
+        let mut exe = exe_program();
+        //Como mucho 0x20 párrafos detrás de la imagen
+        exe[0x0C..0x0E].copy_from_slice(&0x0020u16.to_le_bytes());
+        let mut emulator = Emulator8086::new();
+        emulator.load_exe_bytes(&exe, 0x2000).unwrap();
+        assert_eq!(emulator.registers.cs, 0x2010);
+        assert_eq!(emulator.get_w_from_memory(0x2010, 0x0001), 0x2011);
+        assert_eq!(emulator.get_w_from_memory(0x2000, 0x0002), 0x2010 + 2 + 0x20);
+        run_program(&mut emulator);
+        assert_eq!(emulator.exit_code, 0x2A);
+    }
+
+    #[test]
+    fn test_load_exe_cabeceras_no_validas(){
+        let error = |exe: &[u8]| match Emulator8086::new().load_exe_bytes(exe, 0x0700) {
+            Err(EmulatorError::InvalidExe(reason)) => reason,
+            resultado => panic!("Se esperaba un error de ejecutable: {:?}", resultado.map(|_| ())),
+        };
+        let exe = exe_program();
+        assert!(error(&exe[..0x10]).contains("menos que la cabecera"));
+        let mut sin_firma = exe.clone();
+        sin_firma[0] = b'X';
+        assert_eq!(error(&sin_firma), "falta la firma MZ");
+        assert!(error(&exe[..exe.len() - 1]).contains("la cabecera indica"));
+        let mut cabecera_corta = exe.clone();
+        cabecera_corta[0x08] = 1;
+        assert!(error(&cabecera_corta).contains("tamaño de cabecera"));
+        let mut relocalizacion = exe.clone();
+        relocalizacion[MZ_HEADER_SIZE] = 0x10;
+        assert!(error(&relocalizacion).contains("apunta fuera de la imagen"));
+        let mut sin_memoria = exe.clone();
+        sin_memoria[0x0A..0x0C].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        assert!(error(&sin_memoria).contains("párrafos"));
+        //Sin memoria por encima del segmento de carga, aunque la imagen esté vacía y no pida memoria
+        let mut vacio = mz(&[], &[]);
+        vacio[0x0A..0x0C].copy_from_slice(&0u16.to_le_bytes());
+        for segmento in [0x9FF0, 0xA000, 0xFFF1, 0xFFFF] {
+            let resultado = Emulator8086::new().load_exe_bytes(&vacio, segmento);
+            assert!(matches!(resultado, Err(EmulatorError::InvalidExe(reason)) if reason.contains("segmento de carga")));
         }
-        assert_eq!(emulator.memory[COM_START], 0x05);
-        assert_eq!(emulator.memory[COM_START + 1], 0x0F);
-        assert_eq!(emulator.memory[COM_START + 2], 0x00);
-        assert_eq!(emulator.memory[COM_START + 3], 0xC3);
     }
 
     #[test]
@@ -603,11 +1941,10 @@ mod tests{
         if let Err(e) = emulator.load_com("./tests/mov/MOV_LOW_REG.com") {
             panic!("Error al cargar el programa: {:?}", e);
         }
-        let mut instruction = emulator.fetch(); //Primera instruccion
-        while instruction != 0xc3 {
-            emulator.decode_and_execute(instruction);
+        while !emulator.terminated {
+            let instruction = emulator.fetch();
+            emulator.decode_and_execute(instruction).unwrap();
             emulator.imprimir_estado_registros();
-            instruction = emulator.fetch();
         }
         //Test mov de inmediatos a registros bajos
         assert_eq!(emulator.registers.ax, 0x0011);
@@ -622,11 +1959,10 @@ mod tests{
         if let Err(e) = emulator.load_com("./tests/mov/MOV_HIGH_REG.com") {
             panic!("Error al cargar el programa: {:?}", e);
         }
-        let mut instruction = emulator.fetch(); //Primera instruccion
-        while instruction != 0xc3 {
-            emulator.decode_and_execute(instruction);
+        while !emulator.terminated {
+            let instruction = emulator.fetch();
+            emulator.decode_and_execute(instruction).unwrap();
             emulator.imprimir_estado_registros();
-            instruction = emulator.fetch();
         }
         //Test mov de inmediatos a registros altos
         assert_eq!(emulator.registers.ax, 0x1100);
@@ -634,4 +1970,1891 @@ mod tests{
         assert_eq!(emulator.registers.cx, 0x1100);
         assert_eq!(emulator.registers.dx, 0x1100);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_sub_rmb_rb(){
+        //SUB [BX],AL
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xB0, 0x01, 0x28, 0x07, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0x10);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0x0F);
+        assert_ne!(emulator.registers.flags & FLAG_AF, 0);
+        assert_eq!(emulator.registers.flags & (FLAG_CF | FLAG_ZF | FLAG_SF | FLAG_OF), 0);
+    }
+
+    #[test]
+    fn test_sub_rmw_rw(){
+        //SUB AX,BX con AX=5 y BX=7
+        let mut emulator = load_program(&[0xB8, 0x05, 0x00, 0xBB, 0x07, 0x00, 0x29, 0xD8, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0xFFFE);
+        assert_eq!(emulator.registers.bx, 0x0007);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+        assert_eq!(emulator.registers.flags & (FLAG_ZF | FLAG_OF), 0);
+    }
+
+    #[test]
+    fn test_sub_rb_rmb(){
+        //SUB AL,[BX+2]
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xB0, 0x30, 0x2A, 0x47, 0x02, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA + 2, 0x30);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0000);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_PF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_sub_rw_rmw(){
+        //SUB CX,[SI+0x0100] con desplazamiento de 16 bits
+        let mut emulator = load_program(&[0xBE, 0x00, 0x01, 0xB9, 0x00, 0x10, 0x2B, 0x8C, 0x00, 0x01, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0x0001);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 0x0FFF);
+        assert_ne!(emulator.registers.flags & FLAG_AF, 0);
+        assert_eq!(emulator.registers.flags & (FLAG_CF | FLAG_OF), 0);
+    }
+
+    #[test]
+    fn test_sub_al_ib(){
+        //SUB AL,1 con AL=0x80 desborda
+        let mut emulator = load_program(&[0xB0, 0x80, 0x2C, 0x01, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x007F);
+        assert_ne!(emulator.registers.flags & FLAG_OF, 0);
+        assert_eq!(emulator.registers.flags & (FLAG_CF | FLAG_SF), 0);
+    }
+
+    #[test]
+    fn test_sub_ax_iw(){
+        let mut emulator = load_program(&[0xB8, 0x34, 0x12, 0x2D, 0x34, 0x02, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x1000);
+        assert_eq!(emulator.registers.flags & (FLAG_CF | FLAG_ZF | FLAG_OF | FLAG_AF), 0);
+    }
+
+    #[test]
+    fn test_sbb_rmb_rb(){
+        //SBB [BX],AL con CF=1
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xB0, 0x01, 0x18, 0x07, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0x02);
+        emulator.registers.flags |= FLAG_CF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0x00);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_sbb_rmw_rw(){
+        //SBB DX,CX: parte alta de una resta de 32 bits tras un préstamo
+        let mut emulator = load_program(&[0xBA, 0x00, 0x00, 0xB9, 0x00, 0x00, 0x19, 0xCA, 0xC3]);
+        emulator.registers.flags |= FLAG_CF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.dx, 0xFFFF);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+    }
+
+    #[test]
+    fn test_sbb_rb_rmb(){
+        //SBB BL,[DI]
+        let mut emulator = load_program(&[0xBF, 0x00, 0x02, 0xB3, 0x10, 0x1A, 0x1D, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0x05);
+        emulator.registers.flags |= FLAG_CF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 0x000A);
+        assert_ne!(emulator.registers.flags & FLAG_AF, 0);
+    }
+
+    #[test]
+    fn test_sbb_rw_rmw(){
+        //SBB AX,[0x0200] con dirección directa
+        let mut emulator = load_program(&[0xB8, 0x00, 0x80, 0x1B, 0x06, 0x00, 0x02, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0x0000);
+        emulator.registers.flags |= FLAG_CF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x7FFF);
+        assert_ne!(emulator.registers.flags & FLAG_OF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_sbb_al_ib(){
+        let mut emulator = load_program(&[0xB0, 0x10, 0x1C, 0x0F, 0xC3]);
+        emulator.registers.flags |= FLAG_CF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0000);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+    }
+
+    #[test]
+    fn test_sbb_ax_iw(){
+        //Sin CF se comporta como SUB
+        let mut emulator = load_program(&[0xB8, 0x00, 0x00, 0x1D, 0x01, 0x00, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0xFFFF);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_cmp_rmb_rb(){
+        //CMP [BX-1],AH con desplazamiento negativo
+        let mut emulator = load_program(&[0xBB, 0x01, 0x02, 0xB4, 0x20, 0x38, 0x67, 0xFF, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0x10);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0x10);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+    }
+
+    #[test]
+    fn test_cmp_rmw_rw(){
+        //CMP BX,AX con valores iguales
+        let mut emulator = load_program(&[0xB8, 0x34, 0x12, 0xBB, 0x34, 0x12, 0x39, 0xC3, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 0x1234);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_cmp_rb_rmb(){
+        //CMP CL,DL, comparación con signo: 0x7F - 0xFF desborda
+        let mut emulator = load_program(&[0xB1, 0x7F, 0xB2, 0xFF, 0x3A, 0xCA, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 0x007F);
+        assert_ne!(emulator.registers.flags & FLAG_OF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_cmp_rw_rmw(){
+        //CMP AX,[BX+SI]
+        let mut emulator = load_program(&[0xBB, 0x00, 0x01, 0xBE, 0x00, 0x01, 0xB8, 0x00, 0x01, 0x3B, 0x00, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0x0100);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0100);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+    }
+
+    #[test]
+    fn test_cmp_al_ib(){
+        let mut emulator = load_program(&[0xB0, 0x05, 0x3C, 0x06, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0005);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_AF, 0);
+    }
+
+    #[test]
+    fn test_cmp_ax_iw(){
+        let mut emulator = load_program(&[0xB8, 0x00, 0x10, 0x3D, 0x00, 0x10, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x1000);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+        assert_eq!(emulator.registers.flags & (FLAG_CF | FLAG_SF | FLAG_OF), 0);
+    }
+
+    #[test]
+    fn test_and_rmb_rb(){
+        //AND [BX],AL
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xB0, 0x0F, 0x20, 0x07, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0x3C);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0x0C);
+        assert_ne!(emulator.registers.flags & FLAG_PF, 0);
+    }
+
+    #[test]
+    fn test_and_rw_rmw(){
+        //AND AX,[SI+2]
+        let mut emulator = load_program(&[0xBE, 0x00, 0x02, 0xB8, 0xFF, 0x80, 0x23, 0x44, 0x02, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA + 2, 0xF000);
+        emulator.registers.flags |= FLAG_CF | FLAG_OF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x8000);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+        assert_eq!(emulator.registers.flags & (FLAG_CF | FLAG_OF | FLAG_ZF), 0);
+    }
+
+    #[test]
+    fn test_or_rmw_rw(){
+        //OR [BX],CX
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xB9, 0x0F, 0x00, 0x09, 0x0F, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0xF000);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, DATA), 0xF00F);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+    }
+
+    #[test]
+    fn test_or_rb_rmb(){
+        //OR DL,BH con resultado cero: típica comprobación de cero
+        let mut emulator = load_program(&[0x0A, 0xD7, 0xC3]);
+        emulator.registers.flags |= FLAG_CF | FLAG_OF | FLAG_AF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.dx, 0x0000);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+        assert_eq!(emulator.registers.flags & (FLAG_CF | FLAG_OF | FLAG_AF), 0);
+    }
+
+    #[test]
+    fn test_or_acumulador(){
+        //OR AL,0x80 y OR AX,0x0100
+        let mut emulator = load_program(&[0x0C, 0x80, 0x0D, 0x00, 0x01, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0180);
+        assert_eq!(emulator.registers.flags & (FLAG_SF | FLAG_ZF), 0);
+    }
+
+    #[test]
+    fn test_xor_ax_ax(){
+        //XOR AX,AX pone el registro a 0
+        let mut emulator = load_program(&[0xB8, 0x34, 0x12, 0x31, 0xC0, 0xC3]);
+        emulator.registers.flags |= FLAG_CF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0000);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_PF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_xor_memoria(){
+        //XOR [DI],AH y XOR BX,[DI]
+        let mut emulator = load_program(&[0xBF, 0x00, 0x02, 0xB4, 0xFF, 0x30, 0x25, 0xBB, 0xFF, 0x00, 0x33, 0x1D, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0x12F0);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, DATA), 0x120F);
+        assert_eq!(emulator.registers.bx, 0x12F0);
+    }
+
+    #[test]
+    fn test_xor_acumulador(){
+        let mut emulator = load_program(&[0xB8, 0xFF, 0xFF, 0x34, 0x0F, 0x35, 0x00, 0xFF, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x00F0);
+        assert_eq!(emulator.registers.flags & (FLAG_SF | FLAG_ZF), 0);
+    }
+
+    #[test]
+    fn test_test_rmb_rb(){
+        //TEST [BX],AL no modifica el operando
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xB0, 0x01, 0x84, 0x07, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0xFE);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0xFE);
+        assert_eq!(emulator.registers.ax, 0x0001);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+    }
+
+    #[test]
+    fn test_test_rmw_rw(){
+        //TEST CX,DX
+        let mut emulator = load_program(&[0xB9, 0x00, 0x80, 0xBA, 0x01, 0x80, 0x85, 0xD1, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 0x8000);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_ZF, 0);
+    }
+
+    #[test]
+    fn test_test_acumulador(){
+        //TEST AL,0x01 y TEST AX,0x8000
+        let mut emulator = load_program(&[0xB8, 0x02, 0x80, 0xA8, 0x01, 0xC3]);
+        run_program(&mut emulator);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+        let mut emulator = load_program(&[0xB8, 0x02, 0x80, 0xA9, 0x00, 0x80, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x8002);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_ZF, 0);
+    }
+
+    #[test]
+    fn test_test_rm_inmediato(){
+        //TEST word [BX+2],0x00FF (F7 /0 con desplazamiento e inmediato)
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xF7, 0x47, 0x02, 0xFF, 0x00, 0xB0, 0x07, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA + 2, 0xFF00);
+        run_program(&mut emulator);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+        //Tras el TEST se ejecuta MOV AL,7, así que el inmediato se ha consumido correctamente
+        assert_eq!(emulator.registers.ax, 0x0007);
+    }
+
+    #[test]
+    fn test_not(){
+        //NOT AX y NOT byte [BX]
+        let mut emulator = load_program(&[0xB8, 0x0F, 0xF0, 0xF7, 0xD0, 0xBB, 0x00, 0x02, 0xF6, 0x17, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0x55);
+        let flags = emulator.registers.flags;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0FF0);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0xAA);
+        assert_eq!(emulator.registers.flags, flags);
+    }
+
+    #[test]
+    fn test_add_overflow_con_signo(){
+        //MOV AX,0x7FFF ; ADD AX,1 desborda con signo pero no tiene carry
+        let mut emulator = load_program(&[0xB8, 0xFF, 0x7F, 0x05, 0x01, 0x00, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x8000);
+        assert_ne!(emulator.registers.flags & FLAG_OF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+        //MOV AL,0xC8 ; ADD AL,0x64 tiene carry pero no overflow
+        let mut emulator = load_program(&[0xB0, 0xC8, 0x04, 0x64, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x002C);
+        assert_eq!(emulator.registers.flags & FLAG_OF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_adc(){
+        //MOV AX,0xFFFF ; ADD AX,1 ; MOV BX,0 ; ADC BX,BX ; ADC AL,5
+        let mut emulator = load_program(&[0xB8, 0xFF, 0xFF, 0x05, 0x01, 0x00, 0xBB, 0x00, 0x00, 0x11, 0xDB, 0x14, 0x05, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 0x0001);
+        assert_eq!(emulator.registers.ax, 0x0005);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_inc_dec_registro(){
+        //CMP AL,1 con AL=0 deja CF a 1 ; INC AX ; DEC CX
+        let mut emulator = load_program(&[0x3C, 0x01, 0x40, 0x49, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0001);
+        assert_eq!(emulator.registers.cx, 0xFFFF);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_AF, 0);
+    }
+
+    #[test]
+    fn test_inc_overflow_conserva_carry(){
+        //MOV DX,0x7FFF ; INC DX con CF a 0
+        let mut emulator = load_program(&[0xBA, 0xFF, 0x7F, 0x42, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.dx, 0x8000);
+        assert_ne!(emulator.registers.flags & FLAG_OF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+        //MOV DX,0xFFFF ; INC DX no activa CF
+        let mut emulator = load_program(&[0xBA, 0xFF, 0xFF, 0x42, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.dx, 0x0000);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_inc_dec_rm(){
+        //MOV BX,0x0200 ; INC byte [BX] ; DEC word [BX+2] ; INC BL ; DEC SI (FF /1 con registro)
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xFE, 0x07, 0xFF, 0x4F, 0x02, 0xFE, 0xC3, 0xFF, 0xCE, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0xFF);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA + 2, 0x8000);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0x00);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, DATA + 2), 0x7FFF);
+        assert_eq!(emulator.registers.bx, 0x0201);
+        assert_eq!(emulator.registers.si, 0xFFFF);
+    }
+
+    #[test]
+    fn test_neg(){
+        //MOV AX,5 ; NEG AX
+        let mut emulator = load_program(&[0xB8, 0x05, 0x00, 0xF7, 0xD8, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0xFFFB);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+        //NEG byte [0x0200] con 0x80 desborda, NEG de 0 deja CF a 0
+        let mut emulator = load_program(&[0xF6, 0x1E, 0x00, 0x02, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0x80);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0x80);
+        assert_ne!(emulator.registers.flags & FLAG_OF, 0);
+        let mut emulator = load_program(&[0xF6, 0xD9, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 0);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+    }
+
+    #[test]
+    fn test_grupo_80_81(){
+        //ADD BL,0x10 ; OR byte [0x0200],0x0F ; SUB word [BX+0x0100],0x1234 ; AND CX,0x00FF ; XOR DL,0xFF
+        let mut emulator = load_program(&[
+            0x80, 0xC3, 0x10,
+            0x80, 0x0E, 0x00, 0x02, 0x0F,
+            0x81, 0xAF, 0x00, 0x01, 0x34, 0x12,
+            0x81, 0xE1, 0xFF, 0x00,
+            0x80, 0xF2, 0xFF,
+            0xC3]);
+        emulator.registers.bx = 0x0100;
+        emulator.registers.cx = 0xABCD;
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0x50);
+        emulator.set_w_in_memory(emulator.registers.ds, 0x0210, 0x2234);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 0x0110);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0x5F);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, 0x0210), 0x1000);
+        assert_eq!(emulator.registers.cx, 0x00CD);
+        assert_eq!(emulator.registers.dx, 0x00FF);
+    }
+
+    #[test]
+    fn test_grupo_80_adc_sbb_cmp(){
+        //CMP AL,1 (CF=1) ; ADC AL,0 ; SBB AL,0 (CF=0 tras el ADC) ; CMP AL,0xFF no guarda el resultado
+        let mut emulator = load_program(&[0x80, 0xF8, 0x01, 0x80, 0xD0, 0x00, 0x80, 0xD8, 0x00, 0x80, 0xF8, 0xFF, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0001);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+        //82 es un alias de 80 en el 8086: ADD AL,7
+        let mut emulator = load_program(&[0x82, 0xC0, 0x07, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0007);
+    }
+
+    #[test]
+    fn test_grupo_83_extension_signo(){
+        //MOV AX,0x0010 ; ADD AX,-1 (83 C0 FF) suma 0xFFFF, no 0x00FF
+        let mut emulator = load_program(&[0xB8, 0x10, 0x00, 0x83, 0xC0, 0xFF, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x000F);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+        //SUB word [0x0200],-128 (83 2E 00 02 80) suma 0x80
+        let mut emulator = load_program(&[0x83, 0x2E, 0x00, 0x02, 0x80, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0x0100);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, DATA), 0x0180);
+        //AND BX,0x7F (83 E3 7F) no extiende con unos los inmediatos positivos
+        let mut emulator = load_program(&[0xBB, 0xFF, 0xFF, 0x83, 0xE3, 0x7F, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 0x007F);
+        //CMP CX,-1 (83 F9 FF) con CX=0xFFFF da ZF
+        let mut emulator = load_program(&[0xB9, 0xFF, 0xFF, 0x83, 0xF9, 0xFF, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 0xFFFF);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+    }
+
+    #[test]
+    fn test_daa_das_tabla_referencia(){
+        //Recorre todas las combinaciones de AL, CF y AF de tests/bcd/daa_das.txt
+        let tabla = std::fs::read_to_string("./tests/bcd/daa_das.txt").expect("No se encuentra la tabla de referencia");
+        let mut emulator = load_program(&[0x27, 0x2F]);
+        let mut casos = 0;
+        for linea in tabla.lines().filter(|linea| !linea.starts_with('#') && !linea.is_empty()) {
+            let campos: Vec<&str> = linea.split_whitespace().collect();
+            let hex = |campo: &str| u8::from_str_radix(campo, 16).unwrap();
+            let bit = |campo: &str| campo == "1";
+            let opcode = hex(campos[0]);
+            emulator.registers.ax = hex(campos[1]) as u16;
+            emulator.registers.flags = 0;
+            if bit(campos[2]) { emulator.registers.flags |= FLAG_CF; }
+            if bit(campos[3]) { emulator.registers.flags |= FLAG_AF; }
+            emulator.registers.ip = if opcode == 0x27 { 0x0100 } else { 0x0101 };
+            step(&mut emulator);
+            let flags = emulator.registers.flags;
+            assert_eq!(emulator.registers.ax, hex(campos[5]) as u16, "AL en {}", linea);
+            assert_eq!(flags & FLAG_CF != 0, bit(campos[6]), "CF en {}", linea);
+            assert_eq!(flags & FLAG_AF != 0, bit(campos[7]), "AF en {}", linea);
+            assert_eq!(flags & FLAG_SF != 0, bit(campos[8]), "SF en {}", linea);
+            assert_eq!(flags & FLAG_ZF != 0, bit(campos[9]), "ZF en {}", linea);
+            assert_eq!(flags & FLAG_PF != 0, bit(campos[10]), "PF en {}", linea);
+            casos += 1;
+        }
+        assert_eq!(casos, 2 * 256 * 4);
+    }
+
+    #[test]
+    fn test_daa_suma_bcd(){
+        //MOV AL,0x38 ; ADD AL,0x45 ; DAA -> 83
+        let mut emulator = load_program(&[0xB0, 0x38, 0x04, 0x45, 0x27, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0083);
+        //MOV AL,0x83 ; SUB AL,0x38 ; DAS -> 45
+        let mut emulator = load_program(&[0xB0, 0x83, 0x2C, 0x38, 0x2F, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0045);
+    }
+
+    #[test]
+    fn test_aad_base_10(){
+        //MOV AX,0x0307 ; AAD -> 37
+        let mut emulator = load_program(&[0xB8, 0x07, 0x03, 0xD5, 0x0A, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0025);
+        assert_eq!(emulator.registers.flags & (FLAG_ZF | FLAG_SF | FLAG_PF), 0);
+    }
+
+    #[test]
+    fn test_aad_otra_base(){
+        //MOV AX,0x0F0F ; AAD 16 -> 0xFF, el resultado se trunca a 8 bits
+        let mut emulator = load_program(&[0xB8, 0x0F, 0x0F, 0xD5, 0x10, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x00FF);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_PF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_ZF, 0);
+    }
+
+    #[test]
+    fn test_cbw_cwd(){
+        //MOV AL,0x80 ; CBW ; CWD
+        let mut emulator = load_program(&[0xB0, 0x80, 0x98, 0x99, 0xC3]);
+        emulator.registers.ax = 0x1200;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0xFF80);
+        assert_eq!(emulator.registers.dx, 0xFFFF);
+        //MOV AX,0x127F ; CBW ; CWD
+        let mut emulator = load_program(&[0xB8, 0x7F, 0x12, 0x98, 0x99, 0xC3]);
+        emulator.registers.dx = 0xABCD;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x007F);
+        assert_eq!(emulator.registers.dx, 0x0000);
+    }
+
+    #[test]
+    fn test_xlat(){
+        //MOV BX,0x0200 ; MOV AL,3 ; XLAT ; ES: XLAT
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xB0, 0x03, 0xD7, 0x26, 0xD7, 0xC3]);
+        emulator.registers.es = 0x0800;
+        emulator.set_b_in_memory(emulator.registers.ds, DATA + 3, 0x07);
+        emulator.set_b_in_memory(0x0800, DATA + 7, 0x42);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0042);
+    }
+
+    #[test]
+    fn test_lahf_sahf(){
+        //LAHF ; MOV AH,0xFF ; SAHF
+        let mut emulator = load_program(&[0x9F, 0xB4, 0xFF, 0x9E, 0xC3]);
+        emulator.registers.flags = FLAG_ZF | FLAG_CF | FLAG_OF;
+        step(&mut emulator);
+        assert_eq!(emulator.registers.get_high_byte(emulator.registers.ax), 0x43);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.flags, FLAG_SF | FLAG_ZF | FLAG_AF | FLAG_PF | FLAG_CF | FLAG_OF);
+    }
+
+    #[test]
+    fn test_lea(){
+        //LEA AX,[BX+SI+0x10] ; LEA CX,[BP-2] ; LEA DX,[0x1234] ; ES: LEA SI,[BX] (el prefijo no afecta)
+        let mut emulator = load_program(&[0x8D, 0x40, 0x10, 0x8D, 0x4E, 0xFE, 0x8D, 0x16, 0x34, 0x12, 0x26, 0x8D, 0x37, 0xC3]);
+        emulator.registers.bx = 0x0100;
+        emulator.registers.si = 0x0020;
+        emulator.registers.bp = 0x0001;
+        let flags = emulator.registers.flags;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0130);
+        assert_eq!(emulator.registers.cx, 0xFFFF);
+        assert_eq!(emulator.registers.dx, 0x1234);
+        assert_eq!(emulator.registers.si, 0x0100);
+        assert_eq!(emulator.registers.flags, flags);
+    }
+
+    #[test]
+    fn test_lds_les(){
+        //LDS SI,[0x0200] ; LES DI,[BX+4] con BX=0x0200
+        let mut emulator = load_program(&[0xC4, 0x7F, 0x04, 0xC5, 0x36, 0x00, 0x02, 0xC3]);
+        emulator.registers.bx = DATA;
+        let ds = emulator.registers.ds;
+        emulator.set_w_in_memory(ds, DATA, 0x1111);
+        emulator.set_w_in_memory(ds, DATA + 2, 0x2222);
+        emulator.set_w_in_memory(ds, DATA + 4, 0x3333);
+        emulator.set_w_in_memory(ds, DATA + 6, 0x4444);
+        step(&mut emulator);
+        step(&mut emulator);
+        assert_eq!(emulator.registers.di, 0x3333);
+        assert_eq!(emulator.registers.es, 0x4444);
+        assert_eq!(emulator.registers.si, 0x1111);
+        assert_eq!(emulator.registers.ds, 0x2222);
+    }
+
+    #[test]
+    fn test_lea_lds_con_registro(){
+        //MOV AX,[0x0200] (8B 06) deja 0x0200 como última dirección efectiva ; LEA CX,DX (8D CA) ; LDS BX,SI (C5 DE)
+        let mut emulator = load_program(&[0x8B, 0x06, 0x00, 0x02, 0x8D, 0xCA, 0xC5, 0xDE, 0xC3]);
+        let ds = emulator.registers.ds;
+        emulator.set_w_in_memory(ds, DATA, 0xBEEF);
+        emulator.set_w_in_memory(ds, DATA + 2, 0x0900);
+        step(&mut emulator);
+        step(&mut emulator);
+        assert_eq!(emulator.registers.cx, DATA);
+        step(&mut emulator);
+        assert_eq!(emulator.registers.bx, 0xBEEF);
+        assert_eq!(emulator.registers.ds, 0x0900);
+    }
+
+    #[test]
+    fn test_xchg(){
+        //XCHG AL,[0x0200] ; XCHG CX,DX ; XCHG AX,BX ; NOP
+        let mut emulator = load_program(&[0x86, 0x06, 0x00, 0x02, 0x87, 0xCA, 0x93, 0x90, 0xC3]);
+        emulator.registers.ax = 0x1122;
+        emulator.registers.bx = 0x3344;
+        emulator.registers.cx = 0x5566;
+        emulator.registers.dx = 0x7788;
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0x99);
+        let flags = emulator.registers.flags;
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0x22);
+        assert_eq!(emulator.registers.bx, 0x1199);
+        assert_eq!(emulator.registers.ax, 0x3344);
+        assert_eq!(emulator.registers.cx, 0x7788);
+        assert_eq!(emulator.registers.dx, 0x5566);
+        assert_eq!(emulator.registers.flags, flags);
+    }
+
+    #[test]
+    fn test_control_flags(){
+        //STC ; CMC ; STD ; STI
+        let mut emulator = load_program(&[0xF9, 0xF5, 0xFD, 0xFB, 0xC3]);
+        emulator.registers.flags = 0;
+        step(&mut emulator);
+        assert_eq!(emulator.registers.flags, FLAG_CF);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.flags, FLAG_DF | FLAG_IF);
+        //CMC ; CLC ; CLD ; CLI
+        let mut emulator = load_program(&[0xF5, 0xF8, 0xFC, 0xFA, 0xC3]);
+        emulator.registers.flags = FLAG_DF | FLAG_IF | FLAG_ZF;
+        step(&mut emulator);
+        assert_eq!(emulator.registers.flags & FLAG_CF, FLAG_CF);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.flags, FLAG_ZF);
+    }
+
+    #[test]
+    fn test_hlt_y_despertar(){
+        //HLT ; MOV AX,1 ; INT 20h. La interrupción vuelve a la instrucción siguiente a HLT
+        let mut emulator = load_program(&[0xF4, 0xB8, 0x01, 0x00, 0xCD, 0x20]);
+        //Manejador en 0800:0000 que solo hace IRET
+        emulator.set_b_in_memory(0x0800, 0x0000, 0xCF);
+        emulator.set_w_in_memory(0x0000, 0x08 * 4, 0x0000);
+        emulator.set_w_in_memory(0x0000, 0x08 * 4 + 2, 0x0800);
+        run_program(&mut emulator);
+        assert!(emulator.halted);
+        assert!(!emulator.terminated);
+        assert_eq!(emulator.registers.ip, 0x0101);
+        emulator.interrupt(0x08);
+        assert!(!emulator.halted);
+        run_program(&mut emulator);
+        assert!(emulator.terminated);
+        assert_eq!(emulator.registers.ax, 0x0001);
+    }
+
+    #[test]
+    fn test_wait_lock_esc(){
+        //WAIT ; LOCK INC word [0x0200] ; ESC con disp16 (DD 86 34 12) ; ESC con registro (D9 C0) ; MOV AX,7
+        let mut emulator = load_program(&[
+            0x9B,
+            0xF0, 0xFF, 0x06, 0x00, 0x02,
+            0xDD, 0x86, 0x34, 0x12,
+            0xD9, 0xC0,
+            0xB8, 0x07, 0x00,
+            0xC3]);
+        let flags = emulator.registers.flags;
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, DATA), 0x0001);
+        assert_eq!(emulator.registers.ax, 0x0007);
+        assert_eq!(emulator.registers.flags & FLAG_CF, flags & FLAG_CF);
+    }
+
+    //Apunta el vector a un manejador en 0800:0000 con el código indicado
+    fn install_handler(emulator: &mut Emulator8086, vector: u8, code: &[u8]){
+        let vector_offset = vector as u16 * 4;
+        emulator.set_w_in_memory(0x0000, vector_offset, 0x0000);
+        emulator.set_w_in_memory(0x0000, vector_offset + 2, 0x0800);
+        for (i, &byte) in code.iter().enumerate() {
+            emulator.set_b_in_memory(0x0800, i as u16, byte);
+        }
+    }
+
+    #[test]
+    fn test_int_tabla_vectores(){
+        //STC ; STI ; INT 21h ; INT 20h y el manejador hace MOV AX,0x4C00 ; IRET
+        let mut emulator = load_program(&[0xF9, 0xFB, 0xCD, 0x21, 0xCD, 0x20]);
+        install_handler(&mut emulator, 0x21, &[0xB8, 0x00, 0x4C, 0xCF]);
+        step(&mut emulator);
+        step(&mut emulator);
+        step(&mut emulator);
+        assert_eq!(emulator.registers.cs, 0x0800);
+        assert_eq!(emulator.registers.ip, 0x0000);
+        assert_eq!(emulator.registers.flags & (FLAG_IF | FLAG_TF), 0);
+        assert_eq!(stack_top(&emulator), 0x0104);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x4C00);
+        assert_eq!(emulator.registers.cs, 0x0700);
+        assert_eq!(emulator.registers.flags & (FLAG_IF | FLAG_CF), FLAG_IF | FLAG_CF);
+        assert_eq!(emulator.registers.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn test_int3_into(){
+        //INT 3 ; MOV AL,0x7F ; ADD AL,1 ; INTO ; INT 20h y los manejadores suman 1 y 0x10 a BX
+        let mut emulator = load_program(&[0xCC, 0xB0, 0x7F, 0x04, 0x01, 0xCE, 0xCD, 0x20]);
+        install_handler(&mut emulator, 0x03, &[0x43, 0xCF]);
+        emulator.set_w_in_memory(0x0000, 0x04 * 4, 0x0010);
+        emulator.set_w_in_memory(0x0000, 0x04 * 4 + 2, 0x0800);
+        emulator.memory[0x8010..0x8014].copy_from_slice(&[0x83, 0xC3, 0x10, 0xCF]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 0x0011);
+        //Sin OF INTO no hace nada
+        let mut emulator = load_program(&[0xCE, 0xCD, 0x20]);
+        install_handler(&mut emulator, 0x04, &[0x43, 0xCF]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 0x0000);
+    }
+
+    #[test]
+    fn test_hook_interrupcion(){
+        //MOV AH,9 ; INT 21h ; INT 21h ; INT 20h. El host atiende la función 9 y deja el resto al programa
+        let mut emulator = load_program(&[0xB4, 0x09, 0xCD, 0x21, 0xB4, 0x02, 0xCD, 0x21, 0xCD, 0x20]);
+        install_handler(&mut emulator, 0x21, &[0x41, 0xCF]);
+        emulator.set_interrupt_hook(0x21, |emulator| {
+            if emulator.registers.get_high_byte(emulator.registers.ax) == 0x09 {
+                emulator.registers.dx = 0x1234;
+                true
+            }else{
+                false
+            }
+        });
+        let sp = emulator.registers.sp;
+        step(&mut emulator);
+        step(&mut emulator);
+        assert_eq!(emulator.registers.dx, 0x1234);
+        assert_eq!(emulator.registers.ip, 0x0104);
+        assert_eq!(emulator.registers.sp, sp);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 0x0001);
+    }
+
+    #[test]
+    fn test_hook_sustituye_int20(){
+        //El host puede cambiar el comportamiento por defecto de INT 20h y quitar sus hooks
+        let mut emulator = load_program(&[0xCD, 0x20, 0xCD, 0x20]);
+        emulator.set_interrupt_hook(0x20, |emulator| {
+            emulator.registers.ax = emulator.registers.ax.wrapping_add(1);
+            true
+        });
+        install_handler(&mut emulator, 0x20, &[0xF4]);
+        step(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0001);
+        assert!(!emulator.terminated);
+        emulator.remove_interrupt_hook(0x20);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0001);
+        assert!(!emulator.terminated);
+        assert!(emulator.halted);
+        assert_eq!(emulator.registers.cs, 0x0800);
+    }
+
+    #[test]
+    fn test_paso_a_paso(){
+        //PUSHF ; POP AX ; OR AH,1 ; PUSH AX ; POPF ; NOP ; NOP ; INT 20h con un manejador de INT 1 que cuenta en BX
+        let mut emulator = load_program(&[0x9C, 0x58, 0x80, 0xCC, 0x01, 0x50, 0x9D, 0x90, 0x90, 0xCD, 0x20]);
+        install_handler(&mut emulator, 0x01, &[0x43, 0xCF]);
+        for _ in 0..5 {
+            step(&mut emulator);
+        }
+        //POPF activa TF pero no se interrumpe
+        assert_eq!(emulator.registers.cs, 0x0700);
+        assert_eq!(emulator.registers.ip, 0x0107);
+        step(&mut emulator);
+        assert_eq!(emulator.registers.cs, 0x0800);
+        assert_eq!(stack_top(&emulator), 0x0108);
+        assert_eq!(emulator.registers.flags & FLAG_TF, 0);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 2);
+    }
+
+    #[test]
+    fn test_paso_a_paso_sombra_segmento(){
+        //Con TF activa: MOV SS,AX ; MOV SP,0x0100 ; PUSH DS ; POP ES ; NOP ; INT 20h
+        let mut emulator = load_program(&[0x8E, 0xD0, 0xBC, 0x00, 0x01, 0x1E, 0x07, 0x90, 0xCD, 0x20]);
+        emulator.registers.ax = emulator.registers.ss;
+        install_handler(&mut emulator, 0x01, &[0x43, 0xCF]);
+        emulator.registers.flags |= FLAG_TF;
+        step(&mut emulator);
+        assert_eq!(emulator.registers.cs, 0x0700);
+        step(&mut emulator);
+        assert_eq!(emulator.registers.cs, 0x0800);
+        run_program(&mut emulator);
+        //Se interrumpe tras MOV SP, PUSH DS y NOP, pero no tras MOV SS ni POP ES
+        assert_eq!(emulator.registers.bx, 3);
+    }
+
+    #[test]
+    fn test_intr_respeta_if(){
+        //CLI ; NOP ; STI ; NOP ; NOP ; INT 20h y el manejador de INT 8 cuenta en CX
+        let mut emulator = load_program(&[0xFA, 0x90, 0xFB, 0x90, 0x90, 0xCD, 0x20]);
+        install_handler(&mut emulator, 0x08, &[0x41, 0xCF]);
+        step(&mut emulator);
+        emulator.intr(0x08);
+        step(&mut emulator);
+        assert_eq!(emulator.registers.cs, 0x0700);
+        //La interrupción no entra justo después de STI sino tras la instrucción siguiente
+        step(&mut emulator);
+        assert_eq!(emulator.registers.cs, 0x0700);
+        step(&mut emulator);
+        assert_eq!(emulator.registers.cs, 0x0800);
+        assert_eq!(stack_top(&emulator), 0x0104);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 1);
+    }
+
+    #[test]
+    fn test_intr_despierta_hlt(){
+        //STI ; HLT ; INT 20h, INTR con IF a 0 no despierta al procesador y NMI sí
+        let mut emulator = load_program(&[0xFB, 0xF4, 0xCD, 0x20]);
+        install_handler(&mut emulator, 0x08, &[0x41, 0xCF]);
+        run_program(&mut emulator);
+        assert!(emulator.halted);
+        emulator.intr(0x08);
+        assert!(!emulator.halted);
+        run_program(&mut emulator);
+        assert!(emulator.terminated);
+        assert_eq!(emulator.registers.cx, 1);
+
+        let mut emulator = load_program(&[0xFA, 0xF4, 0xCD, 0x20]);
+        install_handler(&mut emulator, 0x08, &[0x41, 0xCF]);
+        install_handler(&mut emulator, 0x02, &[0x43, 0xCF]);
+        run_program(&mut emulator);
+        emulator.intr(0x08);
+        assert!(emulator.halted);
+        emulator.nmi();
+        assert!(!emulator.halted);
+        run_program(&mut emulator);
+        assert!(emulator.terminated);
+        assert_eq!(emulator.registers.bx, 1);
+        assert_eq!(emulator.registers.cx, 0);
+    }
+
+    #[test]
+    fn test_error_opcode_no_implementado(){
+        //MOV AX,1 ; ES: FE /7 con desplazamiento, el error lleva CS:IP y los bytes de la instrucción
+        let mut emulator = load_program(&[0xB8, 0x01, 0x00, 0x26, 0xFE, 0xBF, 0x34, 0x12, 0xC3]);
+        step(&mut emulator);
+        match emulator.step() {
+            Err(EmulatorError::UnimplementedOpcode { cs, ip, bytes }) => {
+                assert_eq!(cs, 0x0700);
+                assert_eq!(ip, 0x0103);
+                assert_eq!(bytes, vec![0x26, 0xFE, 0xBF, 0x34, 0x12]);
+            },
+            resultado => panic!("Se esperaba un opcode no implementado: {:?}", resultado),
+        }
+        assert_eq!(emulator.registers.ip, 0x0103);
+        assert_eq!(emulator.registers.ax, 0x0001);
+        //JMP far con un registro como operando tampoco existe
+        let mut emulator = load_program(&[0xFF, 0xE8]);
+        assert!(matches!(emulator.run(10), StopReason::Error(EmulatorError::UnimplementedOpcode { ip: 0x0100, .. })));
+    }
+
+    #[test]
+    fn test_error_halted(){
+        let mut emulator = load_program(&[0xF4]);
+        run_program(&mut emulator);
+        assert!(matches!(emulator.step(), Err(EmulatorError::Halted)));
+        assert_eq!(emulator.registers.ip, 0x0101);
+    }
+
+    #[test]
+    fn test_error_carga(){
+        let mut emulator = Emulator8086::new();
+        assert!(matches!(emulator.load_com("./tests/no_existe.com"), Err(EmulatorError::Load(_))));
+        assert!(matches!(emulator.load_com_bytes(&vec![0x90; 0x10000]), Err(EmulatorError::Load(_))));
+    }
+
+    #[test]
+    fn test_error_memoria(){
+        let mut emulator = load_program(&[0x90]);
+        emulator.memory.truncate(0x8000);
+        assert!(matches!(emulator.step(), Err(EmulatorError::MemoryFault { address: 0x8000 })));
+        assert!(matches!(emulator.load_com_bytes(&[0x90]), Err(EmulatorError::MemoryFault { .. })));
+    }
+
+    #[test]
+    fn test_trazas(){
+        use std::rc::Rc;
+        //MOV BX,0x0200 ; MOV AX,[BX] con trazas de memoria: solo instrucciones y accesos a memoria
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0x8B, 0x07, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0x1234);
+        let eventos = Rc::new(RefCell::new(Vec::new()));
+        let destino = Rc::clone(&eventos);
+        emulator.set_trace_sink(move |event: &TraceEvent| destino.borrow_mut().push(*event), TraceLevel::Memory);
+        step(&mut emulator);
+        step(&mut emulator);
+        assert_eq!(*eventos.borrow(), vec![
+            TraceEvent::Instruction { cs: 0x0700, ip: 0x0100, opcode: 0xBB },
+            TraceEvent::Instruction { cs: 0x0700, ip: 0x0103, opcode: 0x8B },
+            TraceEvent::MemoryRead { address: 0x7200, value: 0x1234, word: true },
+        ]);
+        //Con el nivel de registros también aparecen la base y la escritura en AX
+        eventos.borrow_mut().clear();
+        let destino = Rc::clone(&eventos);
+        emulator.set_trace_sink(move |event: &TraceEvent| destino.borrow_mut().push(*event), TraceLevel::Register);
+        emulator.registers.ip = 0x0103;
+        step(&mut emulator);
+        assert!(eventos.borrow().contains(&TraceEvent::BaseAddress { base: "BX", value: 0x0200 }));
+        assert!(eventos.borrow().contains(&TraceEvent::RegisterWrite { register: "AX", value: 0x1234 }));
+        //Sin destino no se traza nada
+        emulator.clear_trace_sink();
+        eventos.borrow_mut().clear();
+        run_program(&mut emulator);
+        assert!(eventos.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_step_resultados(){
+        //NOP ; HLT
+        let mut emulator = load_program(&[0x90, 0xF4]);
+        assert_eq!(emulator.step().unwrap(), StepOutcome::Running);
+        assert_eq!(emulator.step().unwrap(), StepOutcome::Halted);
+        //MOV AX,0x4C03 ; INT 21h
+        let mut emulator = load_program(&[0xB8, 0x03, 0x4C, 0xCD, 0x21, 0x90]);
+        step(&mut emulator);
+        assert_eq!(emulator.step().unwrap(), StepOutcome::Exited(3));
+        //Con el programa terminado no se ejecuta nada más
+        assert_eq!(emulator.step().unwrap(), StepOutcome::Exited(3));
+        assert_eq!(emulator.registers.ip, 0x0105);
+    }
+
+    #[test]
+    fn test_run_motivos_parada(){
+        //El RET final salta al INT 20h del PSP
+        let mut emulator = load_program(&[0x90, 0xC3]);
+        assert!(matches!(emulator.run(100), StopReason::Exited(0)));
+        //JMP $ agota el presupuesto
+        let mut emulator = load_program(&[0xEB, 0xFE]);
+        assert!(matches!(emulator.run(50), StopReason::BudgetExhausted));
+        assert_eq!(emulator.registers.ip, 0x0100);
+        //HLT, y volver a llamar a run con el procesador parado no ejecuta nada
+        let mut emulator = load_program(&[0xF4, 0x90]);
+        assert!(matches!(emulator.run(100), StopReason::Halted));
+        assert!(matches!(emulator.run(100), StopReason::Halted));
+        assert_eq!(emulator.registers.ip, 0x0101);
+        //Opcode no implementado (IN AL,DX)
+        let mut emulator = load_program(&[0x90, 0xEC]);
+        assert!(matches!(emulator.run(100), StopReason::Error(EmulatorError::UnimplementedOpcode { ip: 0x0101, .. })));
+    }
+
+    #[test]
+    fn test_run_breakpoint(){
+        //INC AX ; INC AX ; INC AX ; RET con un punto de ruptura en 0102
+        let mut emulator = load_program(&[0x40, 0x40, 0x40, 0xC3]);
+        emulator.add_breakpoint(0x0700, 0x0102);
+        assert!(matches!(emulator.run(100), StopReason::Breakpoint));
+        assert_eq!(emulator.registers.ip, 0x0102);
+        assert_eq!(emulator.registers.ax, 2);
+        //Al continuar se ejecuta la instrucción del punto de ruptura
+        assert!(matches!(emulator.run(100), StopReason::Exited(0)));
+        assert_eq!(emulator.registers.ax, 3);
+        //Los puntos de ruptura se pueden quitar
+        let mut emulator = load_program(&[0x40, 0x40, 0xC3]);
+        emulator.add_breakpoint(0x0700, 0x0101);
+        emulator.remove_breakpoint(0x0700, 0x0101);
+        assert!(matches!(emulator.run(100), StopReason::Exited(0)));
+    }
+
+    #[test]
+    fn test_condiciones(){
+        let mut emulator = Emulator8086::new();
+        //(flags, condiciones que deben cumplirse de 70 a 7F)
+        let casos: [(u16, [bool; 16]); 4] = [
+            (0, [false, true, false, true, false, true, false, true, false, true, false, true, false, true, false, true]),
+            (FLAG_CF | FLAG_ZF, [false, true, true, false, true, false, true, false, false, true, false, true, false, true, true, false]),
+            (FLAG_SF, [false, true, false, true, false, true, false, true, true, false, false, true, true, false, true, false]),
+            (FLAG_SF | FLAG_OF | FLAG_PF, [true, false, false, true, false, true, false, true, true, false, true, false, false, true, false, true]),
+        ];
+        for (flags, esperado) in casos {
+            emulator.registers.flags = flags;
+            for code in 0..16u8 {
+                assert_eq!(emulator.condition(code), esperado[code as usize], "flags 0x{:04x} J 0x{:02x}", flags, 0x70 + code);
+            }
+        }
+    }
+
+    #[test]
+    fn test_loop(){
+        //  MOV CX,5
+        //  XOR AX,AX
+        //L: ADD AX,CX
+        //  LOOP L
+        //  RET
+        let mut emulator = load_program(&[0xB9, 0x05, 0x00, 0x31, 0xC0, 0x03, 0xC1, 0xE2, 0xFC, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 15);
+        assert_eq!(emulator.registers.cx, 0);
+    }
+
+    #[test]
+    fn test_jcc_bucle_contado(){
+        //  MOV CX,3
+        //  MOV BX,1
+        //  MOV AX,0
+        //L: ADD AX,2       (05 02 00)
+        //  SUB CX,BX       (29 D9)
+        //  JNZ L           (75 F9)
+        //  RET
+        let mut emulator = load_program(&[0xB9, 0x03, 0x00, 0xBB, 0x01, 0x00, 0xB8, 0x00, 0x00, 0x05, 0x02, 0x00, 0x29, 0xD9, 0x75, 0xF9, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 6);
+        assert_eq!(emulator.registers.cx, 0);
+    }
+
+    #[test]
+    fn test_loopz_loopnz(){
+        //LOOPNZ termina cuando ZF=1 aunque CX no haya llegado a 0
+        //  MOV CX,10
+        //  MOV AX,3
+        //L: SUB AX,1       (2D 01 00)
+        //  LOOPNZ L        (E0 FB)
+        //  RET
+        let mut emulator = load_program(&[0xB9, 0x0A, 0x00, 0xB8, 0x03, 0x00, 0x2D, 0x01, 0x00, 0xE0, 0xFB, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0);
+        assert_eq!(emulator.registers.cx, 7);
+
+        //LOOPZ mientras ZF=1 llega hasta CX=0
+        //  MOV CX,10
+        //  MOV AX,0
+        //L: CMP AX,0       (3D 00 00)
+        //  LOOPZ L         (E1 FB)
+        //  RET
+        let mut emulator = load_program(&[0xB9, 0x0A, 0x00, 0xB8, 0x00, 0x00, 0x3D, 0x00, 0x00, 0xE1, 0xFB, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 0);
+    }
+
+    #[test]
+    fn test_jcxz(){
+        //JCXZ salta el MOV AL,1 cuando CX=0
+        let mut emulator = load_program(&[0xE3, 0x02, 0xB0, 0x01, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0);
+        let mut emulator = load_program(&[0xB9, 0x01, 0x00, 0xE3, 0x02, 0xB0, 0x01, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 1);
+        assert_eq!(emulator.registers.cx, 1);
+    }
+
+    #[test]
+    fn test_jmp_short_near(){
+        //JMP short hacia delante, JMP near hacia delante y salto atrás short
+        //0100: EB 04        JMP 0106
+        //0102: B0 01        MOV AL,1 (no se ejecuta)
+        //0104: CD 20        INT 20h
+        //0106: E9 02 00     JMP 010B
+        //0109: B4 01        MOV AH,1 (no se ejecuta)
+        //010B: B3 01        MOV BL,1
+        //010D: EB F5        JMP 0104
+        let mut emulator = load_program(&[0xEB, 0x04, 0xB0, 0x01, 0xCD, 0x20, 0xE9, 0x02, 0x00, 0xB4, 0x01, 0xB3, 0x01, 0xEB, 0xF5]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0);
+        assert_eq!(emulator.registers.bx, 1);
+        assert_eq!(emulator.registers.ip, 0x0106);
+    }
+
+    #[test]
+    fn test_jmp_far(){
+        //JMP 0800:0010 y allí MOV AL,0x22 ; INT 20h
+        let mut emulator = load_program(&[0xEA, 0x10, 0x00, 0x00, 0x08, 0xC3]);
+        emulator.memory[0x8010..0x8014].copy_from_slice(&[0xB0, 0x22, 0xCD, 0x20]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cs, 0x0800);
+        assert_eq!(emulator.registers.ip, 0x0014);
+        assert_eq!(emulator.registers.ax, 0x0022);
+    }
+
+    #[test]
+    fn test_jmp_indirecto(){
+        //JMP BX (FF E3) y JMP far [0x0200] (FF 2E 00 02)
+        let mut emulator = load_program(&[0xBB, 0x06, 0x01, 0xFF, 0xE3, 0xC3, 0xFF, 0x2E, 0x00, 0x02]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0x0020);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA + 2, 0x0900);
+        emulator.set_b_in_memory(0x0900, 0x0020, 0xCD);
+        emulator.set_b_in_memory(0x0900, 0x0021, 0x20);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cs, 0x0900);
+        assert_eq!(emulator.registers.ip, 0x0022);
+    }
+
+    //Lee el word de la cima de la pila sin modificar SP
+    fn stack_top(emulator: &Emulator8086)->u16{
+        emulator.get_w_from_memory(emulator.registers.ss, emulator.registers.sp)
+    }
+
+    #[test]
+    fn test_push_pop_registros(){
+        //PUSH AX ; PUSH BX ; POP CX ; POP DX
+        let mut emulator = load_program(&[0xB8, 0x11, 0x11, 0xBB, 0x22, 0x22, 0x50, 0x53, 0x59, 0x5A, 0xCD, 0x20]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 0x2222);
+        assert_eq!(emulator.registers.dx, 0x1111);
+        assert_eq!(emulator.registers.sp, 0xFFFE);
+        //Los valores quedan en SS:SP por debajo de la cima
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ss, 0xFFFC), 0x1111);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ss, 0xFFFA), 0x2222);
+    }
+
+    #[test]
+    fn test_push_sp(){
+        //PUSH SP guarda el valor ya decrementado
+        let mut emulator = load_program(&[0x54, 0xCD, 0x20]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.sp, 0xFFFC);
+        assert_eq!(stack_top(&emulator), 0xFFFC);
+    }
+
+    #[test]
+    fn test_pop_sp(){
+        //PUSH AX ; POP SP deja en SP el valor leído
+        let mut emulator = load_program(&[0xB8, 0x00, 0x80, 0x50, 0x5C, 0xCD, 0x20]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.sp, 0x8000);
+    }
+
+    #[test]
+    fn test_push_pop_segmentos(){
+        //PUSH CS ; POP ES ; PUSH DS ; POP SS ; PUSH ES ; POP DS
+        let mut emulator = load_program(&[0x0E, 0x07, 0x1E, 0x17, 0x06, 0x1F, 0xCD, 0x20]);
+        emulator.registers.cs = 0x0700;
+        emulator.registers.ds = 0x1234;
+        emulator.registers.es = 0x0000;
+        emulator.registers.ss = 0x0000;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.es, 0x0700);
+        assert_eq!(emulator.registers.ss, 0x1234);
+        assert_eq!(emulator.registers.ds, 0x0700);
+    }
+
+    #[test]
+    fn test_pila_usa_ss(){
+        //La pila se escribe en SS:SP y no en DS:SP
+        let mut emulator = load_program(&[0xB8, 0xCD, 0xAB, 0x50, 0xCD, 0x20]);
+        emulator.registers.ss = 0x2000;
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_w_from_memory(0x2000, 0xFFFC), 0xABCD);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, 0xFFFC), 0x0000);
+    }
+
+    #[test]
+    fn test_push_pop_memoria(){
+        //PUSH word [BX] ; POP word [BX+2]
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xFF, 0x37, 0x8F, 0x47, 0x02, 0xCD, 0x20]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0xBEEF);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, DATA + 2), 0xBEEF);
+        assert_eq!(emulator.registers.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn test_pushf_popf(){
+        //PUSHF guarda los bits reservados a 1
+        let mut emulator = load_program(&[0x9C, 0xCD, 0x20]);
+        emulator.registers.flags = FLAG_IF | FLAG_CF;
+        run_program(&mut emulator);
+        assert_eq!(stack_top(&emulator), 0xF000 | 0x0002 | FLAG_IF | FLAG_CF);
+
+        //POPF descarta los bits que no existen
+        let mut emulator = load_program(&[0xB8, 0xFF, 0xFF, 0x50, 0x9D, 0xCD, 0x20]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.flags, FLAGS_MASK);
+        assert_eq!(emulator.registers.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn test_ret_termina_programa(){
+        //El RET del programa salta al INT 20h del PSP
+        let mut emulator = load_program(&[0xB0, 0x01, 0xC3]);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.cs, 0x0000), 0xCD);
+        run_program(&mut emulator);
+        assert!(emulator.terminated);
+        assert_eq!(emulator.registers.ip, 0x0002);
+        assert_eq!(emulator.registers.sp, 0x0000);
+    }
+
+    #[test]
+    fn test_call_ret(){
+        //0100: E8 05 00     CALL 0108
+        //0103: E8 02 00     CALL 0108
+        //0106: CD 20        INT 20h
+        //0108: 05 01 00     ADD AX,1
+        //010B: C3           RET
+        let mut emulator = load_program(&[0xE8, 0x05, 0x00, 0xE8, 0x02, 0x00, 0xCD, 0x20, 0x05, 0x01, 0x00, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 2);
+        assert_eq!(emulator.registers.sp, 0xFFFE);
+        //La última dirección de retorno guardada es la del segundo CALL
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ss, 0xFFFC), 0x0106);
+    }
+
+    #[test]
+    fn test_ret_inmediato(){
+        //0100: 50           PUSH AX (parámetro)
+        //0101: E8 02 00     CALL 0106
+        //0104: CD 20        INT 20h
+        //0106: C2 02 00     RET 2
+        let mut emulator = load_program(&[0x50, 0xE8, 0x02, 0x00, 0xCD, 0x20, 0xC2, 0x02, 0x00]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.sp, 0xFFFE);
+        assert_eq!(emulator.registers.ip, 0x0106);
+    }
+
+    #[test]
+    fn test_call_far_retf(){
+        //CALL 0800:0000 ; INT 20h y en 0800:0000 MOV BL,7 ; RETF
+        let mut emulator = load_program(&[0x9A, 0x00, 0x00, 0x00, 0x08, 0xCD, 0x20]);
+        emulator.memory[0x8000..0x8003].copy_from_slice(&[0xB3, 0x07, 0xCB]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 7);
+        assert_eq!(emulator.registers.cs, 0x0700);
+        assert_eq!(emulator.registers.ip, 0x0107);
+        assert_eq!(emulator.registers.sp, 0xFFFE);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ss, 0xFFFA), 0x0105);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ss, 0xFFFC), 0x0700);
+    }
+
+    #[test]
+    fn test_retf_inmediato(){
+        //PUSH AX ; PUSH AX ; CALL 0800:0000 ; INT 20h y en 0800:0000 RETF 4
+        let mut emulator = load_program(&[0x50, 0x50, 0x9A, 0x00, 0x00, 0x00, 0x08, 0xCD, 0x20]);
+        emulator.memory[0x8000..0x8003].copy_from_slice(&[0xCA, 0x04, 0x00]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.sp, 0xFFFE);
+        assert_eq!(emulator.registers.cs, 0x0700);
+    }
+
+    #[test]
+    fn test_call_indirecto(){
+        //MOV BX,010A ; CALL BX ; CALL [0x0200] ; INT 20h ; 010A: ADD AX,1 ; RET
+        let mut emulator = load_program(&[0xBB, 0x0B, 0x01, 0xFF, 0xD3, 0xFF, 0x16, 0x00, 0x02, 0xCD, 0x20, 0x05, 0x01, 0x00, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0x010B);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 2);
+        assert_eq!(emulator.registers.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn test_call_far_indirecto(){
+        //CALL far [0x0200] ; INT 20h y en 0900:0010 MOV CL,3 ; RETF
+        let mut emulator = load_program(&[0xFF, 0x1E, 0x00, 0x02, 0xCD, 0x20]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0x0010);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA + 2, 0x0900);
+        emulator.memory[0x9010..0x9013].copy_from_slice(&[0xB1, 0x03, 0xCB]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 3);
+        assert_eq!(emulator.registers.cs, 0x0700);
+        assert_eq!(emulator.registers.ip, 0x0106);
+    }
+
+    #[test]
+    fn test_iret(){
+        //PUSHF con CF ; PUSH CS ; PUSH 0106 (via AX) ; IRET salta a 0106 y recupera las flags
+        //0100: 9C           PUSHF
+        //0101: 0E           PUSH CS
+        //0102: B8 08 01     MOV AX,0108
+        //0105: 50           PUSH AX
+        //0106: CF           IRET
+        //0107: 90           (no se ejecuta)
+        //0108: CD 20        INT 20h
+        let mut emulator = load_program(&[0x9C, 0x0E, 0xB8, 0x08, 0x01, 0x50, 0xCF, 0x90, 0xCD, 0x20]);
+        emulator.registers.flags = FLAG_IF | FLAG_CF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.flags, FLAG_IF | FLAG_CF);
+        assert_eq!(emulator.registers.ip, 0x010A);
+        assert_eq!(emulator.registers.sp, 0xFFFE);
+    }
+
+    //Instala en el vector 0 un manejador en 0800:0000 que guarda 0xDEAD en BX y termina
+    fn install_divide_error_handler(emulator: &mut Emulator8086){
+        emulator.set_w_in_memory(0x0000, 0x0000, 0x0000);
+        emulator.set_w_in_memory(0x0000, 0x0002, 0x0800);
+        emulator.memory[0x8000..0x8005].copy_from_slice(&[0xBB, 0xAD, 0xDE, 0xCD, 0x20]);
+    }
+
+    #[test]
+    fn test_mul_byte(){
+        //MOV AL,0x80 ; MOV BL,2 ; MUL BL
+        let mut emulator = load_program(&[0xB0, 0x80, 0xB3, 0x02, 0xF6, 0xE3, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0100);
+        assert_ne!(emulator.registers.flags & (FLAG_CF | FLAG_OF), 0);
+
+        //Resultado que cabe en AL: CF y OF a 0
+        let mut emulator = load_program(&[0xB0, 0x10, 0xB3, 0x02, 0xF6, 0xE3, 0xC3]);
+        emulator.registers.flags |= FLAG_CF | FLAG_OF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0020);
+        assert_eq!(emulator.registers.flags & (FLAG_CF | FLAG_OF), 0);
+    }
+
+    #[test]
+    fn test_mul_word_memoria(){
+        //MOV AX,0x1234 ; MUL word [0x0200]
+        let mut emulator = load_program(&[0xB8, 0x34, 0x12, 0xF7, 0x26, 0x00, 0x02, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0x0100);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x3400);
+        assert_eq!(emulator.registers.dx, 0x0012);
+        assert_ne!(emulator.registers.flags & (FLAG_CF | FLAG_OF), 0);
+    }
+
+    #[test]
+    fn test_imul(){
+        //MOV AL,-2 ; MOV CL,3 ; IMUL CL -> AX = -6, cabe en AL
+        let mut emulator = load_program(&[0xB0, 0xFE, 0xB1, 0x03, 0xF6, 0xE9, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0xFFFA);
+        assert_eq!(emulator.registers.flags & (FLAG_CF | FLAG_OF), 0);
+
+        //MOV AX,-300 ; MOV BX,300 ; IMUL BX -> DX:AX = -90000
+        let mut emulator = load_program(&[0xB8, 0xD4, 0xFE, 0xBB, 0x2C, 0x01, 0xF7, 0xEB, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(((emulator.registers.dx as u32) << 16 | emulator.registers.ax as u32) as i32, -90000);
+        assert_ne!(emulator.registers.flags & (FLAG_CF | FLAG_OF), 0);
+    }
+
+    #[test]
+    fn test_div(){
+        //MOV AX,100 ; MOV BL,7 ; DIV BL
+        let mut emulator = load_program(&[0xB8, 0x64, 0x00, 0xB3, 0x07, 0xF6, 0xF3, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x020E); //AH = 2, AL = 14
+
+        //DX:AX = 0x00012345 ; DIV word [BX]
+        let mut emulator = load_program(&[0xBA, 0x01, 0x00, 0xB8, 0x45, 0x23, 0xBB, 0x00, 0x02, 0xF7, 0x37, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0x0010);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x1234);
+        assert_eq!(emulator.registers.dx, 0x0005);
+    }
+
+    #[test]
+    fn test_div_entre_cero(){
+        //MOV CL,0 ; DIV CL lanza la interrupción 0
+        let mut emulator = load_program(&[0xB1, 0x00, 0xF6, 0xF1, 0xC3]);
+        install_divide_error_handler(&mut emulator);
+        emulator.registers.flags = FLAG_IF | FLAG_DF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 0xDEAD);
+        assert_eq!(emulator.registers.cs, 0x0800);
+        assert_eq!(emulator.registers.flags & (FLAG_IF | FLAG_DF), FLAG_DF);
+        //En el 8086 la dirección de retorno es la de la instrucción siguiente al DIV
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ss, emulator.registers.sp), 0x0104);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ss, emulator.registers.sp + 2), 0x0700);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ss, emulator.registers.sp + 4), FLAGS_RESERVED | FLAG_IF | FLAG_DF);
+    }
+
+    #[test]
+    fn test_div_overflow(){
+        //AX = 0x1000 / 2 no cabe en AL
+        let mut emulator = load_program(&[0xB8, 0x00, 0x10, 0xB3, 0x02, 0xF6, 0xF3, 0xC3]);
+        install_divide_error_handler(&mut emulator);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 0xDEAD);
+        assert_eq!(emulator.registers.ax, 0x1000);
+    }
+
+    #[test]
+    fn test_idiv(){
+        //MOV AX,-7 ; MOV BL,2 ; IDIV BL -> AL = -3, AH = -1
+        let mut emulator = load_program(&[0xB8, 0xF9, 0xFF, 0xB3, 0x02, 0xF6, 0xFB, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0xFFFD);
+
+        //DX:AX = -100000 ; MOV CX,1000 ; IDIV CX -> AX = -100, DX = 0
+        let mut emulator = load_program(&[0xBA, 0xFE, 0xFF, 0xB8, 0x60, 0x79, 0xB9, 0xE8, 0x03, 0xF7, 0xF9, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, (-100i16) as u16);
+        assert_eq!(emulator.registers.dx, 0);
+    }
+
+    #[test]
+    fn test_idiv_cociente_minimo(){
+        //AX = -128 / 1: el 8086 no admite el cociente -128
+        let mut emulator = load_program(&[0xB8, 0x80, 0xFF, 0xB3, 0x01, 0xF6, 0xFB, 0xC3]);
+        install_divide_error_handler(&mut emulator);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.bx, 0xDEAD);
+
+        //AX = -127 / 1 sí es válido
+        let mut emulator = load_program(&[0xB8, 0x81, 0xFF, 0xB3, 0x01, 0xF6, 0xFB, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0081);
+    }
+
+    #[test]
+    fn test_shl_shr_registro(){
+        //MOV AX,0x4001 ; SHL AX,1 ; MOV BL,0x03 ; SHR BL,1
+        let mut emulator = load_program(&[0xB8, 0x01, 0x40, 0xD1, 0xE0, 0xB3, 0x03, 0xD0, 0xEB, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x8002);
+        assert_eq!(emulator.registers.bx, 0x0001);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_shl_overflow(){
+        //SHL AL,1 con AL=0x40 cambia el signo: OF=1
+        let mut emulator = load_program(&[0xB0, 0x40, 0xD0, 0xE0, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0080);
+        assert_ne!(emulator.registers.flags & FLAG_OF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_sar_cl(){
+        //MOV AX,0x8000 ; MOV CL,15 ; SAR AX,CL
+        let mut emulator = load_program(&[0xB8, 0x00, 0x80, 0xB1, 0x0F, 0xD3, 0xF8, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0xFFFF);
+        assert_eq!(emulator.registers.flags & FLAG_OF, 0);
+    }
+
+    #[test]
+    fn test_shift_cl_sin_mascara(){
+        //MOV AX,0xFFFF ; MOV CL,32 ; SHL AX,CL. En el 8086 el resultado es 0 (en el 80286 el contador se enmascara)
+        let mut emulator = load_program(&[0xB8, 0xFF, 0xFF, 0xB1, 0x20, 0xD3, 0xE0, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0000);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+    }
+
+    #[test]
+    fn test_rotaciones_memoria(){
+        //ROL byte [BX],1 ; RCR word [BX+2],1 con CF del ROL
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xD0, 0x07, 0xD1, 0x5F, 0x02, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0x81);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA + 2, 0x0002);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0x03);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, DATA + 2), 0x8001);
+        assert_eq!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_ror_rcl_cl(){
+        //MOV DX,0x0001 ; MOV CL,4 ; ROR DX,CL ; RCL DX,CL
+        let mut emulator = load_program(&[0xBA, 0x01, 0x00, 0xB1, 0x04, 0xD3, 0xCA, 0xD3, 0xD2, 0xC3]);
+        run_program(&mut emulator);
+        //ROR deja 0x1000 con CF=0 y RCL 4 saca el bit 12 por el carry dejando DX a 0
+        assert_eq!(emulator.registers.dx, 0x0000);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+    }
+
+    #[test]
+    fn test_setmo_no_documentada(){
+        //D0 /6 (D0 F0) con AL=0 deja AL=0xFF; D2 /6 con CL=0 no modifica el operando
+        let mut emulator = load_program(&[0xD0, 0xF0, 0xB1, 0x00, 0xB3, 0x12, 0xD2, 0xF3, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x00FF);
+        assert_eq!(emulator.registers.bx, 0x0012);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+        assert_eq!(emulator.registers.flags & (FLAG_CF | FLAG_OF), 0);
+    }
+
+    //Ejecuta una sola instrucción (o una iteración de un REP)
+    fn step(emulator: &mut Emulator8086){
+        emulator.step().unwrap();
+    }
+
+    #[test]
+    fn test_rep_movsb(){
+        //MOV SI,0200 ; MOV DI,0300 ; MOV CX,5 ; REP MOVSB
+        let mut emulator = load_program(&[0xBE, 0x00, 0x02, 0xBF, 0x00, 0x03, 0xB9, 0x05, 0x00, 0xF3, 0xA4, 0xC3]);
+        emulator.registers.es = 0x0800;
+        emulator.memory[0x7200..0x7206].copy_from_slice(&[1, 2, 3, 4, 5, 6]);
+        run_program(&mut emulator);
+        assert_eq!(&emulator.memory[0x8300..0x8306], &[1, 2, 3, 4, 5, 0]);
+        assert_eq!(emulator.registers.cx, 0);
+        assert_eq!(emulator.registers.si, 0x0205);
+        assert_eq!(emulator.registers.di, 0x0305);
+    }
+
+    #[test]
+    fn test_movsw_df(){
+        //Con DF=1 MOVSW copia hacia atrás
+        let mut emulator = load_program(&[0xBE, 0x02, 0x02, 0xBF, 0x02, 0x03, 0xB9, 0x02, 0x00, 0xF3, 0xA5, 0xC3]);
+        emulator.registers.flags |= FLAG_DF;
+        emulator.set_w_in_memory(emulator.registers.ds, 0x0200, 0x1111);
+        emulator.set_w_in_memory(emulator.registers.ds, 0x0202, 0x2222);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.es, 0x0300), 0x1111);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.es, 0x0302), 0x2222);
+        assert_eq!(emulator.registers.si, 0x01FE);
+        assert_eq!(emulator.registers.di, 0x02FE);
+    }
+
+    #[test]
+    fn test_rep_cx_cero(){
+        //REP STOSB con CX=0 no escribe nada
+        let mut emulator = load_program(&[0xB0, 0xAA, 0xBF, 0x00, 0x03, 0xF3, 0xAA, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.es, 0x0300), 0x00);
+        assert_eq!(emulator.registers.di, 0x0300);
+    }
+
+    #[test]
+    fn test_rep_stosw_lodsb(){
+        //MOV AX,0xBEEF ; MOV DI,0300 ; MOV CX,3 ; REP STOSW ; MOV SI,0302 ; LODSB
+        let mut emulator = load_program(&[0xB8, 0xEF, 0xBE, 0xBF, 0x00, 0x03, 0xB9, 0x03, 0x00, 0xF3, 0xAB, 0xBE, 0x02, 0x03, 0xB8, 0x00, 0x00, 0xAC, 0xC3]);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.es, 0x0304), 0xBEEF);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.es, 0x0306), 0x0000);
+        assert_eq!(emulator.registers.ax, 0x00EF);
+        assert_eq!(emulator.registers.si, 0x0303);
+    }
+
+    #[test]
+    fn test_repe_cmpsb(){
+        //Compara "HOLA" con "HOJA": termina en la primera diferencia
+        let mut emulator = load_program(&[0xBE, 0x00, 0x02, 0xBF, 0x00, 0x03, 0xB9, 0x04, 0x00, 0xF3, 0xA6, 0xC3]);
+        emulator.memory[0x7200..0x7204].copy_from_slice(b"HOLA");
+        emulator.memory[0x7300..0x7304].copy_from_slice(b"HOJA");
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 1);
+        assert_eq!(emulator.registers.si, 0x0203);
+        assert_eq!(emulator.registers.flags & FLAG_ZF, 0);
+    }
+
+    #[test]
+    fn test_repne_scasb(){
+        //Busca el 0 final de una cadena
+        let mut emulator = load_program(&[0xB0, 0x00, 0xBF, 0x00, 0x03, 0xB9, 0xFF, 0xFF, 0xF2, 0xAE, 0xC3]);
+        emulator.memory[0x7300..0x7306].copy_from_slice(b"HOLA!\0");
+        run_program(&mut emulator);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+        assert_eq!(emulator.registers.di, 0x0306);
+        assert_eq!(emulator.registers.cx, 0xFFFF - 6);
+    }
+
+    #[test]
+    fn test_scasw_cmpsw(){
+        //SCASW y CMPSW sin repetición
+        let mut emulator = load_program(&[0xB8, 0x34, 0x12, 0xBF, 0x00, 0x03, 0xAF, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.es, 0x0300, 0x1234);
+        run_program(&mut emulator);
+        assert_ne!(emulator.registers.flags & FLAG_ZF, 0);
+        assert_eq!(emulator.registers.di, 0x0302);
+
+        let mut emulator = load_program(&[0xBE, 0x00, 0x02, 0xBF, 0x00, 0x03, 0xA7, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, 0x0200, 0x0001);
+        emulator.set_w_in_memory(emulator.registers.es, 0x0300, 0x0002);
+        run_program(&mut emulator);
+        assert_ne!(emulator.registers.flags & FLAG_CF, 0);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+    }
+
+    #[test]
+    fn test_string_prefijo_segmento(){
+        //ES: LODSB lee de ES:SI; CS: MOVSB lee de CS:SI pero escribe en ES:DI
+        let mut emulator = load_program(&[0xBE, 0x00, 0x02, 0x26, 0xAC, 0xBE, 0x00, 0x02, 0xBF, 0x00, 0x02, 0x2E, 0xA4, 0xC3]);
+        emulator.registers.es = 0x0800;
+        emulator.registers.ds = 0x0900;
+        emulator.set_b_in_memory(0x0800, 0x0200, 0x11);
+        emulator.set_b_in_memory(0x0700, 0x0200, 0x22);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0011);
+        assert_eq!(emulator.get_b_from_memory(0x0800, 0x0200), 0x22);
+        assert_eq!(emulator.get_b_from_memory(0x0900, 0x0200), 0x00);
+    }
+
+    #[test]
+    fn test_rep_interrumpido_varios_prefijos(){
+        //ES: REP MOVSB (26 F3 A4) copiando 4 bytes de ES:SI a ES:DI
+        //Tras la primera iteración llega una interrupción cuyo manejador es solo IRET.
+        //El 8086 vuelve al último prefijo (REP) y pierde ES:, así que el resto se copia desde DS:SI
+        let mut emulator = load_program(&[0xBE, 0x00, 0x02, 0xBF, 0x00, 0x03, 0xB9, 0x04, 0x00, 0x26, 0xF3, 0xA4, 0xC3]);
+        emulator.registers.es = 0x0800;
+        emulator.memory[0x8200..0x8204].copy_from_slice(&[0xE1, 0xE2, 0xE3, 0xE4]);
+        emulator.memory[0x7200..0x7204].copy_from_slice(&[0xD1, 0xD2, 0xD3, 0xD4]);
+        //Vector 0x40 -> 0900:0000 donde hay un IRET
+        emulator.set_w_in_memory(0x0000, 0x0100, 0x0000);
+        emulator.set_w_in_memory(0x0000, 0x0102, 0x0900);
+        emulator.set_b_in_memory(0x0900, 0x0000, 0xCF);
+        for _ in 0..4 {
+            step(&mut emulator);
+        }
+        assert_eq!(emulator.registers.cx, 3);
+        assert_eq!(emulator.registers.ip, 0x010B);
+        emulator.interrupt(0x40);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ss, emulator.registers.sp), 0x010A);
+        run_program(&mut emulator);
+        assert_eq!(&emulator.memory[0x8300..0x8304], &[0xE1, 0xD2, 0xD3, 0xD4]);
+        assert_eq!(emulator.registers.cx, 0);
+    }
+
+    #[test]
+    fn test_rep_sin_interrupcion_varios_prefijos(){
+        //El mismo programa sin interrupción copia todo desde ES:SI
+        let mut emulator = load_program(&[0xBE, 0x00, 0x02, 0xBF, 0x00, 0x03, 0xB9, 0x04, 0x00, 0x26, 0xF3, 0xA4, 0xC3]);
+        emulator.registers.es = 0x0800;
+        emulator.memory[0x8200..0x8204].copy_from_slice(&[0xE1, 0xE2, 0xE3, 0xE4]);
+        run_program(&mut emulator);
+        assert_eq!(&emulator.memory[0x8300..0x8304], &[0xE1, 0xE2, 0xE3, 0xE4]);
+    }
+
+    #[test]
+    fn test_mov_bp_usa_ss(){
+        //MOV BP,0x0200 ; MOV AX,[BP+2] lee de la pila (SS) y no de DS
+        let mut emulator = load_program(&[0xBD, 0x00, 0x02, 0x8B, 0x46, 0x02, 0xC3]);
+        emulator.registers.ss = 0x2000;
+        emulator.set_w_in_memory(0x2000, DATA + 2, 0x1234);
+        emulator.set_w_in_memory(emulator.registers.ds, DATA + 2, 0xFFFF);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x1234);
+    }
+
+    #[test]
+    fn test_modos_bp_por_defecto(){
+        //[BP+SI] y [BP+DI] usan SS, [BX+SI] usa DS
+        let mut emulator = load_program(&[0xBD, 0x00, 0x02, 0xBE, 0x10, 0x00, 0xBF, 0x20, 0x00,
+            0x8A, 0x02, //MOV AL,[BP+SI]
+            0x8A, 0x23, //MOV AH,[BP+DI]
+            0xBB, 0x00, 0x02, 0x8A, 0x18, //MOV BX,0200 ; MOV BL,[BX+SI]
+            0xC3]);
+        emulator.registers.ss = 0x2000;
+        emulator.set_b_in_memory(0x2000, DATA + 0x10, 0x11);
+        emulator.set_b_in_memory(0x2000, DATA + 0x20, 0x22);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA + 0x10, 0x33);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x2211);
+        assert_eq!(emulator.registers.bx, 0x0233);
+    }
+
+    #[test]
+    fn test_direccion_directa_usa_ds(){
+        //mod 00 r/m 110 es [d16] con DS aunque la codificación sea la de BP
+        let mut emulator = load_program(&[0x8B, 0x0E, 0x00, 0x02, 0xC3]);
+        emulator.registers.ss = 0x2000;
+        emulator.set_w_in_memory(emulator.registers.ds, DATA, 0xCAFE);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cx, 0xCAFE);
+    }
+
+    #[test]
+    fn test_prefijos_segmento(){
+        //ES: MOV AX,[BX] ; CS: MOV CX,[BX] ; DS: MOV DX,[BP] ; SS: MOV [BX],AL
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xBD, 0x00, 0x02,
+            0x26, 0x8B, 0x07,
+            0x2E, 0x8B, 0x0F,
+            0x3E, 0x8B, 0x56, 0x00,
+            0x36, 0x88, 0x07,
+            0xC3]);
+        emulator.registers.es = 0x0800;
+        emulator.registers.ds = 0x0900;
+        emulator.registers.ss = 0x2000;
+        emulator.set_w_in_memory(0x0800, DATA, 0x1111);
+        emulator.set_w_in_memory(0x0700, DATA, 0x2222);
+        emulator.set_w_in_memory(0x0900, DATA, 0x3333);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x1111);
+        assert_eq!(emulator.registers.cx, 0x2222);
+        assert_eq!(emulator.registers.dx, 0x3333);
+        assert_eq!(emulator.get_b_from_memory(0x2000, DATA), 0x11);
+        //El prefijo solo afecta a la instrucción que le sigue
+        assert_eq!(emulator.get_w_from_memory(0x0900, DATA), 0x3333);
+    }
+
+    #[test]
+    fn test_prefijo_segmento_aritmetica(){
+        //ES: ADD [BX+SI],AX ; ES: SUB AX,[BX+SI]
+        let mut emulator = load_program(&[0xB8, 0x01, 0x00, 0xBB, 0x00, 0x02, 0xBE, 0x00, 0x00,
+            0x26, 0x01, 0x00,
+            0x26, 0x2B, 0x00,
+            0xC3]);
+        emulator.registers.es = 0x0800;
+        emulator.set_w_in_memory(0x0800, DATA, 0x00FF);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_w_from_memory(0x0800, DATA), 0x0100);
+        assert_eq!(emulator.registers.ax, 0xFF01);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, DATA), 0x0000);
+    }
+
+    #[test]
+    fn test_add_formas_byte(){
+        //ADD [BX],AL con resultado negativo en byte activa SF ; ADD CL,[BX]
+        let mut emulator = load_program(&[0xBB, 0x00, 0x02, 0xB0, 0x01, 0x00, 0x07, 0xB1, 0x01, 0x02, 0x0F, 0xC3]);
+        emulator.set_b_in_memory(emulator.registers.ds, DATA, 0x7F);
+        run_program(&mut emulator);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, DATA), 0x80);
+        assert_eq!(emulator.registers.cx, 0x0081);
+        assert_ne!(emulator.registers.flags & FLAG_SF, 0);
+    }
+
+    #[test]
+    fn test_direccion_fisica_20_bits(){
+        assert_eq!(Emulator8086::physical_address(0x0700, 0x0100), 0x07100);
+        assert_eq!(Emulator8086::physical_address(0xFFFF, 0x0010), 0x00000);
+        assert_eq!(Emulator8086::physical_address(0xFFFF, 0xFFFF), 0x0FFEF);
+    }
+
+    #[test]
+    fn test_word_en_ffff_da_la_vuelta_en_el_segmento(){
+        let mut emulator = Emulator8086::new();
+        emulator.set_w_in_memory(0x1000, 0xFFFF, 0xABCD);
+        assert_eq!(emulator.memory[0x1FFFF], 0xCD);
+        assert_eq!(emulator.memory[0x10000], 0xAB);
+        assert_eq!(emulator.memory[0x20000], 0x00);
+        assert_eq!(emulator.get_w_from_memory(0x1000, 0xFFFF), 0xABCD);
+
+        //FFFF:FFFF es la dirección 0x0FFEF y su byte alto está en FFFF:0000 = 0xFFFF0
+        emulator.set_w_in_memory(0xFFFF, 0xFFFF, 0x1234);
+        assert_eq!(emulator.memory[0x0FFEF], 0x34);
+        assert_eq!(emulator.memory[0xFFFF0], 0x12);
+    }
+
+    #[test]
+    fn test_direccion_efectiva_da_la_vuelta(){
+        //MOV BX,0xFFFF ; MOV SI,2 ; MOV AX,[BX+SI+0x01FF] -> offset 0x0200
+        let mut emulator = load_program(&[0xBB, 0xFF, 0xFF, 0xBE, 0x02, 0x00, 0x8B, 0x80, 0xFF, 0x01, 0xC3]);
+        emulator.set_w_in_memory(emulator.registers.ds, 0x0200, 0x5555);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x5555);
+    }
+
+    #[test]
+    fn test_acceso_ffff_ffff(){
+        //DS=FFFF ; MOV AX,[0xFFFF] no debe salirse de la memoria
+        let mut emulator = load_program(&[0x8B, 0x06, 0xFF, 0xFF, 0xC3]);
+        emulator.memory[0x0FFEF] = 0x78;
+        emulator.memory[0xFFFF0] = 0x56;
+        emulator.registers.ds = 0xFFFF;
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x5678);
+    }
+
+    #[test]
+    fn test_fetch_ip_da_la_vuelta(){
+        //Una instrucción que empieza en IP=FFFF sigue en el offset 0 del mismo segmento
+        let mut emulator = Emulator8086::new();
+        emulator.registers.cs = 0x2000;
+        emulator.registers.ip = 0xFFFF;
+        emulator.set_b_in_memory(0x2000, 0xFFFF, 0xB0);
+        emulator.set_b_in_memory(0x2000, 0x0000, 0x42);
+        let instruction = emulator.fetch();
+        emulator.decode_and_execute(instruction).unwrap();
+        assert_eq!(emulator.registers.ax, 0x0042);
+        assert_eq!(emulator.registers.ip, 0x0001);
+    }
+
+    //Carga un .com de tests/ y lo ejecuta hasta que termine
+    fn run_com(path: &str)->Emulator8086{
+        let mut emulator = Emulator8086::new();
+        if let Err(e) = emulator.load_com(path) {
+            panic!("Error al cargar el programa: {:?}", e);
+        }
+        run_program(&mut emulator);
+        emulator
+    }
+
+    #[test]
+    fn test_mov_word_reg(){
+        let emulator = run_com("./tests/mov/MOV_WORD_REG.com");
+        assert_eq!(emulator.registers.ax, 0x1234);
+        assert_eq!(emulator.registers.bx, 0x1234);
+        assert_eq!(emulator.registers.cx, 0x1234);
+        assert_eq!(emulator.registers.dx, 0x1234);
+        assert_eq!(emulator.registers.si, 0x1234);
+        assert_eq!(emulator.registers.di, 0x1234);
+    }
+
+    #[test]
+    fn test_mov_word_mem(){
+        let emulator = run_com("./tests/mov/MOV_WORD_MEM.com");
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, 0x0200), 0xBEEF);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, 0x0202), 0xBEEF);
+        assert_eq!(emulator.registers.cx, 0xBEEF);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, 0x0204), 0xEF);
+        assert_eq!(emulator.registers.dx, 0x00EF);
+    }
+
+    #[test]
+    fn test_mov_seg(){
+        let emulator = run_com("./tests/mov/MOV_SEG.com");
+        assert_eq!(emulator.registers.es, 0x0800);
+        assert_eq!(emulator.registers.bx, 0x0800);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, 0x0200), 0x0700);
+        assert_eq!(emulator.registers.dx, 0x0700);
+        assert_eq!(emulator.registers.ss, 0x0800);
+    }
+
+    #[test]
+    fn test_mov_acc(){
+        let emulator = run_com("./tests/mov/MOV_ACC.com");
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, 0x0200), 0x1234);
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, 0x0202), 0x34);
+        assert_eq!(emulator.registers.ax, 0x3434);
+        assert_eq!(emulator.registers.cx, 0x1234);
+    }
+
+    #[test]
+    fn test_mov_imm_mem(){
+        let emulator = run_com("./tests/mov/MOV_IMM_MEM.com");
+        assert_eq!(emulator.get_b_from_memory(emulator.registers.ds, 0x0200), 0x55);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, 0x0202), 0x1234);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, 0x1200), 0xABCD);
+        assert_eq!(emulator.get_w_from_memory(emulator.registers.ds, 0x0300), 0x5678);
+        assert_eq!(emulator.registers.cx, 0x0000);
+    }
+
+    #[test]
+    fn test_mov_acumulador_prefijo(){
+        //ES: MOV AL,[0x0200]
+        let mut emulator = load_program(&[0x26, 0xA0, 0x00, 0x02, 0xC3]);
+        emulator.registers.es = 0x0800;
+        emulator.set_b_in_memory(0x0800, DATA, 0x99);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.ax, 0x0099);
+    }
+
+    #[test]
+    fn test_mov_cs(){
+        //MOV AX,0800 ; MOV CS,AX salta a 0800:0105
+        let mut emulator = load_program(&[0xB8, 0x00, 0x08, 0x8E, 0xC8, 0xC3]);
+        emulator.set_b_in_memory(0x0800, 0x0105, 0xCD);
+        emulator.set_b_in_memory(0x0800, 0x0106, 0x20);
+        run_program(&mut emulator);
+        assert_eq!(emulator.registers.cs, 0x0800);
+        assert_eq!(emulator.registers.ip, 0x0107);
+    }
+
+    #[test]
+    fn test_mov_ss_inhibe_interrupciones(){
+        //MOV SS,AX ; MOV SP,0x0100 ; NOP
+        let mut emulator = load_program(&[0x8E, 0xD0, 0xBC, 0x00, 0x01, 0xC3]);
+        step(&mut emulator);
+        assert!(emulator.interrupt_shadow);
+        step(&mut emulator);
+        assert!(!emulator.interrupt_shadow);
+        assert_eq!(emulator.registers.sp, 0x0100);
+    }
+
+    #[test]
+    fn test_asm_neg_e_imul(){
+        run_asm("mov al,1\nneg al", |cpu| { cpu.reg("al", 0xFF).cf(true).sf(true).of(false); });
+        run_asm("mov al,80h\nneg al", |cpu| { cpu.reg("al", 0x80).of(true); });
+        run_asm("mov ax,-3\nmov bx,7\nimul bx", |cpu| { cpu.reg("ax", 0xFFEB).reg("dx", 0xFFFF).cf(false).of(false); });
+    }
+
+    #[test]
+    fn test_asm_rep_stosb_y_bucle(){
+        run_asm("push ds\npop es\nmov di,200h\nmov cx,4\nmov al,0AAh\ncld\nrep stosb", |cpu| {
+            cpu.bytes(DATA, &[0xAA; 4]).byte(DATA + 4, 0).reg("cx", 0).reg("di", DATA + 4);
+        });
+        run_asm("xor ax,ax\nmov cx,10\n.suma:\nadd ax,cx\nloop .suma", |cpu| { cpu.reg("ax", 55).zf(false); });
+    }
+
+    #[test]
+    fn test_asm_call_ret_y_salida(){
+        run_asm("call doble\nmov ah,4Ch\nint 21h\ndoble:\nmov al,21\nshl al,1\nret", |cpu| { cpu.exit_code(42); });
+    }
+}
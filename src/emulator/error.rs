@@ -0,0 +1,95 @@
+use std::fmt;
+
+//Errores que puede devolver el emulador al cargar o ejecutar un programa
+#[derive(Debug)]
+pub enum EmulatorError {
+    //Opcode (o extensión del ModRM) sin implementar, con la dirección CS:IP de la instrucción
+    //y los bytes leídos de ella hasta detectar el error
+    UnimplementedOpcode { cs: u16, ip: u16, bytes: Vec<u8> },
+    //Acceso a una dirección física fuera de la memoria del emulador
+    MemoryFault { address: usize },
+    //No se ha podido leer o cargar el programa
+    Load(std::io::Error),
+    //La cabecera o la tabla de relocalización del .exe no son válidas
+    InvalidExe(String),
+    //El procesador está parado por HLT, solo una interrupción lo saca de ese estado
+    Halted,
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::UnimplementedOpcode { cs, ip, bytes } => {
+                write!(f, "Opcode no implementado en {:04X}:{:04X}:", cs, ip)?;
+                for byte in bytes {
+                    write!(f, " {:02X}", byte)?;
+                }
+                Ok(())
+            },
+            EmulatorError::MemoryFault { address } => write!(f, "Acceso fuera de la memoria en 0x{:05X}", address),
+            EmulatorError::Load(error) => write!(f, "Error al cargar el programa: {}", error),
+            EmulatorError::InvalidExe(reason) => write!(f, "Ejecutable MZ no válido: {}", reason),
+            EmulatorError::Halted => write!(f, "El procesador está parado (HLT)"),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmulatorError::Load(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for EmulatorError {
+    fn from(error: std::io::Error) -> Self {
+        EmulatorError::Load(error)
+    }
+}
+
+//Error al ensamblar un programa, con la línea del código fuente en la que se ha producido (empezando en 1)
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblerError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Línea {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssemblerError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mensaje_opcode_no_implementado() {
+        let error = EmulatorError::UnimplementedOpcode { cs: 0x0700, ip: 0x0100, bytes: vec![0xFE, 0xD0] };
+        assert_eq!(error.to_string(), "Opcode no implementado en 0700:0100: FE D0");
+    }
+
+    #[test]
+    fn test_error_de_carga() {
+        let error: EmulatorError = std::io::Error::new(std::io::ErrorKind::NotFound, "no existe").into();
+        assert!(matches!(error, EmulatorError::Load(_)));
+        assert!(std::error::Error::source(&error).is_some());
+    }
+
+    #[test]
+    fn test_mensaje_exe_no_valido() {
+        let error = EmulatorError::InvalidExe("falta la firma MZ".to_string());
+        assert_eq!(error.to_string(), "Ejecutable MZ no válido: falta la firma MZ");
+    }
+
+    #[test]
+    fn test_mensaje_error_ensamblador() {
+        let error = AssemblerError { line: 3, message: "instrucción desconocida: movx".to_string() };
+        assert_eq!(error.to_string(), "Línea 3: instrucción desconocida: movx");
+    }
+}
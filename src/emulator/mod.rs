@@ -1,4 +1,12 @@
+#[allow(clippy::module_inception)]
 pub mod emulator;
 pub mod registers;
 pub mod auxiliar;
-pub mod opcodes;
\ No newline at end of file
+pub mod opcodes;
+pub mod decoder;
+pub mod disassembler;
+pub mod assembler;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod error;
+pub mod trace;
\ No newline at end of file
@@ -1,12 +1,106 @@
-use std::collections::HashSet;
+use crate::emulator::decoder::Mnemonic::{self, *};
+use crate::emulator::decoder::Pattern::{self, *};
 
-pub static OPCODES_MOV: [u8; 28] = [
-    0x8A, 0x88, 0x89, 0x8B, 0x8C, 0x8E, 
-    0xA0, 0xA1, 0xA2, 0xA3,
-    0xB0, 0xB1, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 
-    0xBA, 0xBB, 0xBC, 0xBD, 0xBE, 0xBF,
-    0xC6, 0xC7
-];
+//Entrada de la tabla de opcodes: instrucción con la forma de sus dos operandos, prefijo
+//o grupo en el que el campo reg del ModRM elige la instrucción
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Entry {
+    Op(Mnemonic, Pattern, Pattern),
+    Prefix,
+    Group(&'static [(Mnemonic, Pattern, Pattern); 8]),
+}
+
+use Entry::{Group, Op, Prefix};
+
+const fn group(mnemonics: [Mnemonic; 8], first: Pattern, second: Pattern)->[(Mnemonic, Pattern, Pattern); 8]{
+    let mut entries = [(Invalid, first, second); 8];
+    let mut i = 0;
+    while i < 8 {
+        entries[i].0 = mnemonics[i];
+        i += 1;
+    }
+    entries
+}
+
+//Grupos 80-83: /0 ADD  /1 OR  /2 ADC  /3 SBB  /4 AND  /5 SUB  /6 XOR  /7 CMP
+const ALU: [Mnemonic; 8] = [Add, Or, Adc, Sbb, And, Sub, Xor, Cmp];
+//Grupos D0-D3: /6 es SETMO, no documentada
+const SHIFT: [Mnemonic; 8] = [Rol, Ror, Rcl, Rcr, Shl, Shr, Setmo, Sar];
 
-pub static OPCODES_AND: [u8; 6] = [0x20, 0x21, 0x22, 0x23, 0x24, 0x25];
+static GROUP_80: [(Mnemonic, Pattern, Pattern); 8] = group(ALU, Eb, Ib);
+static GROUP_81: [(Mnemonic, Pattern, Pattern); 8] = group(ALU, Ew, Iw);
+static GROUP_83: [(Mnemonic, Pattern, Pattern); 8] = group(ALU, Ew, Is);
+static GROUP_D0: [(Mnemonic, Pattern, Pattern); 8] = group(SHIFT, Eb, One);
+static GROUP_D1: [(Mnemonic, Pattern, Pattern); 8] = group(SHIFT, Ew, One);
+static GROUP_D2: [(Mnemonic, Pattern, Pattern); 8] = group(SHIFT, Eb, Cl);
+static GROUP_D3: [(Mnemonic, Pattern, Pattern); 8] = group(SHIFT, Ew, Cl);
+//8F solo tiene /0 POP r/m16
+static GROUP_8F: [(Mnemonic, Pattern, Pattern); 8] = group([Pop, Invalid, Invalid, Invalid, Invalid, Invalid, Invalid, Invalid], Ew, None);
+//F6/F7: /1 es un alias no documentado de TEST, solo TEST lleva inmediato
+static GROUP_F6: [(Mnemonic, Pattern, Pattern); 8] = [
+    (Test, Eb, Ib), (Test, Eb, Ib), (Not, Eb, None), (Neg, Eb, None),
+    (Mul, Eb, None), (Imul, Eb, None), (Div, Eb, None), (Idiv, Eb, None),
+];
+static GROUP_F7: [(Mnemonic, Pattern, Pattern); 8] = [
+    (Test, Ew, Iw), (Test, Ew, Iw), (Not, Ew, None), (Neg, Ew, None),
+    (Mul, Ew, None), (Imul, Ew, None), (Div, Ew, None), (Idiv, Ew, None),
+];
+//FE solo tiene /0 INC y /1 DEC, el resto se decodifica igualmente para consumir el desplazamiento
+static GROUP_FE: [(Mnemonic, Pattern, Pattern); 8] = group([Inc, Dec, Invalid, Invalid, Invalid, Invalid, Invalid, Invalid], Eb, None);
+static GROUP_FF: [(Mnemonic, Pattern, Pattern); 8] = [
+    (Inc, Ew, None), (Dec, Ew, None), (Call, Ew, None), (CallFar, Ep, None),
+    (Jmp, Ew, None), (JmpFar, Ep, None), (Push, Ew, None), (Invalid, Ew, None),
+];
 
+//Tabla de los 256 opcodes del 8086. Los que no existen (60-6F, C0, C1, C8, C9, D6 y F1, alias en el 8086
+//de otras instrucciones) quedan como Invalid hasta que se emulen
+pub(crate) static OPCODE_TABLE: [Entry; 256] = [
+    //00
+    Op(Add, Eb, Gb), Op(Add, Ew, Gw), Op(Add, Gb, Eb), Op(Add, Gw, Ew), Op(Add, Al, Ib), Op(Add, Ax, Iw), Op(Push, Zs, None), Op(Pop, Zs, None),
+    Op(Or, Eb, Gb), Op(Or, Ew, Gw), Op(Or, Gb, Eb), Op(Or, Gw, Ew), Op(Or, Al, Ib), Op(Or, Ax, Iw), Op(Push, Zs, None), Op(Pop, Zs, None),
+    //10
+    Op(Adc, Eb, Gb), Op(Adc, Ew, Gw), Op(Adc, Gb, Eb), Op(Adc, Gw, Ew), Op(Adc, Al, Ib), Op(Adc, Ax, Iw), Op(Push, Zs, None), Op(Pop, Zs, None),
+    Op(Sbb, Eb, Gb), Op(Sbb, Ew, Gw), Op(Sbb, Gb, Eb), Op(Sbb, Gw, Ew), Op(Sbb, Al, Ib), Op(Sbb, Ax, Iw), Op(Push, Zs, None), Op(Pop, Zs, None),
+    //20
+    Op(And, Eb, Gb), Op(And, Ew, Gw), Op(And, Gb, Eb), Op(And, Gw, Ew), Op(And, Al, Ib), Op(And, Ax, Iw), Prefix, Op(Daa, None, None),
+    Op(Sub, Eb, Gb), Op(Sub, Ew, Gw), Op(Sub, Gb, Eb), Op(Sub, Gw, Ew), Op(Sub, Al, Ib), Op(Sub, Ax, Iw), Prefix, Op(Das, None, None),
+    //30
+    Op(Xor, Eb, Gb), Op(Xor, Ew, Gw), Op(Xor, Gb, Eb), Op(Xor, Gw, Ew), Op(Xor, Al, Ib), Op(Xor, Ax, Iw), Prefix, Op(Aaa, None, None),
+    Op(Cmp, Eb, Gb), Op(Cmp, Ew, Gw), Op(Cmp, Gb, Eb), Op(Cmp, Gw, Ew), Op(Cmp, Al, Ib), Op(Cmp, Ax, Iw), Prefix, Op(Aas, None, None),
+    //40
+    Op(Inc, Zw, None), Op(Inc, Zw, None), Op(Inc, Zw, None), Op(Inc, Zw, None), Op(Inc, Zw, None), Op(Inc, Zw, None), Op(Inc, Zw, None), Op(Inc, Zw, None),
+    Op(Dec, Zw, None), Op(Dec, Zw, None), Op(Dec, Zw, None), Op(Dec, Zw, None), Op(Dec, Zw, None), Op(Dec, Zw, None), Op(Dec, Zw, None), Op(Dec, Zw, None),
+    //50
+    Op(Push, Zw, None), Op(Push, Zw, None), Op(Push, Zw, None), Op(Push, Zw, None), Op(Push, Zw, None), Op(Push, Zw, None), Op(Push, Zw, None), Op(Push, Zw, None),
+    Op(Pop, Zw, None), Op(Pop, Zw, None), Op(Pop, Zw, None), Op(Pop, Zw, None), Op(Pop, Zw, None), Op(Pop, Zw, None), Op(Pop, Zw, None), Op(Pop, Zw, None),
+    //60
+    Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None),
+    Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None), Op(Invalid, None, None),
+    //70
+    Op(Jo, Rb, None), Op(Jno, Rb, None), Op(Jb, Rb, None), Op(Jae, Rb, None), Op(Je, Rb, None), Op(Jne, Rb, None), Op(Jbe, Rb, None), Op(Ja, Rb, None),
+    Op(Js, Rb, None), Op(Jns, Rb, None), Op(Jp, Rb, None), Op(Jnp, Rb, None), Op(Jl, Rb, None), Op(Jge, Rb, None), Op(Jle, Rb, None), Op(Jg, Rb, None),
+    //80, 82 es un alias de 80 en el 8086
+    Group(&GROUP_80), Group(&GROUP_81), Group(&GROUP_80), Group(&GROUP_83), Op(Test, Eb, Gb), Op(Test, Ew, Gw), Op(Xchg, Eb, Gb), Op(Xchg, Ew, Gw),
+    Op(Mov, Eb, Gb), Op(Mov, Ew, Gw), Op(Mov, Gb, Eb), Op(Mov, Gw, Ew), Op(Mov, Ew, Sw), Op(Lea, Gw, Ew), Op(Mov, Sw, Ew), Group(&GROUP_8F),
+    //90
+    Op(Nop, None, None), Op(Xchg, Ax, Zw), Op(Xchg, Ax, Zw), Op(Xchg, Ax, Zw), Op(Xchg, Ax, Zw), Op(Xchg, Ax, Zw), Op(Xchg, Ax, Zw), Op(Xchg, Ax, Zw),
+    Op(Cbw, None, None), Op(Cwd, None, None), Op(CallFar, Ap, None), Op(Wait, None, None), Op(Pushf, None, None), Op(Popf, None, None), Op(Sahf, None, None), Op(Lahf, None, None),
+    //A0
+    Op(Mov, Al, Ob), Op(Mov, Ax, Ow), Op(Mov, Ob, Al), Op(Mov, Ow, Ax), Op(Movsb, None, None), Op(Movsw, None, None), Op(Cmpsb, None, None), Op(Cmpsw, None, None),
+    Op(Test, Al, Ib), Op(Test, Ax, Iw), Op(Stosb, None, None), Op(Stosw, None, None), Op(Lodsb, None, None), Op(Lodsw, None, None), Op(Scasb, None, None), Op(Scasw, None, None),
+    //B0
+    Op(Mov, Zb, Ib), Op(Mov, Zb, Ib), Op(Mov, Zb, Ib), Op(Mov, Zb, Ib), Op(Mov, Zb, Ib), Op(Mov, Zb, Ib), Op(Mov, Zb, Ib), Op(Mov, Zb, Ib),
+    Op(Mov, Zw, Iw), Op(Mov, Zw, Iw), Op(Mov, Zw, Iw), Op(Mov, Zw, Iw), Op(Mov, Zw, Iw), Op(Mov, Zw, Iw), Op(Mov, Zw, Iw), Op(Mov, Zw, Iw),
+    //C0
+    Op(Invalid, None, None), Op(Invalid, None, None), Op(Ret, Iw, None), Op(Ret, None, None), Op(Les, Gw, Ep), Op(Lds, Gw, Ep), Op(Mov, Eb, Ib), Op(Mov, Ew, Iw),
+    Op(Invalid, None, None), Op(Invalid, None, None), Op(Retf, Iw, None), Op(Retf, None, None), Op(Int3, None, None), Op(Int, Ib, None), Op(Into, None, None), Op(Iret, None, None),
+    //D0, AAM y AAD llevan la base (0x0A) como inmediato
+    Group(&GROUP_D0), Group(&GROUP_D1), Group(&GROUP_D2), Group(&GROUP_D3), Op(Aam, Ib, None), Op(Aad, Ib, None), Op(Invalid, None, None), Op(Xlat, None, None),
+    Op(Esc, Cop, Ew), Op(Esc, Cop, Ew), Op(Esc, Cop, Ew), Op(Esc, Cop, Ew), Op(Esc, Cop, Ew), Op(Esc, Cop, Ew), Op(Esc, Cop, Ew), Op(Esc, Cop, Ew),
+    //E0
+    Op(Loopnz, Rb, None), Op(Loopz, Rb, None), Op(Loop, Rb, None), Op(Jcxz, Rb, None), Op(In, Al, Ib), Op(In, Ax, Ib), Op(Out, Ib, Al), Op(Out, Ib, Ax),
+    Op(Call, Rw, None), Op(Jmp, Rw, None), Op(JmpFar, Ap, None), Op(Jmp, Rb, None), Op(In, Al, Dx), Op(In, Ax, Dx), Op(Out, Dx, Al), Op(Out, Dx, Ax),
+    //F0
+    Prefix, Op(Invalid, None, None), Prefix, Prefix, Op(Hlt, None, None), Op(Cmc, None, None), Group(&GROUP_F6), Group(&GROUP_F7),
+    Op(Clc, None, None), Op(Stc, None, None), Op(Cli, None, None), Op(Sti, None, None), Op(Cld, None, None), Op(Std, None, None), Group(&GROUP_FE), Group(&GROUP_FF),
+];
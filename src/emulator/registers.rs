@@ -1,3 +1,8 @@
+//Nombres de los registros según su indice en el ModRM, para las trazas y el desensamblador
+pub const REGISTER_NAMES_W: [&str; 8] = ["AX", "CX", "DX", "BX", "SP", "BP", "SI", "DI"];
+pub const REGISTER_NAMES_B: [&str; 8] = ["AL", "CL", "DL", "BL", "AH", "CH", "DH", "BH"];
+pub const BASE_ADDRESS_NAMES: [&str; 8] = ["BX+SI", "BX+DI", "BP+SI", "BP+DI", "SI", "DI", "BP", "BX"];
+
 pub struct Registers {
     // Registros generales de 16 bits
     pub ax: u16,
@@ -69,213 +74,104 @@ impl Registers{
 
     //Solo se usan para cuando son la base de la instrucción
 
-    //Según el indice en el modo de redireccionamiento devuelve el valor //Correcto
-    // pub fn get_register_by_index(&self,index:u8)->u16{
-    //     //Print del registro que se va a leer
-    //     match index{
-    //         0b000 => self.ax,
-    //         0b001 => self.cx,
-    //         0b010 => self.dx,
-    //         0b011 => self.bx,
-    //         0b100 => self.sp,
-    //         0b101 => self.bp,
-    //         0b110 => self.si,
-    //         0b111 => self.di,
-    //         _ => panic!("Indice de registro no valido: {}", index),
-    //     }
-    // }
+    //Los índices salen de campos de 3 bits del ModRM o del opcode, así que solo se miran esos 3 bits
 
     //Se usa para saber el registro fuente desde el cual cogemos datos por ejemplo MOV AX, BX esta funcion nos sirve para saber BX
     pub fn get_register_by_index(&self, index: u8) -> u16 {
-        match index {
-            0b000 => {
-                println!("Accediendo al registro AX");
-                self.ax
-            },
-            0b001 => {
-                println!("Accediendo al registro CX");
-                self.cx
-            },
-            0b010 => {
-                println!("Accediendo al registro DX");
-                self.dx
-            },
-            0b011 => {
-                println!("Accediendo al registro BX");
-                self.bx
-            },
-            0b100 => {
-                println!("Accediendo al registro SP");
-                self.sp
-            },
-            0b101 => {
-                println!("Accediendo al registro BP");
-                self.bp
-            },
-            0b110 => {
-                println!("Accediendo al registro SI");
-                self.si
-            },
-            0b111 => {
-                println!("Accediendo al registro DI");
-                self.di
-            },
-            _ => panic!("Índice de registro no válido: {}", index),
+        match index & 0x07 {
+            0b000 => self.ax,
+            0b001 => self.cx,
+            0b010 => self.dx,
+            0b011 => self.bx,
+            0b100 => self.sp,
+            0b101 => self.bp,
+            0b110 => self.si,
+            _ => self.di,
         }
     }
 
     //Devuelve la parte baja o alta del registro según el indice solo AX,BX,CX,DX
     pub fn get_register_by_index_byte(&self, index: u8)->u8{
-        match index{
-            0b000 => {
-                println!("Accediendo a la parte baja del registro AX");
-                self.get_low_byte(self.ax)
-            },
-            0b001 => {
-                println!("Accediendo a la parte baja del registro CX");
-                self.get_low_byte(self.cx)
-            },
-            0b010 => {
-                println!("Accediendo a la parte baja del registro DX");
-                self.get_low_byte(self.dx)
-            },
-            0b011 => {
-                println!("Accediendo a la parte baja del registro BX");
-                self.get_low_byte(self.bx)
-            },
-            0b100 => {
-                println!("Accediendo a la parte alta del registro AX");
-                self.get_high_byte(self.ax)
-            },
-            0b101 => {
-                println!("Accediendo a la parte alta del registro CX");
-                self.get_high_byte(self.cx)
-            },
-            0b110 => {
-                println!("Accediendo a la parte alta del registro DX");
-                self.get_high_byte(self.dx)
-            },
-            0b111 => {
-                println!("Accediendo a la parte alta del registro BX");
-                self.get_high_byte(self.bx)
-            },
-            _ => panic!("Indice de registro no valido: {}", index),
+        match index & 0x07 {
+            0b000 => self.get_low_byte(self.ax),
+            0b001 => self.get_low_byte(self.cx),
+            0b010 => self.get_low_byte(self.dx),
+            0b011 => self.get_low_byte(self.bx),
+            0b100 => self.get_high_byte(self.ax),
+            0b101 => self.get_high_byte(self.cx),
+            0b110 => self.get_high_byte(self.dx),
+            _ => self.get_high_byte(self.bx),
         }
     }
     
     //Se usa para saber el registro destino al cual vamos a escribir por ejemplo MOV AX, BX esta funcion nos sirve para saber AX
     pub fn write_register_by_index(&mut self, index: u8, value: u16){
-        match index {
-            0b000 => {
-                println!("Escribiendo en el registro AX el valor: {}", value);
-                self.ax = value
-            },
-            0b001 => {
-                println!("Escribiendo en el registro CX el valor: {}", value);
-                self.cx = value
-            },
-            0b010 => {
-                println!("Escribiendo en el registro DX el valor: {}", value);
-                self.dx = value
-            },
-            0b011 => {
-                println!("Escribiendo en el registro BX el valor: {}", value);
-                self.bx = value
-            },
-            0b100 => {
-                println!("Escribiendo en el registro SP el valor: {}", value);
-                self.sp = value
-            },
-            0b101 => {
-                println!("Escribiendo en el registro BP el valor: {}", value);
-                self.bp = value
-            },
-            0b110 => {
-                println!("Escribiendo en el registro SI el valor: {}", value);
-                self.si = value
-            },
-            0b111 => {
-                println!("Escribiendo en el registro DI el valor: {}", value);
-                self.di = value
-            },
-            _ => panic!("Índice de registro no válido: {}", index),
+        match index & 0x07 {
+            0b000 => self.ax = value,
+            0b001 => self.cx = value,
+            0b010 => self.dx = value,
+            0b011 => self.bx = value,
+            0b100 => self.sp = value,
+            0b101 => self.bp = value,
+            0b110 => self.si = value,
+            _ => self.di = value,
         }
     }
 
     pub fn write_register_by_index_byte(&mut self, index: u8, value: u8){
-        match index{
-            0b000 => {
-                println!("Escribiendo en la parte baja del registro AX el valor: {}", value);
-                self.ax = (self.ax & 0xFF00) | value as u16
-            },
-            0b001 => {
-                println!("Escribiendo en la parte baja del registro CX el valor: {}", value);
-                self.cx = (self.cx & 0xFF00) | value as u16
-            },
-            0b010 => {
-                println!("Escribiendo en la parte baja del registro DX el valor: {}", value);
-                self.dx = (self.dx & 0xFF00) | value as u16
-            },
-            0b011 => {
-                println!("Escribiendo en la parte baja del registro BX el valor: {}", value);
-                self.bx = (self.bx & 0xFF00) | value as u16
-            },
-            0b100 => {
-                println!("Escribiendo en la parte alta del registro AX el valor: {}", value);
-                self.ax = (self.ax & 0x00FF) | (value as u16) << 8
-            },
-            0b101 => {
-                println!("Escribiendo en la parte alta del registro CX el valor: {}", value);
-                self.cx = (self.cx & 0x00FF) | (value as u16) << 8
-            },
-            0b110 => {
-                println!("Escribiendo en la parte alta del registro DX el valor: {}", value);
-                self.dx = (self.dx & 0x00FF) | (value as u16) << 8
-            },
-            0b111 => {
-                println!("Escribiendo en la parte alta del registro BX el valor: {}", value);
-                self.bx = (self.bx & 0x00FF) | (value as u16) << 8
-            },
-            _ => panic!("Indice de registro no valido: {}", index),
+        match index & 0x07 {
+            0b000 => self.ax = (self.ax & 0xFF00) | value as u16,
+            0b001 => self.cx = (self.cx & 0xFF00) | value as u16,
+            0b010 => self.dx = (self.dx & 0xFF00) | value as u16,
+            0b011 => self.bx = (self.bx & 0xFF00) | value as u16,
+            0b100 => self.ax = (self.ax & 0x00FF) | (value as u16) << 8,
+            0b101 => self.cx = (self.cx & 0x00FF) | (value as u16) << 8,
+            0b110 => self.dx = (self.dx & 0x00FF) | (value as u16) << 8,
+            _ => self.bx = (self.bx & 0x00FF) | (value as u16) << 8,
         }
     }
 
     //Se usa para saber el registro destino al cual vamos a escribir por ejemplo MOV [BX+0x10h], esta función nos sirve para saber BX
+    //La suma es de 16 bits y da la vuelta como en el 8086 ([BX+SI] con BX=0xFFFF y SI=2 es el offset 1)
     pub fn get_base_address_from_code(&self,code: u8)->u16{
-        match code {
-            0b000 => {
-                println!("Obteniendo dirección base usando BX+SI: {}", self.bx + self.si);
-                self.bx + self.si
-            },
-            0b001 => {
-                println!("Obteniendo dirección base usando BX+DI: {}", self.bx + self.di);
-                self.bx + self.di
-            },
-            0b010 => {
-                println!("Obteniendo dirección base usando BP+SI: {}", self.bp + self.si);
-                self.bp + self.si
-            },
-            0b011 => {
-                println!("Obteniendo dirección base usando BP+DI: {}", self.bp + self.di);
-                self.bp + self.di
-            },
-            0b100 => {
-                println!("Obteniendo dirección base usando SI: {}", self.si);
-                self.si
-            },
-            0b101 => {
-                println!("Obteniendo dirección base usando DI: {}", self.di);
-                self.di
-            },
-            0b110 => {
-                println!("Obteniendo dirección base usando BP: {}", self.bp);
-                self.bp
-            },
-            0b111 => {
-                println!("Obteniendo dirección base usando BX: {}", self.bx);
-                self.bx
-            },
-            _ => panic!("Código de base no válido: {}", code),
+        match code & 0x07 {
+            0b000 => self.bx.wrapping_add(self.si),
+            0b001 => self.bx.wrapping_add(self.di),
+            0b010 => self.bp.wrapping_add(self.si),
+            0b011 => self.bp.wrapping_add(self.di),
+            0b100 => self.si,
+            0b101 => self.di,
+            0b110 => self.bp,
+            _ => self.bx,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_address_da_la_vuelta() {
+        let mut registers = Registers::initialize();
+        registers.bx = 0xFFFF;
+        registers.bp = 0xFFF0;
+        registers.si = 0x0002;
+        registers.di = 0x0020;
+        assert_eq!(registers.get_base_address_from_code(0b000), 0x0001);
+        assert_eq!(registers.get_base_address_from_code(0b001), 0x001F);
+        assert_eq!(registers.get_base_address_from_code(0b010), 0xFFF2);
+        assert_eq!(registers.get_base_address_from_code(0b011), 0x0010);
+    }
+
+    #[test]
+    fn test_indices_solo_usan_3_bits() {
+        let mut registers = Registers::initialize();
+        registers.write_register_by_index(0b1000_0011, 0x1234);
+        assert_eq!(registers.bx, 0x1234);
+        assert_eq!(registers.get_register_by_index(0xFF), registers.di);
+        registers.write_register_by_index_byte(0x0C, 0x56);
+        assert_eq!(registers.get_register_by_index_byte(0x04), 0x56);
+        assert_eq!(registers.get_base_address_from_code(0x0F), 0x1234);
+    }
+}
@@ -0,0 +1,190 @@
+use std::ops::{Deref, DerefMut};
+use crate::emulator::assembler::assemble;
+use crate::emulator::auxiliar::*;
+use crate::emulator::emulator::{Emulator8086, StopReason};
+
+//Ayudas para los tests: ensamblan un trozo de código, lo ejecutan como un .com en un emulador nuevo
+//y permiten comprobar el resultado encadenando asserts:
+//    run_asm("mov ax,5\nadd ax,3", |cpu| { cpu.reg("ax", 8).flags_clear(FLAG_CF | FLAG_ZF); });
+
+//Instrucciones que se pueden ejecutar como mucho, para que un bucle infinito no cuelgue el test
+const MAX_INSTRUCTIONS: u64 = 1_000_000;
+
+//Emulador después de ejecutar el código, con los asserts. Los fallos muestran el código fuente
+pub(crate) struct Cpu {
+    pub emulator: Emulator8086,
+    source: String,
+}
+
+//Ensambla y ejecuta el código y le pasa el resultado a check
+pub(crate) fn run_asm<F: FnOnce(&mut Cpu)>(source: &str, check: F) {
+    run_asm_with(source, |_| {}, check);
+}
+
+//Igual que run_asm, pero setup puede preparar el emulador (memoria, flags...) después de cargar el programa
+pub(crate) fn run_asm_with<S, F>(source: &str, setup: S, check: F)
+where
+    S: FnOnce(&mut Emulator8086),
+    F: FnOnce(&mut Cpu),
+{
+    let mut cpu = asm(source, setup);
+    check(&mut cpu);
+}
+
+//Ensambla el código con org 100h y un INT 20h al final, lo carga como un .com y lo ejecuta hasta que termina
+pub(crate) fn asm<S: FnOnce(&mut Emulator8086)>(source: &str, setup: S) -> Cpu {
+    let program = match assemble(&format!("org 100h\n{}\nint 20h", source)) {
+        Ok(program) => program,
+        //La línea 1 es el org añadido
+        Err(e) => panic!("Error al ensamblar, línea {}: {}\n{}", e.line - 1, e.message, source),
+    };
+    let mut emulator = Emulator8086::new();
+    emulator.load_com_bytes(&program).unwrap();
+    setup(&mut emulator);
+    match emulator.run(MAX_INSTRUCTIONS) {
+        StopReason::Exited(_) | StopReason::Halted => {},
+        motivo => panic!("El programa no ha terminado: {:?}\n{}", motivo, source),
+    }
+    Cpu { emulator, source: source.to_string() }
+}
+
+impl Deref for Cpu {
+    type Target = Emulator8086;
+
+    fn deref(&self) -> &Emulator8086 {
+        &self.emulator
+    }
+}
+
+impl DerefMut for Cpu {
+    fn deref_mut(&mut self) -> &mut Emulator8086 {
+        &mut self.emulator
+    }
+}
+
+impl Cpu {
+    fn check(&self, name: &str, value: u16, expected: u16) {
+        assert_eq!(value, expected, "{} = 0x{:04X}, se esperaba 0x{:04X}\n{}", name, value, expected, self.source);
+    }
+
+    //Valor de un registro por su nombre: al...bh, ax...di, es...ds, ip y flags
+    pub fn register(&self, name: &str) -> u16 {
+        let registers = &self.emulator.registers;
+        let name = name.to_lowercase();
+        let bytes = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+        if let Some(index) = bytes.iter().position(|register| *register == name) {
+            return registers.get_register_by_index_byte(index as u8) as u16;
+        }
+        match name.as_str() {
+            "ax" => registers.ax, "cx" => registers.cx, "dx" => registers.dx, "bx" => registers.bx,
+            "sp" => registers.sp, "bp" => registers.bp, "si" => registers.si, "di" => registers.di,
+            "es" => registers.es, "cs" => registers.cs, "ss" => registers.ss, "ds" => registers.ds,
+            "ip" => registers.ip, "flags" => registers.flags,
+            _ => panic!("Registro desconocido: {}", name),
+        }
+    }
+
+    pub fn reg(&mut self, name: &str, expected: u16) -> &mut Self {
+        self.check(name, self.register(name), expected);
+        self
+    }
+
+    //Comprueba que todos los flags de mask están activos
+    pub fn flags_set(&mut self, mask: u16) -> &mut Self {
+        self.check("flags & mask", self.emulator.registers.flags & mask, mask);
+        self
+    }
+
+    //Comprueba que ninguno de los flags de mask está activo
+    pub fn flags_clear(&mut self, mask: u16) -> &mut Self {
+        self.check("flags & mask", self.emulator.registers.flags & mask, 0);
+        self
+    }
+
+    pub fn cf(&mut self, set: bool) -> &mut Self {
+        self.flag(FLAG_CF, set)
+    }
+
+    pub fn zf(&mut self, set: bool) -> &mut Self {
+        self.flag(FLAG_ZF, set)
+    }
+
+    pub fn sf(&mut self, set: bool) -> &mut Self {
+        self.flag(FLAG_SF, set)
+    }
+
+    pub fn of(&mut self, set: bool) -> &mut Self {
+        self.flag(FLAG_OF, set)
+    }
+
+    pub fn pf(&mut self, set: bool) -> &mut Self {
+        self.flag(FLAG_PF, set)
+    }
+
+    pub fn af(&mut self, set: bool) -> &mut Self {
+        self.flag(FLAG_AF, set)
+    }
+
+    pub fn flag(&mut self, flag: u16, set: bool) -> &mut Self {
+        if set { self.flags_set(flag) } else { self.flags_clear(flag) }
+    }
+
+    //Byte y word de DS:offset
+    pub fn byte(&mut self, offset: u16, expected: u8) -> &mut Self {
+        let value = self.emulator.get_b_from_memory(self.emulator.registers.ds, offset);
+        self.check(&format!("byte [0x{:04X}]", offset), value as u16, expected as u16);
+        self
+    }
+
+    pub fn word(&mut self, offset: u16, expected: u16) -> &mut Self {
+        let value = self.emulator.get_w_from_memory(self.emulator.registers.ds, offset);
+        self.check(&format!("word [0x{:04X}]", offset), value, expected);
+        self
+    }
+
+    //Bytes seguidos a partir de DS:offset
+    pub fn bytes(&mut self, offset: u16, expected: &[u8]) -> &mut Self {
+        for (i, byte) in expected.iter().enumerate() {
+            self.byte(offset.wrapping_add(i as u16), *byte);
+        }
+        self
+    }
+
+    pub fn exit_code(&mut self, expected: u8) -> &mut Self {
+        self.check("código de salida", self.emulator.exit_code as u16, expected as u16);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_asm_registros_y_flags() {
+        run_asm("mov ax,5\nadd ax,3", |cpu| {
+            cpu.reg("ax", 8).reg("al", 8).reg("ah", 0).cf(false).zf(false).flags_clear(FLAG_SF | FLAG_OF);
+        });
+        run_asm("mov al,0FFh\ninc al", |cpu| {
+            cpu.reg("al", 0).zf(true).af(true).pf(true);
+        });
+    }
+
+    #[test]
+    fn test_run_asm_memoria_y_setup() {
+        run_asm_with("mov bx,200h\nmov al,[bx]\nadd [bx+1],al\nmov word [204h],1234h", |emulator| {
+            emulator.set_b_in_memory(emulator.registers.ds, 0x0200, 0x10);
+            emulator.set_b_in_memory(emulator.registers.ds, 0x0201, 0x20);
+        }, |cpu| {
+            cpu.bytes(0x0200, &[0x10, 0x30]).word(0x0204, 0x1234).exit_code(0);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "ax = 0x0008, se esperaba 0x0009")]
+    fn test_run_asm_falla_con_el_valor() {
+        run_asm("mov ax,5\nadd ax,3", |cpu| {
+            cpu.reg("ax", 9);
+        });
+    }
+}
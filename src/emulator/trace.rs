@@ -0,0 +1,94 @@
+use std::fmt;
+
+//Nivel de detalle de las trazas, de menos a más
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TraceLevel {
+    //Cada instrucción que se ejecuta
+    Instruction,
+    //Accesos a memoria
+    Memory,
+    //Accesos a registros y cálculo de direcciones base
+    Register,
+}
+
+//Evento de traza del emulador
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceEvent {
+    //Comienzo de una instrucción en CS:IP con su primer byte
+    Instruction { cs: u16, ip: u16, opcode: u8 },
+    //Lectura o escritura de memoria en una dirección física, word indica si es de 16 bits
+    MemoryRead { address: usize, value: u16, word: bool },
+    MemoryWrite { address: usize, value: u16, word: bool },
+    //Lectura o escritura de un registro de propósito general (AX..DI o AL..BH)
+    RegisterRead { register: &'static str, value: u16 },
+    RegisterWrite { register: &'static str, value: u16 },
+    //Dirección base del ModRM (BX+SI, BP, ...) antes de sumar el desplazamiento
+    BaseAddress { base: &'static str, value: u16 },
+}
+
+impl TraceEvent {
+    pub fn level(&self) -> TraceLevel {
+        match self {
+            TraceEvent::Instruction { .. } => TraceLevel::Instruction,
+            TraceEvent::MemoryRead { .. } | TraceEvent::MemoryWrite { .. } => TraceLevel::Memory,
+            TraceEvent::RegisterRead { .. } | TraceEvent::RegisterWrite { .. } | TraceEvent::BaseAddress { .. } => TraceLevel::Register,
+        }
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceEvent::Instruction { cs, ip, opcode } => write!(f, "{:04X}:{:04X} opcode 0x{:02X}", cs, ip, opcode),
+            TraceEvent::MemoryRead { address, value, word: true } => write!(f, "Leyendo de 0x{:05X} el word 0x{:04X}", address, value),
+            TraceEvent::MemoryRead { address, value, word: false } => write!(f, "Leyendo de 0x{:05X} el byte 0x{:02X}", address, value),
+            TraceEvent::MemoryWrite { address, value, word: true } => write!(f, "Escribiendo en 0x{:05X} el word 0x{:04X}", address, value),
+            TraceEvent::MemoryWrite { address, value, word: false } => write!(f, "Escribiendo en 0x{:05X} el byte 0x{:02X}", address, value),
+            TraceEvent::RegisterRead { register, value } => write!(f, "Accediendo al registro {}: 0x{:04X}", register, value),
+            TraceEvent::RegisterWrite { register, value } => write!(f, "Escribiendo en el registro {} el valor: 0x{:04X}", register, value),
+            TraceEvent::BaseAddress { base, value } => write!(f, "Obteniendo dirección base usando {}: 0x{:04X}", base, value),
+        }
+    }
+}
+
+//Destino de las trazas. Cualquier closure FnMut(&TraceEvent) sirve como destino
+pub trait TraceSink {
+    fn event(&mut self, event: &TraceEvent);
+}
+
+impl<F: FnMut(&TraceEvent)> TraceSink for F {
+    fn event(&mut self, event: &TraceEvent) {
+        self(event)
+    }
+}
+
+//Escribe las trazas por la salida estándar, como hacía antes el emulador
+pub struct StdoutSink;
+
+impl TraceSink for StdoutSink {
+    fn event(&mut self, event: &TraceEvent) {
+        println!("{}", event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_niveles() {
+        assert!(TraceLevel::Instruction < TraceLevel::Memory);
+        assert!(TraceLevel::Memory < TraceLevel::Register);
+        let event = TraceEvent::BaseAddress { base: "BX+SI", value: 0x0102 };
+        assert_eq!(event.level(), TraceLevel::Register);
+        assert_eq!(event.to_string(), "Obteniendo dirección base usando BX+SI: 0x0102");
+    }
+
+    #[test]
+    fn test_closure_como_destino() {
+        let mut eventos = Vec::new();
+        let mut sink = |event: &TraceEvent| eventos.push(*event);
+        sink.event(&TraceEvent::Instruction { cs: 0x0700, ip: 0x0100, opcode: 0x90 });
+        assert_eq!(eventos.len(), 1);
+    }
+}
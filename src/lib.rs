@@ -1,4 +1,4 @@
-mod emulator;
+pub mod emulator;
 use crate::emulator::emulator::Emulator8086;
 use std::ffi::CString;
 use std::os::raw::c_char;
@@ -10,31 +10,52 @@ pub extern "C" fn create_emulator() -> *mut Emulator8086{
     Box::into_raw(Box::new(emulator))
 }
 
+/// Libera un emulador creado con `create_emulator`.
+///
+/// # Safety
+///
+/// `ptr` tiene que ser nulo o un puntero devuelto por `create_emulator` que todavía no se haya liberado.
+/// Después de la llamada no se puede volver a usar.
 #[no_mangle]
-pub extern "C" fn destroy_emulator(ptr: *mut Emulator8086) {
+pub unsafe extern "C" fn destroy_emulator(ptr: *mut Emulator8086) {
     if !ptr.is_null() {
         unsafe {
-            Box::from_raw(ptr);
+            drop(Box::from_raw(ptr));
         }
     }
 }
 
+/// Devuelve el estado de los registros como cadena de C, que hay que liberar con `liberar_cadena`.
+///
+/// # Safety
+///
+/// `ptr` tiene que ser nulo o un puntero válido devuelto por `create_emulator`, sin liberar y sin que
+/// otro hilo lo esté modificando durante la llamada.
 #[no_mangle]
-pub extern "C" fn obtener_estado_registros_ffi(ptr: *mut Emulator8086) -> *mut c_char {
-    let emulator = unsafe {
-        assert!(!ptr.is_null());
-        &*ptr
-    };
+pub unsafe extern "C" fn obtener_estado_registros_ffi(ptr: *mut Emulator8086) -> *mut c_char {
+    //Un panic no puede cruzar la frontera FFI, los errores se devuelven como puntero nulo
+    if ptr.is_null() {
+        return std::ptr::null_mut();
+    }
+    let emulator = unsafe { &*ptr };
     let estado = obtener_estado_registros(emulator);
-    let c_str = CString::new(estado).unwrap();
-    c_str.into_raw()
+    match CString::new(estado) {
+        Ok(c_str) => c_str.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
 }
 
+/// Libera una cadena devuelta por `obtener_estado_registros_ffi`.
+///
+/// # Safety
+///
+/// `ptr` tiene que ser nulo o una cadena devuelta por `obtener_estado_registros_ffi` que todavía no se
+/// haya liberado.
 #[no_mangle]
-pub extern "C" fn liberar_cadena(ptr: *mut c_char) {
+pub unsafe extern "C" fn liberar_cadena(ptr: *mut c_char) {
     unsafe {
         if !ptr.is_null() {
-            CString::from_raw(ptr);
+            drop(CString::from_raw(ptr));
         }
     }
 }
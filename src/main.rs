@@ -1,26 +1,107 @@
-mod emulator;
-use crate::emulator::emulator::Emulator8086;
+use emu8086::emulator::emulator::{Emulator8086, StopReason};
+use emu8086::emulator::disassembler::{listing, Syntax};
+use emu8086::emulator::assembler::assemble;
 use std::env;
+
+//Instrucciones que se ejecutan como mucho si no se indica --limit
+const DEFAULT_LIMIT: u64 = 100_000_000;
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let file_path:String;
-    if args.len() < 2 {
+    //emu8086 disasm programa.com [--intel] muestra el listado del programa en lugar de ejecutarlo
+    if args.get(1).map(String::as_str) == Some("disasm") {
+        let Some(file_path) = args.get(2) else {
+            fail("Uso: emu8086 disasm programa.com [--intel]");
+        };
+        let syntax = if args.iter().any(|arg| arg == "--intel") { Syntax::Intel } else { Syntax::Fasm };
+        disasm(file_path, syntax);
+        return;
+    }
+    //emu8086 asm programa.asm [-o programa.com] ensambla el programa, por defecto junto al fuente
+    if args.get(1).map(String::as_str) == Some("asm") {
+        const USAGE: &str = "Uso: emu8086 asm programa.asm [-o programa.com]";
+        let Some(file_path) = args.get(2) else {
+            fail(USAGE);
+        };
+        let output = match args.iter().position(|arg| arg == "-o") {
+            Some(position) => match args.get(position + 1) {
+                Some(output) => output.clone(),
+                None => fail(USAGE),
+            },
+            None => std::path::Path::new(file_path).with_extension("com").to_string_lossy().to_string(),
+        };
+        asm(file_path, &output);
+        return;
+    }
+    let file_path:String = if args.len() < 2 {
         println!("Por favor, proporciona la dirección del archivo como argumento.");
-        file_path = "noname.com".to_string();
+        "noname.com".to_string()
     }else{
-        file_path = args[1].to_string();
-    }
+        args[1].to_string()
+    };
+    //--limit N cambia el número máximo de instrucciones, para que un bucle infinito no cuelgue la ejecución
+    let limit = match args.iter().position(|arg| arg == "--limit") {
+        Some(position) => match args.get(position + 1).and_then(|limit| limit.parse().ok()) {
+            Some(limit) => limit,
+            None => fail("Uso: emu8086 programa.com [--limit instrucciones]"),
+        },
+        None => DEFAULT_LIMIT,
+    };
+    run(&file_path, limit);
+}
+
+fn run(file_path: &str, limit: u64) {
     println!("Cargando el programa: {}", file_path);
     let mut emulator = Emulator8086::new();
-    if let Err(e) = emulator.load_com(&file_path) {
-        println!("Error al cargar el programa: {:?}", e);
-        return;
+    //Los .exe llevan cabecera MZ, el resto se carga como .com
+    let result = if file_path.to_lowercase().ends_with(".exe") {
+        emulator.load_exe(file_path)
+    }else{
+        emulator.load_com(file_path)
+    };
+    if let Err(e) = result {
+        fail(&e.to_string());
+    }
+    let reason = emulator.run(limit);
+    emulator.imprimir_estado_registros();
+    match reason {
+        //El código de salida del programa es el de la ejecución
+        StopReason::Exited(code) => {
+            println!("Programa terminado con código {}", code);
+            std::process::exit(code as i32);
+        },
+        StopReason::Halted => println!("Procesador parado (HLT)"),
+        StopReason::Breakpoint => println!("Punto de ruptura en {:04X}:{:04X}", emulator.registers.cs, emulator.registers.ip),
+        StopReason::BudgetExhausted => fail(&format!("El programa no ha terminado tras {} instrucciones", limit)),
+        StopReason::Error(e) => fail(&e.to_string()),
+    }
+}
+
+//Los .com se cargan en el offset 0x100, detrás del PSP
+fn disasm(file_path: &str, syntax: Syntax) {
+    match std::fs::read(file_path) {
+        Ok(program) => print!("{}", listing(&program, 0x0100, syntax)),
+        Err(e) => fail(&format!("Error al leer {}: {}", file_path, e)),
     }
-    let mut instruction = emulator.fetch(); //Primera instruccion
-    while instruction != 0xc3 {
-        emulator.decode_and_execute(instruction);
-        emulator.imprimir_estado_registros();
-        instruction = emulator.fetch();
+}
+
+//Muestra el error por la salida de errores y termina con código 1, para que los scripts sepan que ha fallado
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn asm(file_path: &str, output: &str) {
+    let source = match std::fs::read_to_string(file_path) {
+        Ok(source) => source,
+        Err(e) => fail(&format!("Error al leer {}: {}", file_path, e)),
+    };
+    match assemble(&source) {
+        Ok(program) => match std::fs::write(output, &program) {
+            Ok(()) => println!("{}: {} bytes", output, program.len()),
+            Err(e) => fail(&format!("Error al escribir {}: {}", output, e)),
+        },
+        Err(e) => fail(&format!("{}: {}", file_path, e)),
     }
 }
 //http://atc2.aut.uah.es/~avicente/asignaturas/ects/pdf/ects_t2.pdf
@@ -0,0 +1,54 @@
+#!/usr/bin/env python3
+# Genera daa_das.txt transcribiendo el pseudocódigo de DAA y DAS del Intel SDM (Vol. 2A),
+# escrito aparte del emulador para que la tabla sirva de referencia independiente.
+# Uso: python3 tests/bcd/daa_das.py > tests/bcd/daa_das.txt
+
+
+def daa(al, cf, af):
+    old_al, old_cf = al, cf
+    cf = 0
+    if (al & 0x0F) > 9 or af == 1:
+        carry = 1 if al + 6 > 0xFF else 0
+        al = (al + 6) & 0xFF
+        cf = old_cf | carry
+        af = 1
+    else:
+        af = 0
+    if old_al > 0x99 or old_cf == 1:
+        al = (al + 0x60) & 0xFF
+        cf = 1
+    else:
+        cf = 0
+    return al, cf, af
+
+
+def das(al, cf, af):
+    old_al, old_cf = al, cf
+    cf = 0
+    if (al & 0x0F) > 9 or af == 1:
+        borrow = 1 if al < 6 else 0
+        al = (al - 6) & 0xFF
+        cf = old_cf | borrow
+        af = 1
+    else:
+        af = 0
+    if old_al > 0x99 or old_cf == 1:
+        al = (al - 0x60) & 0xFF
+        cf = 1
+    return al, cf, af
+
+
+print("# Tabla de referencia de DAA (27) y DAS (2F) para todas las entradas de AL, CF y AF")
+print("# Generada con daa_das.py, que transcribe el pseudocódigo de DAA y DAS del Intel SDM (Vol. 2A)")
+print("# sin usar el código del emulador. En DAS el préstamo de AL-6 también pone CF a 1")
+print("# Formato: opcode AL_entrada CF AF -> AL_salida CF AF SF ZF PF (hexadecimal y bits)")
+print("# OF queda indefinida tras DAA/DAS y no se comprueba")
+for opcode, function in ((0x27, daa), (0x2F, das)):
+    for al in range(256):
+        for cf in (0, 1):
+            for af in (0, 1):
+                result, new_cf, new_af = function(al, cf, af)
+                sf = result >> 7
+                zf = 1 if result == 0 else 0
+                pf = 1 if bin(result).count("1") % 2 == 0 else 0
+                print(f"{opcode:02X} {al:02X} {cf} {af} -> {result:02X} {new_cf} {new_af} {sf} {zf} {pf}")
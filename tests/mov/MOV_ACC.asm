@@ -0,0 +1,11 @@
+org 100h
+
+MOV ax,1234h
+MOV [200h],ax
+MOV [202h],al
+MOV ax,0
+MOV al,[202h]
+MOV ah,al
+MOV cx,[200h]
+
+ret
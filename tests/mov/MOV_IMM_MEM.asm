@@ -0,0 +1,11 @@
+org 100h
+
+MOV bx,200h
+MOV byte [bx],55h
+MOV word [bx+2],1234h
+MOV word [bx+1000h],0ABCDh
+MOV word [300h],5678h
+; Forma C7 con registro, FASM codificaría MOV cx,0 como B9
+db 0C7h,0C1h,00h,00h
+
+ret
@@ -0,0 +1,10 @@
+org 100h
+
+MOV ax,0800h
+MOV es,ax
+MOV bx,es
+MOV [200h],cs
+MOV dx,[200h]
+MOV ss,ax
+
+ret
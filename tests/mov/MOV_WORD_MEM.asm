@@ -0,0 +1,12 @@
+org 100h
+
+MOV bx,200h
+MOV ax,0BEEFh
+MOV [bx],ax
+MOV [bx+2],ax
+MOV cx,[bx]
+MOV [bx+4],al
+MOV dx,0
+MOV dl,[bx+4]
+
+ret
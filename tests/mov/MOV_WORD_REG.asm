@@ -0,0 +1,12 @@
+org 100h
+
+MOV ax,1234h
+MOV bx,ax
+; Forma 8B con registro, FASM codificaría MOV cx,bx como 89 D9
+db 8Bh,0CBh
+MOV dx,cx
+; MOV si,dx con la forma 8B, FASM usaría 89 D6
+db 8Bh,0F2h
+MOV di,si
+
+ret